@@ -13,6 +13,12 @@ pub struct Config {
     pub w2: f64,
     /// AVB 的平均 Worst case delay
     pub w3: f64,
+    /// 排程被迫改動的舊 TT 資料流數量
+    #[serde(default)]
+    pub w4: f64,
+    /// 超額預留頻寬的連結數量
+    #[serde(default)]
+    pub w5: f64,
     /// 快速終止模式，看見第一組可行解即返回
     pub fast_stop: bool,
     /// 計算能見度時，TSN 對舊路徑的偏好程度
@@ -23,6 +29,51 @@ pub struct Config {
     pub t_limit: u128,
     /// 執行實驗的次數
     pub exp_times: usize,
+    /// 交換機是否支援訊框搶佔（802.3br / 802.1Qbu），TT 與 A 類 AVB 為快速訊框
+    #[serde(default)]
+    pub preemption: bool,
+    /// 是否在每個 TT 時段前加上保護帶，期間關閉非 TT 的閘門
+    #[serde(default)]
+    pub guard_band: bool,
+    /// 計算超週期時，是否也把 AVB 資料流的週期納入最小公倍數
+    #[serde(default)]
+    pub hyper_p_with_avb: bool,
+    /// TT 資料流的排程算法
+    #[serde(default)]
+    pub tt_scheduler: TTScheduler,
+    /// TT 資料流的排程順序
+    #[serde(default)]
+    pub tt_order: TTOrder,
+    /// 貪婪排程失敗時，換其它順序重試的時間上限，以微秒計，0 代表不重試
+    #[serde(default)]
+    pub tt_retry_budget: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TTScheduler {
+    /// 逐一把資料流排在最早可行的時間，快但可能錯過可行解
+    #[default]
+    Greedy,
+    /// 回溯搜索，能找到貪婪算法錯過的解；先試左對齊的時間點，再試每個整數時間點，
+    /// 在搜索上限內試完仍失敗即代表沒有可行解，但大的實例可能在達到上限後放棄
+    Backtrack,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TTOrder {
+    /// 死線（max_delay）短的優先，其次是週期短的，再其次是路徑長的
+    #[default]
+    DeadlineMonotonic,
+    /// 絕對死線（最早釋出時間加上 max_delay）早的優先
+    Edf,
+    /// 在路徑上佔用頻寬比例高的優先
+    Utilization,
+    /// 餘裕（max_delay 扣掉路徑上的傳輸時間）少的優先
+    MostConstrained,
+    /// 隨機順序
+    Random,
 }
 
 impl Config {
@@ -0,0 +1,37 @@
+mod qbv;
+pub use qbv::{export_qbv, QbvBaseTime, QbvCycleTime, QbvEntry, QbvPort, QbvSchedule};
+mod netconf;
+pub use netconf::export_netconf;
+mod omnet;
+pub use omnet::{export_omnet, OmnetExport};
+mod svg;
+pub use svg::render_gcl_svg;
+
+/// 跳脫 XML 文字與屬性值中的特殊字元
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!("ge-0/0/1", escape_xml("ge-0/0/1"));
+        assert_eq!(
+            "a&amp;b &lt;c&gt; &quot;d&quot; &apos;e&apos;",
+            escape_xml(r#"a&b <c> "d" 'e'"#)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use super::{escape_xml, export_qbv, QbvPort};
+use crate::graph_util::{Graph, StreamAwareGraph};
+use crate::recorder::GCL;
+use std::fmt::Write;
+
+const NETCONF_NS: &str = "urn:ietf:params:xml:ns:netconf:base:1.0";
+const INTERFACES_NS: &str = "urn:ietf:params:xml:ns:yang:ietf-interfaces";
+const IANA_IF_TYPE_NS: &str = "urn:ietf:params:xml:ns:yang:iana-if-type";
+const SCHED_NS: &str = "urn:ieee:std:802.1Q:yang:ieee802-dot1q-sched";
+
+/// 依 ieee802-dot1q-sched YANG 模型，為每台交換機產生一份 NETCONF `<config>` 文件
+///
+/// 每個往外的埠口為一個乙太網路 interface（ietf-interfaces 中必填的 `type` 為 `ethernetCsmacd`），
+/// 名稱經過 XML 跳脫，其中包含閘門控制清單（見 `export_qbv`）與各佇列的 max SDU；
+/// 經過該埠口的 TT 資料流所用的佇列，以註解的形式附在 interface 中，方便人工核對。
+/// * `base_time` - AdminBaseTime，單位為奈秒
+/// * 回傳 - (交換機的節點編號, XML 文件)，依節點編號排列
+pub fn export_netconf(graph: &StreamAwareGraph, gcl: &GCL, base_time: u64) -> Vec<(usize, String)> {
+    let ports = export_qbv(graph, gcl, base_time).ports;
+    let mut bridges = vec![];
+    graph.foreach_node(|id, is_switch| {
+        if is_switch {
+            bridges.push(id);
+        }
+    });
+    bridges
+        .into_iter()
+        .map(|bridge| {
+            let bridge_ports: Vec<&QbvPort> = ports.iter().filter(|p| p.node == bridge).collect();
+            (bridge, gen_bridge_config(&bridge_ports, gcl))
+        })
+        .collect()
+}
+
+fn gen_bridge_config(ports: &[&QbvPort], gcl: &GCL) -> String {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(xml, r#"<config xmlns="{}">"#, NETCONF_NS).unwrap();
+    writeln!(xml, r#"  <interfaces xmlns="{}">"#, INTERFACES_NS).unwrap();
+    for port in ports.iter() {
+        write_interface(&mut xml, port, gcl);
+    }
+    writeln!(xml, "  </interfaces>").unwrap();
+    writeln!(xml, "</config>").unwrap();
+    xml
+}
+
+fn write_interface(xml: &mut String, port: &QbvPort, gcl: &GCL) {
+    let config = gcl.get_port_config(port.link_id);
+    writeln!(xml, "    <interface>").unwrap();
+    writeln!(xml, "      <name>{}</name>", escape_xml(&port.name)).unwrap();
+    writeln!(
+        xml,
+        r#"      <type xmlns:ianaift="{}">ianaift:ethernetCsmacd</type>"#,
+        IANA_IF_TYPE_NS
+    )
+    .unwrap();
+    // 經過這個埠口的 TT 資料流及其佇列
+    let mut assignments: Vec<(usize, u8)> = gcl
+        .get_raw_gate_events(port.link_id)
+        .into_iter()
+        .map(|(_, _, queue_id, flow_id)| (flow_id.0, queue_id))
+        .collect();
+    assignments.sort();
+    assignments.dedup();
+    for (flow_id, queue_id) in assignments.into_iter() {
+        writeln!(
+            xml,
+            "      <!-- TT flow {} -> queue {} -->",
+            flow_id, queue_id
+        )
+        .unwrap();
+    }
+    writeln!(
+        xml,
+        r#"      <gate-parameters xmlns="{}" xmlns:sched="{}">"#,
+        SCHED_NS, SCHED_NS
+    )
+    .unwrap();
+    for queue_id in 0..config.queue_cnt {
+        writeln!(xml, "        <queue-max-sdu-table>").unwrap();
+        writeln!(xml, "          <traffic-class>{}</traffic-class>", queue_id).unwrap();
+        writeln!(
+            xml,
+            "          <queue-max-sdu>{}</queue-max-sdu>",
+            config.mtu
+        )
+        .unwrap();
+        writeln!(xml, "        </queue-max-sdu-table>").unwrap();
+    }
+    writeln!(
+        xml,
+        "        <gate-enabled>{}</gate-enabled>",
+        port.gate_enabled
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "        <admin-gate-states>{}</admin-gate-states>",
+        port.admin_gate_states
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "        <admin-control-list-length>{}</admin-control-list-length>",
+        port.admin_control_list_length
+    )
+    .unwrap();
+    for entry in port.admin_control_list.iter() {
+        writeln!(xml, "        <admin-control-list>").unwrap();
+        writeln!(xml, "          <index>{}</index>", entry.index).unwrap();
+        writeln!(
+            xml,
+            "          <operation-name>sched:{}</operation-name>",
+            entry.operation_name
+        )
+        .unwrap();
+        writeln!(xml, "          <sgs-params>").unwrap();
+        writeln!(
+            xml,
+            "            <gate-states-value>{}</gate-states-value>",
+            entry.gate_states_value
+        )
+        .unwrap();
+        writeln!(
+            xml,
+            "            <time-interval-value>{}</time-interval-value>",
+            entry.time_interval_value
+        )
+        .unwrap();
+        writeln!(xml, "          </sgs-params>").unwrap();
+        writeln!(xml, "        </admin-control-list>").unwrap();
+    }
+    writeln!(xml, "        <admin-cycle-time>").unwrap();
+    writeln!(
+        xml,
+        "          <numerator>{}</numerator>",
+        port.admin_cycle_time.numerator
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "          <denominator>{}</denominator>",
+        port.admin_cycle_time.denominator
+    )
+    .unwrap();
+    writeln!(xml, "        </admin-cycle-time>").unwrap();
+    writeln!(
+        xml,
+        "        <admin-cycle-time-extension>{}</admin-cycle-time-extension>",
+        port.admin_cycle_time_extension
+    )
+    .unwrap();
+    writeln!(xml, "        <admin-base-time>").unwrap();
+    writeln!(
+        xml,
+        "          <seconds>{}</seconds>",
+        port.admin_base_time.seconds
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "          <fractional-seconds>{}</fractional-seconds>",
+        port.admin_base_time.nanoseconds
+    )
+    .unwrap();
+    writeln!(xml, "        </admin-base-time>").unwrap();
+    writeln!(xml, "        <config-change>true</config-change>").unwrap();
+    writeln!(xml, "      </gate-parameters>").unwrap();
+    writeln!(xml, "    </interface>").unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_export_netconf() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(2));
+        g.add_switch(Some(1));
+        g.add_edge((0, 2), 100.0).unwrap();
+        g.add_edge((2, 1), 100.0).unwrap();
+        g.set_port_name((2, 1), "ge-0/0/1").unwrap();
+        let mut gcl = GCL::with_port_configs(100, g.get_port_configs());
+        gcl.insert_gate_evt(0, 0.into(), 5, 0, 10);
+        gcl.insert_gate_evt(2, 0.into(), 5, 10, 10);
+        let docs = export_netconf(&g, &gcl, 0);
+        // 只有交換機有設定檔，其中只有它往外的兩個埠口
+        assert_eq!(1, docs.len());
+        let (bridge, xml) = &docs[0];
+        assert_eq!(2, *bridge);
+        assert_eq!(2, xml.matches("<interface>").count());
+        assert!(xml.contains("<name>ge-0/0/1</name>"));
+        assert!(xml.contains("<name>eth0</name>"));
+        assert_eq!(2, xml.matches("ianaift:ethernetCsmacd</type>").count());
+        assert!(xml.contains("<!-- TT flow 0 -> queue 5 -->"));
+        assert!(xml.contains("<operation-name>sched:set-gate-states</operation-name>"));
+        assert!(xml.contains("<gate-states-value>32</gate-states-value>"));
+        assert_eq!(
+            xml.matches("<interface>").count(),
+            xml.matches("</interface>").count()
+        );
+        // 埠口名稱中的特殊字元要跳脫
+        g.set_port_name((2, 1), "a&b<1>").unwrap();
+        let docs = export_netconf(&g, &gcl, 0);
+        assert!(docs[0].1.contains("<name>a&amp;b&lt;1&gt;</name>"));
+    }
+}
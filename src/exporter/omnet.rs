@@ -0,0 +1,581 @@
+use super::{export_qbv, QbvPort};
+use crate::flow::{data::AVBClass, AVBFlow, FlowID, TSNFlow};
+use crate::graph_util::{Graph, StreamAwareGraph, DEFAULT_MTU};
+use crate::network_wrapper::get_frame_sizes;
+use crate::recorder::GCL;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+/// 每個資料流的 UDP 埠號為此值加上其編號
+const BASE_PORT: usize = 1000;
+/// 模擬幾個超週期
+const SIM_HYPER_PERIODS: u32 = 10;
+
+/// OMNeT++/INET 的模擬設定：一份 NED 網路與一份 omnetpp.ini
+#[derive(Clone, Debug)]
+pub struct OmnetExport {
+    pub ned: String,
+    pub ini: String,
+}
+
+/// 模擬中的網路：各節點的模組名稱、各埠口的介面編號與 Qbv 設定
+struct Network<'a> {
+    name: &'a str,
+    /// 節點 -> (模組名稱, 是否為交換機)
+    modules: BTreeMap<usize, (String, bool)>,
+    /// (節點, 對端節點) -> 介面編號
+    interfaces: HashMap<(usize, usize), usize>,
+    ports: Vec<QbvPort>,
+}
+
+/// 一條資料流在模擬中的設定
+struct Stream<'a> {
+    name: String,
+    flow_id: FlowID,
+    src: usize,
+    dst: usize,
+    period: u32,
+    /// 每個訊框的 (封包長度, 週期內的產生時間)
+    frames: Vec<(usize, u32)>,
+    route: &'a Vec<usize>,
+}
+
+/// 把拓樸、路徑與 GCL 轉成 INET 的 TSN 模擬（`TsnDevice` 與 `TsnSwitch`）
+///
+/// * 每個資料流是一個 UDP 來源，以 VLAN 標籤（編號加一）辨識，交換機依 VLAN 與目的地靜態轉發，
+///   故同一對端點的資料流可以走不同的路徑。
+/// * 大於路徑上最小 MTU 的資料流切成數個封包，每個訊框一個 UDP 來源；
+///   TT 訊框在 GCL 中第一個連結的閘門開啟時產生，AVB 訊框則都在週期開始時產生。
+/// * 每個埠口以 `StreamClassifier` 把資料流分到 GCL 中所用的佇列，閘門依 `export_qbv` 的控制清單開關；
+///   AVB 佇列以 Credit Based Shaper 整形，`idleSlope` 為經過該埠口的同類 AVB 資料流（含訊框開銷）的頻寬總和。
+/// * 封包長度即酬載大小，不扣除 UDP/IP 標頭。
+/// * `network` - NED 中網路的名稱
+pub fn export_omnet(
+    graph: &StreamAwareGraph,
+    gcl: &GCL,
+    tsns: &[(&TSNFlow, &Vec<usize>)],
+    avbs: &[(&AVBFlow, &Vec<usize>)],
+    network: &str,
+) -> OmnetExport {
+    let mut nodes = BTreeMap::<usize, bool>::new();
+    graph.foreach_node(|id, is_switch| {
+        nodes.insert(id, is_switch);
+    });
+    // 不同的節點名稱可能換成同一個模組名稱（如 `switch-1` 與 `switch_1`），依節點編號加上後綴區分
+    let mut modules = BTreeMap::<usize, (String, bool)>::new();
+    let mut used = HashSet::<String>::new();
+    for (id, is_switch) in nodes.into_iter() {
+        let base = get_module_name(&graph.get_node_name(id));
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while !used.insert(name.clone()) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        modules.insert(id, (name, is_switch));
+    }
+    // 每條雙向連結在 NED 中是一個連線，依連線的順序決定埠口的介面編號
+    let mut connections = Vec::<(usize, usize, f64)>::new();
+    let mut interfaces = HashMap::<(usize, usize), usize>::new();
+    let mut iface_cnt = HashMap::<usize, usize>::new();
+    for (_, (a, b), bandwidth) in graph.get_links().into_iter() {
+        if interfaces.contains_key(&(a, b)) {
+            continue;
+        }
+        for &(node, peer) in [(a, b), (b, a)].iter() {
+            let cnt = iface_cnt.entry(node).or_insert(0);
+            interfaces.insert((node, peer), *cnt);
+            *cnt += 1;
+        }
+        connections.push((a, b, bandwidth));
+    }
+    let mut streams = Vec::<Stream>::new();
+    for &(flow, route) in tsns.iter() {
+        let payloads = get_payloads(flow.size, get_route_mtu(graph, gcl, route));
+        let release = gcl.get_release(flow.id).unwrap_or(flow.spec_data.offset);
+        // NOTE 第一個連結上，前幾個閘門事件就是第一個實例的各個訊框
+        let first_link = graph.get_links_id_bandwidth(route)[0].0;
+        let events = gcl.get_flow_gate_events(first_link, flow.id);
+        let scheduled = events.len() >= payloads.len();
+        let frames = payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| {
+                if scheduled {
+                    (payload, events[i].0 % flow.period)
+                } else {
+                    (payload, release)
+                }
+            })
+            .collect();
+        streams.push(Stream {
+            name: format!("tt{}", flow.id.0),
+            flow_id: flow.id,
+            src: flow.src,
+            dst: flow.dst,
+            period: flow.period,
+            frames,
+            route,
+        });
+    }
+    for &(flow, route) in avbs.iter() {
+        let payloads = get_payloads(flow.size, get_route_mtu(graph, gcl, route));
+        streams.push(Stream {
+            name: format!("avb{}", flow.id.0),
+            flow_id: flow.id,
+            src: flow.src,
+            dst: flow.dst,
+            period: flow.period,
+            frames: payloads.into_iter().map(|payload| (payload, 0)).collect(),
+            route,
+        });
+    }
+    let net = Network {
+        name: network,
+        modules,
+        interfaces,
+        ports: export_qbv(graph, gcl, 0).ports,
+    };
+    let ned = gen_ned(&net, &connections);
+    let ini = gen_ini(&net, graph, gcl, &streams, tsns, avbs);
+    OmnetExport { ned, ini }
+}
+
+/// 路徑上最小的 MTU，資料流一路上都不能切割
+fn get_route_mtu(graph: &StreamAwareGraph, gcl: &GCL, route: &[usize]) -> usize {
+    graph
+        .get_links_id_bandwidth(route)
+        .iter()
+        .map(|&(link_id, _)| gcl.get_port_config(link_id).mtu)
+        .min()
+        .unwrap_or(DEFAULT_MTU)
+}
+
+/// 把大小為 size 的資料流切成酬載不超過 `mtu` 的訊框，回傳每個訊框的酬載
+fn get_payloads(size: usize, mtu: usize) -> Vec<usize> {
+    let mut payloads = vec![mtu; size / mtu];
+    if !size.is_multiple_of(mtu) {
+        payloads.push(size % mtu);
+    }
+    payloads
+}
+
+fn gen_ned(net: &Network, connections: &[(usize, usize, f64)]) -> String {
+    let mut ned = String::new();
+    writeln!(ned, "import inet.networks.base.TsnNetworkBase;").unwrap();
+    writeln!(ned, "import inet.node.ethernet.EthernetLink;").unwrap();
+    writeln!(ned, "import inet.node.tsn.TsnDevice;").unwrap();
+    writeln!(ned, "import inet.node.tsn.TsnSwitch;").unwrap();
+    writeln!(ned).unwrap();
+    writeln!(ned, "network {} extends TsnNetworkBase", net.name).unwrap();
+    writeln!(ned, "{{").unwrap();
+    writeln!(ned, "    submodules:").unwrap();
+    for (name, is_switch) in net.modules.values() {
+        let module = if *is_switch { "TsnSwitch" } else { "TsnDevice" };
+        writeln!(ned, "        {}: {};", name, module).unwrap();
+    }
+    writeln!(ned, "    connections:").unwrap();
+    for &(a, b, bandwidth) in connections.iter() {
+        // NOTE 頻寬的單位是位元組 / 微秒，即 8 Mbps
+        writeln!(
+            ned,
+            "        {}.ethg++ <--> EthernetLink {{ datarate = {}Mbps; }} <--> {}.ethg++;",
+            net.modules[&a].0,
+            bandwidth * 8.0,
+            net.modules[&b].0
+        )
+        .unwrap();
+    }
+    writeln!(ned, "}}").unwrap();
+    ned
+}
+
+fn gen_ini(
+    net: &Network,
+    graph: &StreamAwareGraph,
+    gcl: &GCL,
+    streams: &[Stream],
+    tsns: &[(&TSNFlow, &Vec<usize>)],
+    avbs: &[(&AVBFlow, &Vec<usize>)],
+) -> String {
+    let names = &net.modules;
+    let interfaces = &net.interfaces;
+    let ports = &net.ports;
+    let mut ini = String::new();
+    writeln!(ini, "[General]").unwrap();
+    writeln!(ini, "network = {}", net.name).unwrap();
+    writeln!(
+        ini,
+        "sim-time-limit = {}us",
+        gcl.get_hyper_p() * SIM_HYPER_PERIODS
+    )
+    .unwrap();
+    writeln!(ini, "*.*.hasEgressTrafficShaping = true").unwrap();
+    writeln!(ini, "*.*.hasOutgoingStreams = true").unwrap();
+    writeln!(ini, "*.*.hasIncomingStreams = true").unwrap();
+
+    writeln!(ini, "\n# 資料流的來源與終點").unwrap();
+    let mut apps = BTreeMap::<usize, Vec<String>>::new();
+    for stream in streams.iter() {
+        let port = BASE_PORT + stream.flow_id.0;
+        let src_apps = apps.entry(stream.src).or_insert(vec![]);
+        for (i, &(length, offset)) in stream.frames.iter().enumerate() {
+            let display_name = if stream.frames.len() == 1 {
+                stream.name.clone()
+            } else {
+                format!("{}-{}", stream.name, i)
+            };
+            src_apps.push(format!(
+                "typename = \"UdpSourceApp\"\n\
+                 display-name = \"{}\"\n\
+                 io.destAddress = \"{}\"\n\
+                 io.destPort = {}\n\
+                 source.packetLength = {}B\n\
+                 source.productionInterval = {}us\n\
+                 source.initialProductionOffset = {}us",
+                display_name, names[&stream.dst].0, port, length, stream.period, offset
+            ));
+        }
+        let dst_apps = apps.entry(stream.dst).or_insert(vec![]);
+        dst_apps.push(format!(
+            "typename = \"UdpSinkApp\"\nio.localPort = {}",
+            port
+        ));
+    }
+    for (node, node_apps) in apps.iter() {
+        let name = &names[node].0;
+        writeln!(ini, "*.{}.numApps = {}", name, node_apps.len()).unwrap();
+        for (i, app) in node_apps.iter().enumerate() {
+            for line in app.lines() {
+                writeln!(ini, "*.{}.app[{}].{}", name, i, line).unwrap();
+            }
+        }
+    }
+
+    writeln!(ini, "\n# 以 VLAN 標籤辨識資料流").unwrap();
+    for (node, (name, is_switch)) in names.iter() {
+        let mut identifiers = vec![];
+        let mut encoders = vec![];
+        let mut decoders = vec![];
+        for stream in streams.iter() {
+            let vlan = stream.flow_id.0 + 1;
+            if stream.src == *node {
+                identifiers.push(format!(
+                    "{{stream: \"{}\", packetFilter: expr(udp.destPort == {})}}",
+                    stream.name,
+                    BASE_PORT + stream.flow_id.0
+                ));
+                encoders.push(format!("{{stream: \"{}\", vlan: {}}}", stream.name, vlan));
+            } else if *is_switch && stream.route.contains(node) {
+                decoders.push(format!("{{vlan: {}, stream: \"{}\"}}", vlan, stream.name));
+            }
+        }
+        if !identifiers.is_empty() {
+            writeln!(
+                ini,
+                "*.{}.bridging.streamIdentifier.identifier.mapping = [{}]",
+                name,
+                identifiers.join(", ")
+            )
+            .unwrap();
+            writeln!(
+                ini,
+                "*.{}.bridging.streamCoder.encoder.mapping = [{}]",
+                name,
+                encoders.join(", ")
+            )
+            .unwrap();
+        }
+        if !decoders.is_empty() {
+            writeln!(
+                ini,
+                "*.{}.bridging.streamCoder.decoder.mapping = [{}]",
+                name,
+                decoders.join(", ")
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(ini, "\n# 依路徑靜態轉發").unwrap();
+    let mut forwarding = BTreeMap::<usize, Vec<String>>::new();
+    for stream in streams.iter() {
+        let route = stream.route;
+        for i in 1..route.len() - 1 {
+            let iface = interfaces[&(route[i], route[i + 1])];
+            forwarding.entry(route[i]).or_insert(vec![]).push(format!(
+                "{{address: \"{}\", vlan: {}, interface: \"eth{}\"}}",
+                names[&stream.dst].0,
+                stream.flow_id.0 + 1,
+                iface
+            ));
+        }
+    }
+    for (node, entries) in forwarding.iter() {
+        writeln!(
+            ini,
+            "*.{}.macTable.forwardingTable = [{}]",
+            names[node].0,
+            entries.join(", ")
+        )
+        .unwrap();
+    }
+
+    writeln!(ini, "\n# 每個埠口的佇列與閘門").unwrap();
+    let mut classes = HashMap::<usize, Vec<String>>::new();
+    for &(flow, _) in tsns.iter() {
+        for port in ports.iter() {
+            let events = gcl.get_raw_gate_events(port.link_id);
+            if let Some(&(.., queue_id, _)) = events.iter().find(|evt| evt.3 == flow.id) {
+                classes
+                    .entry(port.link_id)
+                    .or_insert(vec![])
+                    .push(format!("\"tt{}\": {}", flow.id.0, queue_id));
+            }
+        }
+    }
+    // NOTE (連結, 佇列) -> 該佇列的 AVB 資料流在線路上佔用的頻寬總和（位元組 / 微秒）
+    let mut idle_slopes = BTreeMap::<(usize, u8), f64>::new();
+    for &(flow, route) in avbs.iter() {
+        let wire_size: usize = get_frame_sizes(flow.size, get_route_mtu(graph, gcl, route))
+            .iter()
+            .sum();
+        for port in ports.iter() {
+            let on_route =
+                (0..route.len() - 1).any(|i| route[i] == port.node && route[i + 1] == port.peer);
+            if on_route {
+                let config = gcl.get_port_config(port.link_id);
+                let queue_id = match flow.spec_data.avb_class {
+                    AVBClass::A => config.avb_a_queue,
+                    AVBClass::B => config.avb_b_queue,
+                };
+                classes
+                    .entry(port.link_id)
+                    .or_insert(vec![])
+                    .push(format!("\"avb{}\": {}", flow.id.0, queue_id));
+                *idle_slopes.entry((port.link_id, queue_id)).or_insert(0.0) +=
+                    wire_size as f64 / flow.period as f64;
+            }
+        }
+    }
+    for port in ports.iter() {
+        let config = gcl.get_port_config(port.link_id);
+        writeln!(ini, "# {}/{}", port.node_name, port.name).unwrap();
+        let prefix = format!(
+            "*.{}.eth[{}].macLayer.queue",
+            names[&port.node].0,
+            interfaces[&(port.node, port.peer)]
+        );
+        writeln!(ini, "{}.numTrafficClasses = {}", prefix, config.queue_cnt).unwrap();
+        writeln!(ini, "{}.classifier.typename = \"StreamClassifier\"", prefix).unwrap();
+        writeln!(
+            ini,
+            "{}.classifier.mapping = {{{}}}",
+            prefix,
+            classes
+                .get(&port.link_id)
+                .map(|c| c.join(", "))
+                .unwrap_or_default()
+        )
+        .unwrap();
+        writeln!(
+            ini,
+            "{}.classifier.defaultGateIndex = {}",
+            prefix, config.be_queue
+        )
+        .unwrap();
+        for (&(_, queue_id), &load) in
+            idle_slopes.range((port.link_id, 0)..=(port.link_id, u8::MAX))
+        {
+            let shaper = format!("{}.transmissionSelectionAlgorithm[{}]", prefix, queue_id);
+            writeln!(ini, "{}.typename = \"Ieee8021qCreditBasedShaper\"", shaper).unwrap();
+            // NOTE 頻寬的單位是位元組 / 微秒，即 8 Mbps
+            writeln!(ini, "{}.idleSlope = {}Mbps", shaper, load * 8.0).unwrap();
+        }
+        if !port.gate_enabled {
+            continue;
+        }
+        for queue_id in 0..config.queue_cnt {
+            let (initially_open, durations) = get_gate_durations(port, queue_id);
+            let durations: Vec<String> = durations.iter().map(|d| format!("{}us", d)).collect();
+            let gate = format!("{}.transmissionGate[{}]", prefix, queue_id);
+            writeln!(ini, "{}.initiallyOpen = {}", gate, initially_open).unwrap();
+            // NOTE 閘門控制清單從 `phase` 開始，模擬開始時已經走到清單中的 hyper_p - phase
+            let offset = (gcl.get_hyper_p() - port.phase) % gcl.get_hyper_p();
+            writeln!(ini, "{}.offset = {}us", gate, offset).unwrap();
+            writeln!(ini, "{}.durations = [{}]", gate, durations.join(", ")).unwrap();
+        }
+    }
+    ini
+}
+
+/// NED 中的模組名稱只能由英數字及底線組成，且不能以數字開頭，其它字元一律換成底線
+fn get_module_name(node_name: &str) -> String {
+    let name: String = node_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// 某個佇列的閘門一開始是否開啟，以及之後每次維持同一狀態多久（微秒），開與關交替出現
+///
+/// 整個週期都維持同一狀態者，不需要任何區段
+fn get_gate_durations(port: &QbvPort, queue_id: u8) -> (bool, Vec<u32>) {
+    let mut segments = Vec::<(bool, u32)>::new();
+    for entry in port.admin_control_list.iter() {
+        let open = entry.gate_states_value & (1 << queue_id) != 0;
+        let duration = entry.time_interval_value / 1000;
+        match segments.last_mut() {
+            Some(last) if last.0 == open => last.1 += duration,
+            _ => segments.push((open, duration)),
+        }
+    }
+    let initially_open = segments.first().is_none_or(|s| s.0);
+    if segments.len() <= 1 {
+        (initially_open, vec![])
+    } else {
+        (initially_open, segments.into_iter().map(|s| s.1).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flow::data::{AVBData, TSNData};
+    #[test]
+    fn test_export_omnet() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(2));
+        g.add_switch(Some(1));
+        g.add_edge((0, 2), 100.0).unwrap();
+        g.add_edge((2, 1), 100.0).unwrap();
+        let flow = TSNFlow {
+            id: 0.into(),
+            size: 100,
+            src: 0,
+            dst: 1,
+            period: 100,
+            max_delay: 100,
+            spec_data: TSNData {
+                offset: 0,
+                max_jitter: None,
+                latest_offset: None,
+            },
+        };
+        let route = vec![0, 2, 1];
+        let mut gcl = GCL::with_port_configs(100, g.get_port_configs());
+        gcl.set_release(5, flow.id);
+        gcl.insert_gate_evt(0, flow.id, 3, 5, 10);
+        gcl.insert_gate_evt(2, flow.id, 3, 20, 10);
+        let export = export_omnet(&g, &gcl, &vec![(&flow, &route)], &vec![], "Net");
+
+        assert!(export.ned.contains("network Net extends TsnNetworkBase"));
+        assert!(export.ned.contains("switch_2: TsnSwitch;"));
+        assert!(export.ned.contains(
+            "host_0.ethg++ <--> EthernetLink { datarate = 800Mbps; } <--> switch_2.ethg++;"
+        ));
+
+        let ini = &export.ini;
+        assert!(ini.contains("*.host_0.app[0].source.initialProductionOffset = 5us"));
+        assert!(ini.contains("*.host_1.app[0].io.localPort = 1000"));
+        assert!(ini.contains(
+            "*.switch_2.macTable.forwardingTable = [{address: \"host_1\", vlan: 1, interface: \"eth1\"}]"
+        ));
+        assert!(ini.contains(
+            "*.switch_2.bridging.streamCoder.decoder.mapping = [{vlan: 1, stream: \"tt0\"}]"
+        ));
+        assert!(ini.contains("# switch-2/eth1\n*.switch_2.eth[1].macLayer.queue.numTrafficClasses"));
+        assert!(ini.contains("*.switch_2.eth[1].macLayer.queue.classifier.mapping = {\"tt0\": 3}"));
+        // 3 號佇列只在 20~30 之間開啟，BE 佇列則相反
+        let gate = "*.switch_2.eth[1].macLayer.queue.transmissionGate";
+        assert!(ini.contains(&format!("{}[3].initiallyOpen = false", gate)));
+        // 清單從 30 開始，故模擬開始時已經走到 70
+        assert!(ini.contains(&format!("{}[3].durations = [90us, 10us]", gate)));
+        assert!(ini.contains(&format!("{}[3].offset = 70us", gate)));
+        assert!(ini.contains(&format!("{}[0].initiallyOpen = true", gate)));
+        // 沒有 TT 的埠口不需要閘門
+        assert!(!ini.contains("*.switch_2.eth[0].macLayer.queue.transmissionGate"));
+    }
+    #[test]
+    fn test_export_frames_and_cbs() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(2));
+        g.add_switch(Some(1));
+        g.add_edge((0, 2), 100.0).unwrap();
+        g.add_edge((2, 1), 100.0).unwrap();
+        // 3000 位元組超過預設的 MTU，要切成兩個訊框
+        let tsn = TSNFlow {
+            id: 0.into(),
+            size: 3000,
+            src: 0,
+            dst: 1,
+            period: 100,
+            max_delay: 100,
+            spec_data: TSNData {
+                offset: 0,
+                max_jitter: None,
+                latest_offset: None,
+            },
+        };
+        let avb = AVBFlow {
+            id: 1.into(),
+            size: 158,
+            src: 0,
+            dst: 1,
+            period: 100,
+            max_delay: 1000,
+            spec_data: AVBData {
+                avb_class: AVBClass::A,
+            },
+        };
+        let route = vec![0, 2, 1];
+        let mut gcl = GCL::with_port_configs(100, g.get_port_configs());
+        gcl.set_release(5, tsn.id);
+        gcl.insert_gate_evt(0, tsn.id, 3, 30, 16);
+        gcl.insert_gate_evt(0, tsn.id, 3, 5, 16);
+        let export = export_omnet(&g, &gcl, &[(&tsn, &route)], &[(&avb, &route)], "Net");
+
+        let ini = &export.ini;
+        assert!(ini.contains("*.host_0.numApps = 3"));
+        assert!(ini.contains("*.host_0.app[0].display-name = \"tt0-0\""));
+        assert!(ini.contains("*.host_0.app[0].source.packetLength = 1500B"));
+        assert!(ini.contains("*.host_0.app[0].source.initialProductionOffset = 5us"));
+        assert!(ini.contains("*.host_0.app[1].source.packetLength = 1500B"));
+        assert!(ini.contains("*.host_0.app[1].source.initialProductionOffset = 30us"));
+        assert!(ini.contains("*.host_0.app[2].display-name = \"avb1\""));
+        // 158 位元組加上 42 位元組的訊框開銷，每 100 微秒一次，即每微秒 2 位元組
+        let shaper = "*.switch_2.eth[1].macLayer.queue.transmissionSelectionAlgorithm[2]";
+        assert!(ini.contains(&format!(
+            "{}.typename = \"Ieee8021qCreditBasedShaper\"",
+            shaper
+        )));
+        assert!(ini.contains(&format!("{}.idleSlope = 16Mbps", shaper)));
+        assert!(ini.contains(
+            "*.host_0.eth[0].macLayer.queue.transmissionSelectionAlgorithm[2].idleSlope = 16Mbps"
+        ));
+        // 不在路徑上的埠口不需要整形
+        assert!(!ini.contains("*.switch_2.eth[0].macLayer.queue.transmissionSelectionAlgorithm"));
+    }
+    #[test]
+    fn test_module_name_collision() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(1));
+        g.add_switch(Some(2));
+        g.add_edge((0, 1), 100.0).unwrap();
+        g.add_edge((1, 2), 100.0).unwrap();
+        g.set_node_name(1, "sw-1").unwrap();
+        g.set_node_name(2, "sw_1").unwrap();
+        let export = export_omnet(&g, &GCL::new(100, 4), &[], &[], "Net");
+
+        assert!(export.ned.contains("sw_1: TsnSwitch;"));
+        assert!(export.ned.contains("sw_1_2: TsnSwitch;"));
+        assert!(export
+            .ned
+            .contains("sw_1.ethg++ <--> EthernetLink { datarate = 800Mbps; } <--> sw_1_2.ethg++;"));
+    }
+}
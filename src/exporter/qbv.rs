@@ -0,0 +1,164 @@
+use crate::graph_util::StreamAwareGraph;
+use crate::recorder::GCL;
+use serde::Serialize;
+
+/// 程式內部的時間單位是微秒，802.1Qbv 則以奈秒計
+const NS_PER_US: u64 = 1000;
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// 所有埠口的 802.1Qbv 設定，可以直接輸出成 JSON
+#[derive(Clone, Debug, Serialize)]
+pub struct QbvSchedule {
+    pub ports: Vec<QbvPort>,
+}
+
+/// 單一埠口（有向邊）的閘門控制設定
+#[derive(Clone, Debug, Serialize)]
+pub struct QbvPort {
+    pub link_id: usize,
+    /// 這個埠口位於哪個節點
+    pub node: usize,
+    /// 節點的名稱（見 `StreamAwareGraph::get_node_name`）
+    pub node_name: String,
+    /// 這個埠口連往哪個節點
+    pub peer: usize,
+    /// 埠口在節點上的名稱（見 `StreamAwareGraph::get_port_name`）
+    pub name: String,
+    /// 這個埠口上是否有 TT 資料流；沒有的話整個週期都維持 `admin_gate_states`，交換機可以不啟用排程
+    pub gate_enabled: bool,
+    /// 排程開始前的閘門狀態
+    pub admin_gate_states: u8,
+    /// 閘門控制清單從超週期中的哪個時間點開始（微秒），已經算進 `admin_base_time`
+    pub phase: u32,
+    pub admin_base_time: QbvBaseTime,
+    pub admin_cycle_time: QbvCycleTime,
+    pub admin_cycle_time_extension: u32,
+    pub admin_control_list_length: usize,
+    pub admin_control_list: Vec<QbvEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QbvBaseTime {
+    pub seconds: u64,
+    pub nanoseconds: u32,
+}
+
+/// 週期長度，以秒為單位的有理數
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QbvCycleTime {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+/// AdminControlList 中的一筆 SetGateStates 操作
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QbvEntry {
+    pub index: usize,
+    pub operation_name: &'static str,
+    /// 第 i 個位元代表第 i 個佇列的閘門是否開啟
+    pub gate_states_value: u8,
+    /// 維持這個閘門狀態多久，單位為奈秒
+    pub time_interval_value: u32,
+}
+
+/// 把 GCL 轉成每個埠口的 802.1Qbv 閘門控制清單，一個週期即為超週期
+///
+/// TT 資料流的閘門事件期間只開啟其佇列，保護帶期間關閉所有閘門；其餘時間則開啟 AVB 與 BE 的佇列，保留給 TT 專用的佇列保持關閉。
+/// 清單經過壓縮（見 `GCL::get_compact_gate_list`），若其起點不是超週期的開頭，則把 AdminBaseTime 往後平移。
+/// * `base_time` - AdminBaseTime，單位為奈秒
+pub fn export_qbv(graph: &StreamAwareGraph, gcl: &GCL, base_time: u64) -> QbvSchedule {
+    let cycle_time = gcl.get_hyper_p() as u64 * NS_PER_US;
+    let ports = graph
+        .get_links()
+        .into_iter()
+        .map(|(link_id, (node, peer), _)| {
+            let compact_list = gcl.get_compact_gate_list(link_id);
+            let phase = compact_list[0].0;
+            let base_time = base_time + phase as u64 * NS_PER_US;
+            let admin_control_list: Vec<QbvEntry> = compact_list
+                .into_iter()
+                .enumerate()
+                .map(|(index, (_, duration, gate_states_value))| QbvEntry {
+                    index,
+                    operation_name: "set-gate-states",
+                    gate_states_value,
+                    time_interval_value: (duration as u64 * NS_PER_US) as u32,
+                })
+                .collect();
+            QbvPort {
+                link_id,
+                node,
+                node_name: graph.get_node_name(node),
+                peer,
+                name: graph.get_port_name(link_id),
+                gate_enabled: !gcl.get_gate_events(link_id).is_empty(),
+                admin_gate_states: gcl.get_port_config(link_id).get_non_tt_gates(),
+                phase,
+                admin_base_time: QbvBaseTime {
+                    seconds: base_time / NS_PER_SEC,
+                    nanoseconds: (base_time % NS_PER_SEC) as u32,
+                },
+                admin_cycle_time: QbvCycleTime {
+                    numerator: cycle_time,
+                    denominator: NS_PER_SEC,
+                },
+                admin_cycle_time_extension: 0,
+                admin_control_list_length: admin_control_list.len(),
+                admin_control_list,
+            }
+        })
+        .collect();
+    QbvSchedule { ports }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph_util::{Graph, PortConfig};
+    #[test]
+    fn test_export_qbv() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(2));
+        g.add_edge((0, 1), 100.0).unwrap();
+        g.set_port_config((0, 1), PortConfig::with_queue_cnt(5).unwrap())
+            .unwrap();
+        let mut gcl = GCL::with_port_configs(100, g.get_port_configs());
+        gcl.insert_gate_evt(0, 0.into(), 3, 10, 5);
+        gcl.insert_gate_evt(0, 1.into(), 4, 15, 5);
+        gcl.insert_gate_evt(0, 2.into(), 4, 20, 5);
+        let schedule = export_qbv(&g, &gcl, 1_500_000_000);
+        assert_eq!(2, schedule.ports.len());
+
+        let port = &schedule.ports[0];
+        assert_eq!((0, 0, 1), (port.link_id, port.node, port.peer));
+        assert_eq!("host-0", port.node_name);
+        assert_eq!("eth0", port.name);
+        assert!(port.gate_enabled);
+        assert_eq!(0b00111, port.admin_gate_states);
+        // 頭尾的空檔合併後，清單從 25 開始
+        assert_eq!(25, port.phase);
+        assert_eq!(
+            QbvBaseTime {
+                seconds: 1,
+                nanoseconds: 500_025_000
+            },
+            port.admin_base_time
+        );
+        assert_eq!(100_000, port.admin_cycle_time.numerator);
+        let entries: Vec<(u8, u32)> = port
+            .admin_control_list
+            .iter()
+            .map(|entry| (entry.gate_states_value, entry.time_interval_value))
+            .collect();
+        // 首尾相接的 TT 閘門合併為一筆
+        assert_eq!(vec![(0b00111, 85_000), (0b11000, 15_000)], entries);
+        assert_eq!(2, port.admin_control_list_length);
+
+        // 沒有 TT 的埠口整個週期都維持同一個閘門狀態
+        let port = &schedule.ports[1];
+        assert!(!port.gate_enabled);
+        assert_eq!(0b111, port.admin_gate_states);
+        assert_eq!(1, port.admin_control_list.len());
+        assert_eq!(100_000, port.admin_control_list[0].time_interval_value);
+    }
+}
@@ -0,0 +1,178 @@
+use super::escape_xml;
+use crate::flow::FlowID;
+use crate::graph_util::StreamAwareGraph;
+use crate::recorder::GCL;
+use std::fmt::Write;
+
+/// 左側標籤的寬度
+const LABEL_WIDTH: f64 = 200.0;
+/// 時間軸的寬度
+const PLOT_WIDTH: f64 = 1200.0;
+const ROW_HEIGHT: f64 = 20.0;
+/// 連結那一列下方，AVB 被 TT 阻擋的區段的高度
+const BLOCK_HEIGHT: f64 = 4.0;
+const AXIS_HEIGHT: f64 = 30.0;
+/// 時間軸上的刻度數量
+const TICK_CNT: u32 = 10;
+
+/// 把 GCL 畫成一個超週期的甘特圖（SVG）
+///
+/// 每個有事件的連結佔一列，畫出其閘門事件（依資料流著色），下方的紅條為 AVB 被 TT 阻擋的區段；
+/// 接著該連結上每個被佔用過的佇列各佔一列，以淡色畫出佔用事件。每個區塊都帶有說明文字，滑鼠移上去即可看到。
+pub fn render_gcl_svg(graph: &StreamAwareGraph, gcl: &GCL) -> String {
+    let hyper_p = gcl.get_hyper_p();
+    let to_x = |time: u32| LABEL_WIDTH + time as f64 / hyper_p as f64 * PLOT_WIDTH;
+    let to_w = |duration: u32| duration as f64 / hyper_p as f64 * PLOT_WIDTH;
+    let mut body = String::new();
+    let mut y = AXIS_HEIGHT;
+    for (link_id, (_, peer), _) in graph.get_links().into_iter() {
+        let gate_evts = gcl.get_raw_gate_events(link_id);
+        if gate_evts.is_empty() {
+            continue;
+        }
+        write_label(
+            &mut body,
+            y,
+            &format!(
+                "{} → {}",
+                graph.get_link_name(link_id),
+                graph.get_node_name(peer)
+            ),
+        );
+        for &(start, duration, queue_id, flow_id) in gate_evts.iter() {
+            writeln!(
+                body,
+                r#"<rect class="gate" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}"><title>flow {} queue {} [{}, {})</title></rect>"#,
+                to_x(start),
+                y + 2.0,
+                to_w(duration),
+                ROW_HEIGHT - BLOCK_HEIGHT - 4.0,
+                get_flow_color(flow_id),
+                flow_id.0,
+                queue_id,
+                start,
+                start + duration
+            )
+            .unwrap();
+        }
+        // NOTE 與 AVB 延遲估計相同，以合併後的閘門事件作為 AVB 被阻擋的區段
+        for &(start, duration) in gcl.get_gate_events(link_id).iter() {
+            writeln!(
+                body,
+                r##"<rect class="avb-block" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="#d62728" fill-opacity="0.6"><title>AVB blocked [{}, {})</title></rect>"##,
+                to_x(start),
+                y + ROW_HEIGHT - BLOCK_HEIGHT - 1.0,
+                to_w(duration),
+                BLOCK_HEIGHT,
+                start,
+                start + duration
+            )
+            .unwrap();
+        }
+        y += ROW_HEIGHT;
+        for queue_id in 0..gcl.get_port_config(link_id).queue_cnt {
+            let queue_evts = gcl.get_raw_queue_events(link_id, queue_id);
+            if queue_evts.is_empty() {
+                continue;
+            }
+            write_label(&mut body, y, &format!("  queue {}", queue_id));
+            for &(start, duration, flow_id) in queue_evts.iter() {
+                writeln!(
+                    body,
+                    r#"<rect class="queue" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}" fill-opacity="0.35"><title>flow {} occupies queue {} [{}, {})</title></rect>"#,
+                    to_x(start),
+                    y + 2.0,
+                    to_w(duration),
+                    ROW_HEIGHT - 4.0,
+                    get_flow_color(flow_id),
+                    flow_id.0,
+                    queue_id,
+                    start,
+                    start + duration
+                )
+                .unwrap();
+            }
+            y += ROW_HEIGHT;
+        }
+    }
+    let height = y + ROW_HEIGHT;
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="11">"#,
+        LABEL_WIDTH + PLOT_WIDTH + 20.0,
+        height
+    )
+    .unwrap();
+    for i in 0..=TICK_CNT {
+        let time = hyper_p as u64 * i as u64 / TICK_CNT as u64;
+        let x = to_x(time as u32);
+        writeln!(
+            svg,
+            r##"<line x1="{:.2}" y1="{}" x2="{:.2}" y2="{}" stroke="#ccc"/><text x="{:.2}" y="{}" text-anchor="middle">{}</text>"##,
+            x,
+            AXIS_HEIGHT - 5.0,
+            x,
+            y,
+            x,
+            AXIS_HEIGHT - 10.0,
+            time
+        )
+        .unwrap();
+    }
+    svg.push_str(&body);
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn write_label(body: &mut String, y: f64, label: &str) {
+    writeln!(
+        body,
+        r#"<text x="4" y="{:.2}">{}</text>"#,
+        y + ROW_HEIGHT * 0.7,
+        escape_xml(label)
+    )
+    .unwrap();
+}
+
+/// 以黃金角分散色相，讓編號相近的資料流顏色也差得夠多
+fn get_flow_color(flow_id: FlowID) -> String {
+    let hue = (flow_id.0 as f64 * 137.508) % 360.0;
+    format!("hsl({:.0},65%,50%)", hue)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph_util::Graph;
+    #[test]
+    fn test_render_gcl_svg() {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(3));
+        g.add_edge((0, 1), 100.0).unwrap();
+        g.add_edge((1, 2), 100.0).unwrap();
+        let mut gcl = GCL::new(100, 4);
+        gcl.insert_gate_evt(0, 0.into(), 3, 0, 10);
+        gcl.insert_gate_evt(0, 1.into(), 4, 10, 10);
+        gcl.insert_gate_evt(2, 0.into(), 3, 20, 10);
+        gcl.insert_queue_evt(2, 0.into(), 3, 0, 20);
+        let svg = render_gcl_svg(&g, &gcl);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(3, svg.matches(r#"class="gate""#).count());
+        // 首尾相接的閘門事件合併為一個阻擋區段
+        assert_eq!(2, svg.matches(r#"class="avb-block""#).count());
+        assert_eq!(1, svg.matches(r#"class="queue""#).count());
+        // 沒有事件的連結不畫
+        assert!(svg.contains("host-0/eth0 → host-1"));
+        assert!(!svg.contains("host-1/eth0 "));
+        assert!(svg.contains("<title>flow 1 queue 4 [10, 20)</title>"));
+        assert_ne!(get_flow_color(0.into()), get_flow_color(1.into()));
+
+        // 標籤中的名稱要跳脫
+        g.set_node_name(1, "a<b&c").unwrap();
+        let svg = render_gcl_svg(&g, &gcl);
+        assert!(svg.contains("host-0/eth0 → a&lt;b&amp;c"));
+        assert!(!svg.contains("a<b"));
+    }
+}
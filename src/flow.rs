@@ -1,4 +1,6 @@
-#[derive(Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Debug, Serialize, Deserialize)]
 pub struct FlowID(pub(crate) usize);
 impl From<usize> for FlowID {
     fn from(i: usize) -> Self {
@@ -12,7 +14,8 @@ impl Into<usize> for FlowID {
 }
 
 pub mod data {
-    #[derive(Clone, Copy, Debug)]
+    use serde::{Deserialize, Serialize};
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
     pub enum AVBClass {
         A,
         B,
@@ -32,19 +35,43 @@ pub mod data {
                 false
             }
         }
+        /// 802.1Q 中該等級的量測區間（class measurement interval），以微秒計
+        pub fn get_interval(&self) -> u32 {
+            match self {
+                AVBClass::A => 125,
+                AVBClass::B => 250,
+            }
+        }
+        /// 802.1BA 中該等級的端對端延遲預算（七跳以內），以微秒計
+        pub fn get_latency_budget(&self) -> u32 {
+            match self {
+                AVBClass::A => 2000,
+                AVBClass::B => 50000,
+            }
+        }
     }
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct AVBData {
         pub avb_class: AVBClass,
     }
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct TSNData {
+        /// 最早的釋出時間
         pub offset: u32,
+        /// 端對端抖動的上限，None 代表不在乎抖動
+        pub max_jitter: Option<u32>,
+        /// 最晚的釋出時間，None 代表只能在 `offset` 釋出
+        pub latest_offset: Option<u32>,
+    }
+    impl TSNData {
+        pub fn get_latest_offset(&self) -> u32 {
+            self.latest_offset.unwrap_or(self.offset)
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Flow<T: Clone> {
     pub id: FlowID,
     pub size: usize,
@@ -3,11 +3,21 @@ use crate::flow::FlowID;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+/// 單一條邊上預留的頻寬，單位同頻寬（位元組 / 微秒）
+#[derive(Clone, Default)]
+struct Reservation {
+    avb: f64,
+    tt: f64,
+    /// 每個資料流預留了多少，以及是否為 AVB
+    flows: HashMap<FlowID, (f64, bool)>,
+}
+
 /// 每條邊上記憶了其承載的資料流識別碼。使用淺層複製，圖的節點、邊、頻寬、開關等資訊都將共用，僅有記憶被複製。
 #[derive(Clone)]
 pub struct MemorizingGraph {
     inner: Rc<StreamAwareGraph>,
     edge_info: HashMap<(usize, usize), HashSet<FlowID>>,
+    reservation: HashMap<(usize, usize), Reservation>,
 }
 
 impl std::ops::Deref for MemorizingGraph {
@@ -20,12 +30,15 @@ impl std::ops::Deref for MemorizingGraph {
 impl MemorizingGraph {
     pub fn new(graph: StreamAwareGraph) -> Self {
         let mut edge_info = HashMap::<(usize, usize), HashSet<FlowID>>::new();
+        let mut reservation = HashMap::<(usize, usize), Reservation>::new();
         for (key, _) in graph.edge_info.iter() {
-            edge_info.insert(key.clone(), HashSet::new());
+            edge_info.insert(*key, HashSet::new());
+            reservation.insert(*key, Reservation::default());
         }
         MemorizingGraph {
             inner: Rc::new(graph),
             edge_info,
+            reservation,
         }
     }
     /// 確定一條資料流的路徑時，將該資料流的ID記憶在它經過的邊上，移除路徑時則將ID遺忘。
@@ -44,11 +57,70 @@ impl MemorizingGraph {
             }
         }
     }
+    /// 在資料流經過的邊上預留（或釋放）頻寬。同一個資料流重複預留時，以最後一次為準；釋放沒有預留過的資料流則不做事。
+    /// * `reserve` - 布林值，預留或是釋放
+    /// * `flow_id` - 資料流ID
+    /// * `load` - 該資料流需要的頻寬，即 size / period
+    /// * `is_avb` - 是否為 AVB 資料流
+    /// * `route` - 該路徑(以節點組成)
+    pub fn update_reservation_on_route(
+        &mut self,
+        reserve: bool,
+        flow_id: FlowID,
+        load: f64,
+        is_avb: bool,
+        route: &[usize],
+    ) {
+        for i in 0..route.len() - 1 {
+            let r = self.reservation.get_mut(&(route[i], route[i + 1])).unwrap();
+            let old = if reserve {
+                r.flows.insert(flow_id, (load, is_avb))
+            } else {
+                r.flows.remove(&flow_id)
+            };
+            if let Some((old_load, old_is_avb)) = old {
+                if old_is_avb {
+                    r.avb -= old_load;
+                } else {
+                    r.tt -= old_load;
+                }
+            }
+            if reserve {
+                if is_avb {
+                    r.avb += load;
+                } else {
+                    r.tt += load;
+                }
+            }
+        }
+    }
+    /// 回傳一條邊上已預留的頻寬 (AVB, TT)
+    pub fn get_reservation(&self, edge: (usize, usize)) -> (f64, f64) {
+        let r = self.reservation.get(&edge).unwrap();
+        (r.avb, r.tt)
+    }
+    /// 找出所有超額預留的邊：AVB 超過 `max_avb_ratio` 倍的頻寬，或 TT 加 AVB 超過頻寬
+    pub fn get_overloaded_edges(&self, max_avb_ratio: f64) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = self
+            .reservation
+            .iter()
+            .filter(|(edge, r)| {
+                let bandwidth = self.inner.edge_info.get(edge).unwrap().1;
+                r.avb > max_avb_ratio * bandwidth || r.avb + r.tt > bandwidth
+            })
+            .map(|(edge, _)| *edge)
+            .collect();
+        edges.sort();
+        edges
+    }
     /// 把邊上記憶的資訊通通忘掉！
     pub fn forget_all_flows(&mut self) {
         for (_, set) in self.edge_info.iter_mut() {
             *set = HashSet::new();
         }
+        for (_, r) in self.reservation.iter_mut() {
+            *r = Reservation::default();
+        }
     }
     /// 詢問一條路徑上所有共用過邊的資料流。針對路上每個邊都會回傳一個陣列，內含走了這個邊的資料流（空陣列代表無人走過）
     ///
@@ -110,4 +182,38 @@ mod test {
 
         Ok(())
     }
+    #[test]
+    fn test_reservation() -> Result<(), String> {
+        let mut g = StreamAwareGraph::new();
+        g.add_host(Some(3));
+        g.add_edge((0, 1), 10.0)?;
+        g.add_edge((1, 2), 20.0)?;
+
+        let mut g = MemorizingGraph::new(g);
+        g.update_reservation_on_route(true, 0.into(), 5.0, true, &vec![0, 1, 2]);
+        assert_eq!((5.0, 0.0), g.get_reservation((0, 1)));
+        assert_eq!((0.0, 0.0), g.get_reservation((1, 0))); // 兩個方向不視為同個邊
+        assert_eq!(Vec::<(usize, usize)>::new(), g.get_overloaded_edges(0.75));
+
+        // 重複預留，以最後一次為準
+        g.update_reservation_on_route(true, 0.into(), 8.0, true, &vec![0, 1, 2]);
+        assert_eq!((8.0, 0.0), g.get_reservation((0, 1)));
+        assert_eq!(vec![(0, 1)], g.get_overloaded_edges(0.75));
+
+        // AVB 沒有超過 75%，但加上 TT 就超過頻寬了
+        g.update_reservation_on_route(true, 0.into(), 7.0, true, &vec![0, 1, 2]);
+        g.update_reservation_on_route(true, 1.into(), 4.0, false, &vec![2, 1, 0]);
+        g.update_reservation_on_route(true, 2.into(), 4.0, false, &vec![0, 1]);
+        assert_eq!((7.0, 4.0), g.get_reservation((0, 1)));
+        assert_eq!(vec![(0, 1)], g.get_overloaded_edges(0.75));
+
+        g.update_reservation_on_route(false, 2.into(), 0.0, false, &vec![0, 1]);
+        g.update_reservation_on_route(false, 99.into(), 0.0, false, &vec![0, 1]);
+        assert_eq!((7.0, 0.0), g.get_reservation((0, 1)));
+        assert_eq!(Vec::<(usize, usize)>::new(), g.get_overloaded_edges(0.75));
+
+        g.forget_all_flows();
+        assert_eq!((0.0, 0.0), g.get_reservation((1, 2)));
+        Ok(())
+    }
 }
@@ -24,3 +24,5 @@ mod stream_aware_graph;
 pub use stream_aware_graph::StreamAwareGraph;
 mod memorizing_graph;
 pub use memorizing_graph::MemorizingGraph;
+mod port_config;
+pub use port_config::{PortConfig, DEFAULT_MTU};
@@ -0,0 +1,129 @@
+use crate::MAX_QUEUE;
+
+/// 沒有特別設定的連結，其 MTU（單一訊框酬載的上限，單位為位元組）
+pub const DEFAULT_MTU: usize = 1500;
+
+/// 一個埠口（有向邊）的佇列設定，佇列編號越大，優先度越高
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortConfig {
+    /// 這個埠口共有幾個佇列
+    pub queue_cnt: u8,
+    /// 保留給 TT 資料流的佇列，排程時依序嘗試
+    pub tt_queues: Vec<u8>,
+    pub avb_a_queue: u8,
+    pub avb_b_queue: u8,
+    pub be_queue: u8,
+    /// 這個連結上單一訊框的酬載最多幾個位元組
+    pub mtu: usize,
+    /// 交換機在這個埠口上最多能存放幾筆閘門控制清單（壓縮後，見 `GCL::get_compact_gate_list`），`None` 代表沒有上限
+    pub max_gcl_len: Option<usize>,
+}
+impl Default for PortConfig {
+    /// 沒有特別設定的埠口：共 `MAX_QUEUE` 個佇列，對應方式同 `with_queue_cnt`
+    fn default() -> Self {
+        PortConfig::with_queue_cnt(MAX_QUEUE).unwrap()
+    }
+}
+impl PortConfig {
+    /// 依佇列數量產生預設的對應：BE 為 0、B 類為 1、A 類為 2，其餘保留給 TT
+    pub fn with_queue_cnt(queue_cnt: u8) -> Result<Self, String> {
+        if !(4..=MAX_QUEUE).contains(&queue_cnt) {
+            return Err(format!(
+                "佇列數量必需介於 4 到 {} 之間，卻為 {}",
+                MAX_QUEUE, queue_cnt
+            ));
+        }
+        Ok(PortConfig {
+            queue_cnt,
+            tt_queues: (3..queue_cnt).collect(),
+            avb_a_queue: 2,
+            avb_b_queue: 1,
+            be_queue: 0,
+            mtu: DEFAULT_MTU,
+            max_gcl_len: None,
+        })
+    }
+    /// 沒有 TT 時段時開啟的閘門：AVB、BE，以及沒有保留給 TT 的佇列，第 i 個位元代表第 i 個佇列
+    pub fn get_non_tt_gates(&self) -> u8 {
+        let mut gates = 0;
+        for queue_id in 0..self.queue_cnt {
+            let is_other = queue_id == self.avb_a_queue
+                || queue_id == self.avb_b_queue
+                || queue_id == self.be_queue;
+            if is_other || !self.tt_queues.contains(&queue_id) {
+                gates |= 1 << queue_id;
+            }
+        }
+        gates
+    }
+    /// 檢查所有佇列編號都在範圍內，且保留給 TT 的佇列不與其它流量共用
+    pub fn validate(&self) -> Result<(), String> {
+        if self.queue_cnt == 0 || self.queue_cnt > MAX_QUEUE {
+            return Err(format!("佇列數量不合法：{}", self.queue_cnt));
+        }
+        if self.mtu == 0 {
+            return Err("MTU 不能為 0".to_owned());
+        }
+        if self.max_gcl_len == Some(0) {
+            return Err("閘門控制清單至少要能存放一筆".to_owned());
+        }
+        if self.tt_queues.is_empty() {
+            return Err("至少要保留一個佇列給 TT".to_owned());
+        }
+        let others = [self.avb_a_queue, self.avb_b_queue, self.be_queue];
+        for &queue_id in self.tt_queues.iter().chain(others.iter()) {
+            if queue_id >= self.queue_cnt {
+                return Err(format!(
+                    "佇列 {} 超出範圍（共 {} 個佇列）",
+                    queue_id, self.queue_cnt
+                ));
+            }
+        }
+        for queue_id in self.tt_queues.iter() {
+            if others.contains(queue_id) {
+                return Err(format!(
+                    "保留給 TT 的佇列 {} 不能與 AVB 或 BE 共用",
+                    queue_id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_port_config() {
+        let config = PortConfig::with_queue_cnt(4).unwrap();
+        assert_eq!(vec![3], config.tt_queues);
+        assert_eq!(0b0111, config.get_non_tt_gates());
+        assert_eq!(0b111, PortConfig::default().get_non_tt_gates());
+        assert_eq!(
+            (3..MAX_QUEUE).collect::<Vec<_>>(),
+            PortConfig::default().tt_queues
+        );
+        assert!(config.validate().is_ok());
+        assert!(PortConfig::with_queue_cnt(2).is_err());
+        assert!(PortConfig::with_queue_cnt(MAX_QUEUE + 1).is_err());
+
+        let mut config = PortConfig::with_queue_cnt(8).unwrap();
+        config.tt_queues = vec![2, 7];
+        assert!(config.validate().is_err());
+        config.tt_queues = vec![6, 8];
+        assert!(config.validate().is_err());
+        config.tt_queues = vec![];
+        assert!(config.validate().is_err());
+        // TT 用上所有佇列，就會與 AVB 及 BE 共用
+        config.tt_queues = (0..8).collect();
+        assert!(config.validate().is_err());
+        assert!(PortConfig::default().validate().is_ok());
+        let mut config = PortConfig::default();
+        config.mtu = 0;
+        assert!(config.validate().is_err());
+        let mut config = PortConfig::default();
+        config.max_gcl_len = Some(0);
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,9 +1,11 @@
-use crate::graph_util::{Graph, OnOffGraph};
+use crate::graph_util::{Graph, OnOffGraph, PortConfig};
 use std::collections::HashMap;
 
 struct Node {
     is_switch: bool,
     edges: HashMap<usize, (f64, bool)>,
+    /// 從此節點往外的連結編號，依編號排列，刪除邊後仍保留
+    links: Vec<usize>,
     exist: bool,
     active: bool,
 }
@@ -18,6 +20,7 @@ impl Clone for Node {
             exist: self.exist,
             active: self.active,
             edges,
+            links: self.links.clone(),
         };
     }
 }
@@ -31,6 +34,14 @@ pub struct StreamAwareGraph {
     inactive_edges: Vec<(usize, usize)>,
     inactive_nodes: Vec<usize>,
     pub(super) edge_info: HashMap<(usize, usize), (usize, f64)>,
+    /// 以連結編號為索引的 (起點, 終點)
+    link_ends: Vec<(usize, usize)>,
+    /// 有特別設定佇列的埠口，以連結編號為鍵
+    port_configs: HashMap<usize, PortConfig>,
+    /// 有特別命名的節點，以節點編號為鍵
+    node_names: HashMap<usize, String>,
+    /// 有特別命名的埠口，以連結編號為鍵
+    port_names: HashMap<usize, String>,
 }
 impl StreamAwareGraph {
     fn _add_node(&mut self, cnt: Option<usize>, is_switch: bool) -> Vec<usize> {
@@ -50,6 +61,7 @@ impl StreamAwareGraph {
                 exist: true,
                 active: true,
                 edges: HashMap::new(),
+                links: vec![],
             };
             self.nodes.push(node);
             v.push(id);
@@ -64,6 +76,9 @@ impl StreamAwareGraph {
             .edges
             .insert(node_pair.1, (bandwidth, true));
         self.edge_info.insert(node_pair, (id, bandwidth));
+        self.nodes[node_pair.0].links.push(id);
+        debug_assert_eq!(self.link_ends.len(), id);
+        self.link_ends.push(node_pair);
     }
     fn _del_single_edge(&mut self, id_pair: (usize, usize)) -> Result<f64, String> {
         if let Some(e) = self.nodes[id_pair.0].edges.remove(&id_pair.1) {
@@ -98,9 +113,138 @@ impl StreamAwareGraph {
             inactive_edges: vec![],
             inactive_nodes: vec![],
             edge_info: HashMap::new(),
+            link_ends: vec![],
+            port_configs: HashMap::new(),
+            node_names: HashMap::new(),
+            port_names: HashMap::new(),
         }
     }
-    pub fn get_links_id_bandwidth(&self, route: &Vec<usize>) -> Vec<(usize, f64)> {
+    /// 替節點命名，名稱不可與其它節點重複
+    pub fn set_node_name(&mut self, id: usize, name: &str) -> Result<(), String> {
+        if !self._check_exist(id) {
+            return Err(format!("命名時發現節點不存在：{}", id));
+        }
+        let dup =
+            (0..self.nodes.len()).any(|other| other != id && self.get_node_name(other) == name);
+        if name.is_empty() || dup {
+            return Err(format!("節點名稱重複或為空：{:?}", name));
+        }
+        self.node_names.insert(id, name.to_owned());
+        Ok(())
+    }
+    /// 節點的名稱，未命名者為 `switch-{編號}` 或 `host-{編號}`
+    pub fn get_node_name(&self, id: usize) -> String {
+        match self.node_names.get(&id) {
+            Some(name) => name.clone(),
+            None if self.nodes[id].is_switch => format!("switch-{}", id),
+            None => format!("host-{}", id),
+        }
+    }
+    /// 替 `id_pair.0` 往 `id_pair.1` 的埠口命名，名稱不可與同一節點上的其它埠口重複
+    pub fn set_port_name(&mut self, id_pair: (usize, usize), name: &str) -> Result<(), String> {
+        let edge_id = match self.edge_info.get(&id_pair) {
+            Some(&(edge_id, _)) => edge_id,
+            None => return Err(format!("命名埠口時發現邊不存在：{:?}", id_pair)),
+        };
+        let dup = self
+            .get_node_links(id_pair.0)
+            .iter()
+            .any(|&other| other != edge_id && self.get_port_name(other) == name);
+        if name.is_empty() || dup {
+            return Err(format!(
+                "節點 {} 上的埠口名稱重複或為空：{:?}",
+                id_pair.0, name
+            ));
+        }
+        self.port_names.insert(edge_id, name.to_owned());
+        Ok(())
+    }
+    /// 連結在起點上的埠口名稱，未命名者為 `eth{i}`，i 為該埠口在節點上的順序（依連結編號）
+    pub fn get_port_name(&self, link_id: usize) -> String {
+        if let Some(name) = self.port_names.get(&link_id) {
+            return name.clone();
+        }
+        let (node, _) = self.get_link_ends(link_id);
+        let index = self
+            .get_node_links(node)
+            .iter()
+            .position(|&id| id == link_id)
+            .unwrap();
+        format!("eth{}", index)
+    }
+    /// 連結的完整名稱，即起點上的埠口，如 `switch-3/eth2`
+    pub fn get_link_name(&self, link_id: usize) -> String {
+        let (node, _) = self.get_link_ends(link_id);
+        format!(
+            "{}/{}",
+            self.get_node_name(node),
+            self.get_port_name(link_id)
+        )
+    }
+    /// 路徑上每個連結的完整名稱，與 `get_links_id_bandwidth` 的順序對應
+    pub fn get_route_link_names(&self, route: &[usize]) -> Vec<String> {
+        self.get_links_id_bandwidth(route)
+            .into_iter()
+            .map(|(link_id, _)| self.get_link_name(link_id))
+            .collect()
+    }
+    /// 連結的 (起點, 終點)
+    pub fn get_link_ends(&self, link_id: usize) -> (usize, usize) {
+        *self.link_ends.get(link_id).expect("找不到連結")
+    }
+    /// 從節點往外的所有連結，依連結編號排列
+    fn get_node_links(&self, id: usize) -> &[usize] {
+        &self.nodes[id].links
+    }
+    /// 設定 `id_pair.0` 往 `id_pair.1` 的埠口上的佇列
+    pub fn set_port_config(
+        &mut self,
+        id_pair: (usize, usize),
+        config: PortConfig,
+    ) -> Result<(), String> {
+        config.validate()?;
+        if let Some(&(edge_id, _)) = self.edge_info.get(&id_pair) {
+            self.port_configs.insert(edge_id, config);
+            Ok(())
+        } else {
+            Err(format!("設定埠口時發現邊不存在：{:?}", id_pair))
+        }
+    }
+    /// 設定某個節點上所有往外的埠口
+    pub fn set_node_port_config(&mut self, id: usize, config: PortConfig) -> Result<(), String> {
+        if !self._check_exist(id) {
+            return Err(format!("設定埠口時發現節點不存在：{}", id));
+        }
+        let mut pairs: Vec<(usize, usize)> = self
+            .edge_info
+            .keys()
+            .filter(|pair| pair.0 == id)
+            .cloned()
+            .collect();
+        pairs.sort();
+        for pair in pairs.into_iter() {
+            self.set_port_config(pair, config.clone())?;
+        }
+        Ok(())
+    }
+    /// 依連結編號排列的佇列設定，沒有特別設定的埠口採用預設值
+    pub fn get_port_configs(&self) -> Vec<PortConfig> {
+        (0..self.cur_edge_id)
+            .map(|edge_id| self.port_configs.get(&edge_id).cloned().unwrap_or_default())
+            .collect()
+    }
+    /// 所有存在的連結，依編號排列，其內容為 (連結編號, (起點, 終點), 頻寬)
+    pub fn get_links(&self) -> Vec<(usize, (usize, usize), f64)> {
+        let mut links: Vec<(usize, (usize, usize), f64)> = self
+            .edge_info
+            .iter()
+            .filter(|(&(a, b), _)| self.nodes[a].edges.contains_key(&b))
+            .map(|(&pair, &(edge_id, bandwidth))| (edge_id, pair, bandwidth))
+            .collect();
+        links.sort_by_key(|&(edge_id, ..)| edge_id);
+        links
+    }
+    pub fn get_links_id_bandwidth(&self, route: &[usize]) -> Vec<(usize, f64)> {
         let mut vec = vec![];
         for i in 0..route.len() - 1 {
             if let Some(tuple) = self.edge_info.get(&(route[i], route[i + 1])) {
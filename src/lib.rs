@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 
 pub mod config;
+/// 把排程結果轉成外部工具（交換機、模擬器等）看得懂的格式
+pub mod exporter;
 pub mod flow;
 pub mod graph_util;
 pub mod network_wrapper;
@@ -11,6 +13,8 @@ pub mod util;
 
 pub const MAX_QUEUE: u8 = 8;
 pub const MAX_K: usize = 20;
+/// 超週期的上限，資料流週期的最小公倍數超過此值時拒絕延長
+pub const MAX_HYPER_P: u32 = 1_000_000;
 
 use flow::{AVBFlow, TSNFlow};
 
@@ -27,6 +31,14 @@ fn read_flows_from_file_once(tsns: &mut Vec<TSNFlow>, avbs: &mut Vec<AVBFlow>, f
     let all_flows: AllFlows =
         serde_json::from_str(&txt).expect(&format!("無法解析檔案: {}", file_name));
     for cur_flow in all_flows.tt_flows.iter() {
+        if let Some(latest_offset) = cur_flow.latest_offset {
+            if latest_offset < cur_flow.offset || latest_offset >= cur_flow.period {
+                panic!(
+                    "{}: latest_offset 必需介於 offset 與週期之間，卻為 {}",
+                    file_name, latest_offset
+                );
+            }
+        }
         tsns.push(flow::Flow {
             id: 0.into(),
             size: cur_flow.size,
@@ -36,30 +48,116 @@ fn read_flows_from_file_once(tsns: &mut Vec<TSNFlow>, avbs: &mut Vec<AVBFlow>, f
             max_delay: cur_flow.max_delay,
             spec_data: flow::data::TSNData {
                 offset: cur_flow.offset,
+                max_jitter: cur_flow.max_jitter,
+                latest_offset: cur_flow.latest_offset,
             },
         });
     }
     for cur_flow in all_flows.avb_flows.iter() {
+        let avb_class = if cur_flow.avb_type == 'A' {
+            flow::data::AVBClass::A
+        } else if cur_flow.avb_type == 'B' {
+            flow::data::AVBClass::B
+        } else {
+            panic!("AVB type 必需為 `A` 或 `B`");
+        };
+        let (size, period, max_delay) = resolve_avb_spec(cur_flow, &avb_class)
+            .unwrap_or_else(|msg| panic!("{}: {}", file_name, msg));
         avbs.push(flow::Flow {
             id: 0.into(),
-            size: cur_flow.size,
+            size,
             src: cur_flow.src,
             dst: cur_flow.dst,
-            period: cur_flow.period,
-            max_delay: cur_flow.max_delay,
-            spec_data: flow::data::AVBData {
-                avb_class: if cur_flow.avb_type == 'A' {
-                    flow::data::AVBClass::A
-                } else if cur_flow.avb_type == 'B' {
-                    flow::data::AVBClass::B
-                } else {
-                    panic!("AVB type 必需為 `A` 或 `B`");
-                },
-            },
+            period,
+            max_delay,
+            spec_data: flow::data::AVBData { avb_class },
         });
     }
 }
 
+/// 802.1Q 中 SR class 的訊框最大可以多大
+const MAX_SRP_FRAME_SIZE: usize = 1500;
+
+/// 把 AVB 資料流的描述轉換成 (size, period, max_delay)
+///
+/// 若有給定 `max_frame_size` 及 `max_interval_frames`（即 Talker 廣播的格式），則依 SR class 的語意：
+/// 每個量測區間（A 類 125 微秒，B 類 250 微秒）內最多送出 `max_interval_frames` 個大小不超過 `max_frame_size` 的訊框，
+/// 延遲上限若未給定則採用該等級的延遲預算。此時若另外寫了 `size` 或 `period`，必需與推導出來的值一致。
+///
+/// 否則沿用舊格式，`size`、`period`、`max_delay` 皆必需給定。
+///
+/// 兩種格式最後都要通過 `check_avb_conformance`。
+fn resolve_avb_spec(
+    raw: &RawAVBFlow,
+    avb_class: &flow::data::AVBClass,
+) -> Result<(usize, u32, u32), String> {
+    let (size, period, max_delay) = match (raw.max_frame_size, raw.max_interval_frames) {
+        (Some(max_frame_size), Some(max_interval_frames)) => {
+            if max_frame_size == 0 || max_frame_size > MAX_SRP_FRAME_SIZE {
+                return Err(format!(
+                    "max_frame_size 必需介於 1 到 {} 之間，卻為 {}",
+                    MAX_SRP_FRAME_SIZE, max_frame_size
+                ));
+            }
+            if max_interval_frames == 0 {
+                return Err("max_interval_frames 至少要為 1".to_owned());
+            }
+            let size = max_frame_size * max_interval_frames as usize;
+            let period = avb_class.get_interval();
+            if raw.size.is_some_and(|s| s != size) {
+                return Err(format!(
+                    "size 應為 max_frame_size * max_interval_frames = {}",
+                    size
+                ));
+            }
+            if raw.period.is_some_and(|p| p != period) {
+                return Err(format!(
+                    "{:?} 類資料流的 period 應為量測區間 {}",
+                    avb_class, period
+                ));
+            }
+            let max_delay = raw
+                .max_delay
+                .unwrap_or_else(|| avb_class.get_latency_budget());
+            (size, period, max_delay)
+        }
+        (None, None) => match (raw.size, raw.period, raw.max_delay) {
+            (Some(size), Some(period), Some(max_delay)) => (size, period, max_delay),
+            _ => {
+                return Err(
+                    "未使用 SR class 格式的 AVB 資料流必需給定 size、period 及 max_delay"
+                        .to_owned(),
+                )
+            }
+        },
+        _ => return Err("max_frame_size 與 max_interval_frames 必需同時給定".to_owned()),
+    };
+    check_avb_conformance(size, period, max_delay, avb_class)?;
+    Ok((size, period, max_delay))
+}
+
+/// 確認資料流符合其宣告的 SR class
+///
+/// 大小、週期與延遲上限皆不得為 0；延遲上限不得超過該等級的延遲預算，否則應宣告為較寬鬆的等級。
+fn check_avb_conformance(
+    size: usize,
+    period: u32,
+    max_delay: u32,
+    avb_class: &flow::data::AVBClass,
+) -> Result<(), String> {
+    if size == 0 || period == 0 || max_delay == 0 {
+        return Err("AVB 資料流的 size、period 及 max_delay 皆不得為 0".to_owned());
+    }
+    let budget = avb_class.get_latency_budget();
+    if max_delay > budget {
+        return Err(format!(
+            "{:?} 類資料流的 max_delay 不得超過延遲預算 {}，卻為 {}",
+            avb_class, budget, max_delay
+        ));
+    }
+    Ok(())
+}
+
 use graph_util::Graph;
 pub fn read_topo_from_file(file_name: &str) -> graph_util::StreamAwareGraph {
     let txt = fs::read_to_string(file_name).expect(&format!("找不到檔案: {}", file_name));
@@ -71,9 +169,53 @@ pub fn read_topo_from_file(file_name: &str) -> graph_util::StreamAwareGraph {
     for (n1, n2, bandwidth) in json.edges.into_iter() {
         g.add_edge((n1, n2), bandwidth).expect("插入邊失敗");
     }
+    set_port_configs(&mut g, &json.port_configs)
+        .unwrap_or_else(|msg| panic!("{}: {}", file_name, msg));
+    set_names(&mut g, &json.node_names, &json.ports)
+        .unwrap_or_else(|msg| panic!("{}: {}", file_name, msg));
     g
 }
 
+/// `node_names` 依節點編號排列，可以只給前幾個；`ports` 替每條邊兩端的埠口命名
+fn set_names(
+    g: &mut graph_util::StreamAwareGraph,
+    node_names: &[String],
+    ports: &[RawLinkPorts],
+) -> Result<(), String> {
+    for (id, name) in node_names.iter().enumerate() {
+        g.set_node_name(id, name)?;
+    }
+    for raw in ports.iter() {
+        let (a, b) = raw.edge;
+        g.set_port_name((a, b), &raw.ports.0)?;
+        g.set_port_name((b, a), &raw.ports.1)?;
+    }
+    Ok(())
+}
+
+/// 先套用整台交換機的設定，再以個別埠口的設定覆蓋之
+fn set_port_configs(
+    g: &mut graph_util::StreamAwareGraph,
+    raw_configs: &[RawPortConfig],
+) -> Result<(), String> {
+    for raw in raw_configs.iter() {
+        if raw.node.is_some() == raw.edge.is_some() {
+            return Err("埠口設定必需恰給定 node 或 edge 其中之一".to_owned());
+        }
+    }
+    for raw in raw_configs.iter() {
+        if let Some(node) = raw.node {
+            g.set_node_port_config(node, raw.resolve()?)?;
+        }
+    }
+    for raw in raw_configs.iter() {
+        if let Some(edge) = raw.edge {
+            g.set_port_config(edge, raw.resolve()?)?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize)]
 struct AllFlows {
     tt_flows: Vec<RawTSNFlow>,
@@ -87,15 +229,26 @@ struct RawTSNFlow {
     period: u32,
     max_delay: u32,
     offset: u32,
+    #[serde(default)]
+    max_jitter: Option<u32>,
+    #[serde(default)]
+    latest_offset: Option<u32>,
 }
 #[derive(Serialize, Deserialize)]
 struct RawAVBFlow {
-    size: usize,
+    #[serde(default)]
+    size: Option<usize>,
     src: usize,
     dst: usize,
-    period: u32,
-    max_delay: u32,
+    #[serde(default)]
+    period: Option<u32>,
+    #[serde(default)]
+    max_delay: Option<u32>,
     avb_type: char,
+    #[serde(default)]
+    max_frame_size: Option<usize>,
+    #[serde(default)]
+    max_interval_frames: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -103,4 +256,197 @@ struct GraphJSON {
     host_cnt: usize,
     switch_cnt: usize,
     edges: Vec<(usize, usize, f64)>,
+    #[serde(default)]
+    port_configs: Vec<RawPortConfig>,
+    /// 依節點編號排列的節點名稱
+    #[serde(default)]
+    node_names: Vec<String>,
+    #[serde(default)]
+    ports: Vec<RawLinkPorts>,
+}
+/// 拓撲檔中一條邊兩端的埠口名稱，`ports.0` 位於 `edge.0` 上，`ports.1` 位於 `edge.1` 上
+#[derive(Serialize, Deserialize)]
+struct RawLinkPorts {
+    edge: (usize, usize),
+    ports: (String, String),
+}
+/// 拓撲檔中的埠口設定，`node` 代表該節點所有往外的埠口，`edge` 代表單一埠口
+#[derive(Serialize, Deserialize)]
+struct RawPortConfig {
+    #[serde(default)]
+    node: Option<usize>,
+    #[serde(default)]
+    edge: Option<(usize, usize)>,
+    #[serde(default)]
+    queue_cnt: Option<u8>,
+    #[serde(default)]
+    tt_queues: Option<Vec<u8>>,
+    #[serde(default)]
+    avb_a_queue: Option<u8>,
+    #[serde(default)]
+    avb_b_queue: Option<u8>,
+    #[serde(default)]
+    be_queue: Option<u8>,
+    #[serde(default)]
+    mtu: Option<usize>,
+    #[serde(default)]
+    max_gcl_len: Option<usize>,
+}
+impl RawPortConfig {
+    /// 未給定的欄位採用 `PortConfig::with_queue_cnt` 的對應，連佇列數量都未給定則採用預設值
+    fn resolve(&self) -> Result<graph_util::PortConfig, String> {
+        let mut config = match self.queue_cnt {
+            Some(queue_cnt) => graph_util::PortConfig::with_queue_cnt(queue_cnt)?,
+            None => graph_util::PortConfig::default(),
+        };
+        if let Some(tt_queues) = &self.tt_queues {
+            config.tt_queues = tt_queues.clone();
+        }
+        config.avb_a_queue = self.avb_a_queue.unwrap_or(config.avb_a_queue);
+        config.avb_b_queue = self.avb_b_queue.unwrap_or(config.avb_b_queue);
+        config.be_queue = self.be_queue.unwrap_or(config.be_queue);
+        config.mtu = self.mtu.unwrap_or(config.mtu);
+        config.max_gcl_len = self.max_gcl_len.or(config.max_gcl_len);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flow::data::AVBClass;
+    fn parse(txt: &str) -> RawAVBFlow {
+        serde_json::from_str(txt).unwrap()
+    }
+    #[test]
+    fn test_resolve_srp_avb_spec() {
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "max_frame_size": 100, "max_interval_frames": 2}"#,
+        );
+        assert_eq!(Ok((200, 125, 2000)), resolve_avb_spec(&raw, &AVBClass::A));
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "B", "max_frame_size": 100, "max_interval_frames": 1,
+                "size": 100, "period": 250, "max_delay": 1000}"#,
+        );
+        assert_eq!(Ok((100, 250, 1000)), resolve_avb_spec(&raw, &AVBClass::B));
+        // 舊格式
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "size": 300, "period": 10000, "max_delay": 500}"#,
+        );
+        assert_eq!(Ok((300, 10000, 500)), resolve_avb_spec(&raw, &AVBClass::A));
+    }
+    #[test]
+    fn test_reject_nonconforming_avb_spec() {
+        // 週期與 A 類的量測區間不符
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "max_frame_size": 100, "max_interval_frames": 1, "period": 250}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::A).is_err());
+        // 大小與推導出來的不符
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "max_frame_size": 100, "max_interval_frames": 2, "size": 100}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::A).is_err());
+        // 訊框過大
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "B", "max_frame_size": 2000, "max_interval_frames": 1}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::B).is_err());
+        // 只給一半
+        let raw = parse(r#"{"src": 0, "dst": 1, "avb_type": "B", "max_frame_size": 100}"#);
+        assert!(resolve_avb_spec(&raw, &AVBClass::B).is_err());
+        // 舊格式缺欄位
+        let raw = parse(r#"{"src": 0, "dst": 1, "avb_type": "B", "size": 100}"#);
+        assert!(resolve_avb_spec(&raw, &AVBClass::B).is_err());
+        // 兩種格式的延遲上限都不得超過等級的延遲預算
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "size": 300, "period": 10000, "max_delay": 2001}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::A).is_err());
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "A", "max_frame_size": 100, "max_interval_frames": 1, "max_delay": 2001}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::A).is_err());
+        // 舊格式的週期為 0
+        let raw = parse(
+            r#"{"src": 0, "dst": 1, "avb_type": "B", "size": 100, "period": 0, "max_delay": 500}"#,
+        );
+        assert!(resolve_avb_spec(&raw, &AVBClass::B).is_err());
+    }
+    #[test]
+    fn test_port_configs() {
+        let json: GraphJSON = serde_json::from_str(
+            r#"{"host_cnt": 1, "switch_cnt": 2, "edges": [[0, 1, 100.0], [1, 2, 100.0]],
+                "port_configs": [
+                    {"edge": [1, 2], "queue_cnt": 8, "tt_queues": [6, 7]},
+                    {"node": 1, "queue_cnt": 4},
+                    {"edge": [0, 1], "mtu": 1000, "max_gcl_len": 16}
+                ]}"#,
+        )
+        .unwrap();
+        let mut g = graph_util::StreamAwareGraph::new();
+        g.add_host(Some(json.host_cnt));
+        g.add_switch(Some(json.switch_cnt));
+        for &(n1, n2, bandwidth) in json.edges.iter() {
+            g.add_edge((n1, n2), bandwidth).unwrap();
+        }
+        set_port_configs(&mut g, &json.port_configs).unwrap();
+        let configs = g.get_port_configs();
+        // 0->1 只設定 MTU；2->1 沒有設定；1->0 套用整台交換機的設定；1->2 被個別埠口的設定覆蓋
+        assert_eq!(1000, configs[0].mtu);
+        assert_eq!(Some(16), configs[0].max_gcl_len);
+        assert_eq!(None, configs[1].max_gcl_len);
+        assert_eq!(
+            graph_util::PortConfig::default().tt_queues,
+            configs[0].tt_queues
+        );
+        assert_eq!(
+            graph_util::PortConfig::with_queue_cnt(4).unwrap(),
+            configs[1]
+        );
+        assert_eq!(vec![6, 7], configs[2].tt_queues);
+        assert_eq!(graph_util::PortConfig::default(), configs[3]);
+
+        let raw: RawPortConfig =
+            serde_json::from_str(r#"{"node": 1, "queue_cnt": 4, "tt_queues": [1, 3]}"#).unwrap();
+        assert!(raw.resolve().is_err());
+        let raw: Vec<RawPortConfig> =
+            serde_json::from_str(r#"[{"node": 1, "edge": [1, 2], "queue_cnt": 4}]"#).unwrap();
+        assert!(set_port_configs(&mut g, &raw).is_err());
+    }
+    #[test]
+    fn test_names() {
+        let json: GraphJSON = serde_json::from_str(
+            r#"{"host_cnt": 1, "switch_cnt": 2, "edges": [[0, 1, 100.0], [1, 2, 100.0]],
+                "node_names": ["talker"],
+                "ports": [{"edge": [2, 1], "ports": ["ge-0/0/1", "eth5"]}]}"#,
+        )
+        .unwrap();
+        let mut g = graph_util::StreamAwareGraph::new();
+        g.add_host(Some(json.host_cnt));
+        g.add_switch(Some(json.switch_cnt));
+        for &(n1, n2, bandwidth) in json.edges.iter() {
+            g.add_edge((n1, n2), bandwidth).unwrap();
+        }
+        set_names(&mut g, &json.node_names, &json.ports).unwrap();
+        assert_eq!("talker", g.get_node_name(0));
+        assert_eq!("switch-1", g.get_node_name(1));
+        // 未命名的埠口依連結編號排序
+        assert_eq!(
+            vec!["talker/eth0", "switch-1/eth5"],
+            g.get_route_link_names(&vec![0, 1, 2])
+        );
+        assert_eq!("switch-2/ge-0/0/1", g.get_link_name(3));
+        assert_eq!("switch-1/eth0", g.get_link_name(1));
+        // 名稱不可重複
+        assert!(g.set_node_name(2, "talker").is_err());
+        assert!(g.set_port_name((1, 0), "eth5").is_err());
+        assert!(g.set_port_name((1, 0), "eth1").is_ok());
+        // 刪除邊後，連結編號與名稱不變
+        g.del_edge((0, 1)).unwrap();
+        assert_eq!((2, 1), g.get_link_ends(3));
+        assert_eq!("switch-2/ge-0/0/1", g.get_link_name(3));
+        assert_eq!("switch-1/eth1", g.get_link_name(1));
+    }
 }
@@ -1,21 +1,83 @@
-use adams_leaf::network_wrapper::RoutingCost;
+use adams_leaf::exporter::{export_netconf, export_omnet, export_qbv, render_gcl_svg};
+use adams_leaf::network_wrapper::{Deployment, RoutingCost};
+use adams_leaf::recorder::diff_gcl;
 use adams_leaf::routing_algos::{AdamsAnt, RoutingAlgo, RO, SPF};
 use adams_leaf::{config::Config, read_flows_from_file, read_topo_from_file};
 use regex::Regex;
 use std::env;
+use std::fs;
+
+/// 從參數列中取出形如 `--name=value` 的選項
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r"--{}=([^ ]+)", name)).unwrap();
+    for i in 0..args.len() {
+        if let Some(cap) = re.captures(&args[i]) {
+            let value = cap[1].to_owned();
+            args.remove(i);
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// 匯出的 OMNeT++ 模擬中，網路的名稱
+const OMNET_NETWORK: &str = "AdamsLeafNetwork";
+
+/// 額外的輸出檔案，內容皆取自最後一次實驗
+struct OutputOptions {
+    /// 每一輪的 AVB 延遲組成報告
+    json: Option<String>,
+    /// 每個埠口的 802.1Qbv 閘門控制清單
+    qbv: Option<String>,
+    /// 每台交換機的 NETCONF 設定檔（ieee802-dot1q-sched），輸出至此資料夾
+    netconf: Option<String>,
+    /// OMNeT++/INET 的模擬設定（NED 與 omnetpp.ini），輸出至此資料夾
+    omnet: Option<String>,
+    /// GCL 的甘特圖（SVG）
+    svg: Option<String>,
+    /// 部署結果（資料流、路徑及 GCL），供下次以 `--load` 載回
+    save: Option<String>,
+    /// 兩輪排程之間每個埠口的 GCL 變化
+    gcl_diff: Option<String>,
+    /// 802.1Qbv 的 AdminBaseTime，單位為奈秒
+    qbv_base_time: u64,
+}
+impl OutputOptions {
+    fn take_from(args: &mut Vec<String>) -> Result<Self, String> {
+        let qbv_base_time = match take_option(args, "qbv_base_time") {
+            Some(t) => t
+                .parse()
+                .or_else(|_| Err(format!("qbv_base_time 必需為整數： {}", t)))?,
+            None => 0,
+        };
+        Ok(OutputOptions {
+            json: take_option(args, "json"),
+            qbv: take_option(args, "qbv"),
+            netconf: take_option(args, "netconf"),
+            omnet: take_option(args, "omnet"),
+            svg: take_option(args, "svg"),
+            save: take_option(args, "save"),
+            gcl_diff: take_option(args, "gcl_diff"),
+            qbv_base_time,
+        })
+    }
+}
 
 fn main() -> Result<(), String> {
-    let (algo_type, topo_file_name, flow_file_name, flow_file_name2, times, config_name) = {
+    let (
+        algo_type,
+        topo_file_name,
+        flow_file_name,
+        flow_file_name2,
+        times,
+        config_name,
+        load_name,
+        outputs,
+    ) = {
         let mut args: Vec<String> = env::args().collect();
-        let re = Regex::new(r"--config=([^ ]+)").unwrap();
-        let mut config_name: Option<String> = None;
-        for i in 0..args.len() {
-            if let Some(cap) = re.captures(&args[i]) {
-                config_name = Some(cap[1].to_owned());
-                args.remove(i);
-                break;
-            }
-        }
+        let config_name = take_option(&mut args, "config");
+        let load_name = take_option(&mut args, "load");
+        let outputs = OutputOptions::take_from(&mut args)?;
         if args.len() == 6 {
             (
                 args[1].clone(),
@@ -24,9 +86,11 @@ fn main() -> Result<(), String> {
                 args[4].clone(),
                 args[5].parse::<usize>().unwrap(),
                 config_name,
+                load_name,
+                outputs,
             )
         } else {
-            return Err("用法： adams_leaf [algo type] [topo.json] [base_flow.json] [reconf_flow.json] [倍數] (--config=[設定檔])".to_owned());
+            return Err("用法： adams_leaf [algo type] [topo.json] [base_flow.json] [reconf_flow.json] [倍數] (--config=[設定檔]) (--load=[部署檔]) (--save=[輸出檔]) (--gcl_diff=[輸出檔]) (--json=[輸出檔]) (--qbv=[輸出檔]) (--netconf=[輸出資料夾]) (--omnet=[輸出資料夾]) (--svg=[輸出檔]) (--qbv_base_time=[奈秒])".to_owned());
         }
     };
     if let Some(config_name) = config_name {
@@ -37,10 +101,28 @@ fn main() -> Result<(), String> {
     let (tsns1, avbs1) = read_flows_from_file(&flow_file_name, 1);
     let (tsns2, avbs2) = read_flows_from_file(&flow_file_name2, times);
     let g = read_topo_from_file(&topo_file_name);
+    // 已部署的排程，每次實驗都從它開始
+    let deployment: Option<Deployment> = match &load_name {
+        Some(load_name) => {
+            let txt =
+                fs::read_to_string(load_name).or(Err(format!("找不到檔案： {}", load_name)))?;
+            let deployment = serde_json::from_str(&txt)
+                .or_else(|err| Err(format!("無法解析檔案： {}: {}", load_name, err)))?;
+            Some(deployment)
+        }
+        None => None,
+    };
     // FIXME 對這個圖作 Yens algo，0->2這條路有時找得到6條，有時只找得到5條
 
     let mut cost_list = Vec::<RoutingCost>::new();
     let mut sum_comp_time = 0;
+    let mut reports = vec![];
+    let mut qbv = None;
+    let mut netconf = vec![];
+    let mut omnet = None;
+    let mut svg = None;
+    let mut saved = None;
+    let mut gcl_diff = None;
     for _ in 0..Config::get().exp_times {
         let mut algo: Box<dyn RoutingAlgo> = {
             if algo_type == "aco" {
@@ -53,13 +135,25 @@ fn main() -> Result<(), String> {
                 panic!("{} 是啥鬼= =", algo_type);
             }
         };
-        algo.add_flows(tsns1.clone(), avbs1.clone());
+        // NOTE 載入部署的話，第一輪就是已部署的資料流，不再加入 base_flow 中的資料流
+        if let Some(deployment) = &deployment {
+            algo.load_deployment(deployment.clone())
+                .or_else(|msg| Err(format!("載入部署失敗： {}", msg)))?;
+        } else {
+            algo.add_flows(tsns1.clone(), avbs1.clone());
+        }
+        let report1 = algo.get_avb_latency_report();
         #[cfg(not(feature = "batch-eval"))]
         {
             println!("=== round 1 ===");
             algo.show_results();
         }
+        // NOTE 複製 GCL 並比對的成本不低，只在需要輸出差異時才做
+        let gcl1 = outputs.gcl_diff.as_ref().map(|_| algo.get_gcl().clone());
         algo.add_flows(tsns2.clone(), avbs2.clone());
+        let diff = gcl1
+            .map(|gcl1| diff_gcl(&gcl1, algo.get_gcl()))
+            .transpose()?;
         #[cfg(not(feature = "batch-eval"))]
         {
             println!("=== round 2 ===");
@@ -68,9 +162,95 @@ fn main() -> Result<(), String> {
                 "--- compute time: {} micro sec ---",
                 algo.get_last_compute_time()
             );
+            if let Some(diff) = &diff {
+                println!(
+                    "--- reprovisioned ports: {} / {} ---",
+                    diff.get_reprovision_ports().len(),
+                    diff.ports.len()
+                );
+            }
+        }
+        // 部署前先獨立檢查排程
+        for violation in algo.validate_schedule().iter() {
+            match violation.get_link_id() {
+                Some(link_id) => eprintln!(
+                    "排程不合法（{}）：{:?}",
+                    algo.get_graph().get_link_name(link_id),
+                    violation
+                ),
+                None => eprintln!("排程不合法：{:?}", violation),
+            }
         }
         cost_list.push(algo.get_cost());
         sum_comp_time += algo.get_last_compute_time();
+        reports = vec![report1, algo.get_avb_latency_report()];
+        if outputs.qbv.is_some() {
+            qbv = Some(export_qbv(
+                algo.get_graph(),
+                algo.get_gcl(),
+                outputs.qbv_base_time,
+            ));
+        }
+        if outputs.netconf.is_some() {
+            netconf = export_netconf(algo.get_graph(), algo.get_gcl(), outputs.qbv_base_time);
+        }
+        if outputs.omnet.is_some() {
+            omnet = Some(export_omnet(
+                algo.get_graph(),
+                algo.get_gcl(),
+                &algo.get_routed_tsns(),
+                &algo.get_routed_avbs(),
+                OMNET_NETWORK,
+            ));
+        }
+        if outputs.svg.is_some() {
+            svg = Some(render_gcl_svg(algo.get_graph(), algo.get_gcl()));
+        }
+        if outputs.save.is_some() {
+            saved = Some(algo.get_deployment());
+        }
+        gcl_diff = diff;
+    }
+    if let Some(json_name) = outputs.json {
+        let txt = serde_json::to_string_pretty(&reports).unwrap();
+        fs::write(&json_name, txt).or(Err(format!("寫檔失敗： {}", json_name)))?;
+    }
+    if let (Some(qbv_name), Some(qbv)) = (outputs.qbv, qbv) {
+        let txt = serde_json::to_string_pretty(&qbv).unwrap();
+        fs::write(&qbv_name, txt).or(Err(format!("寫檔失敗： {}", qbv_name)))?;
+    }
+    if let Some(dir_name) = outputs.netconf {
+        fs::create_dir_all(&dir_name).or(Err(format!("無法建立資料夾： {}", dir_name)))?;
+        for (bridge, xml) in netconf.into_iter() {
+            // NOTE 節點名稱中可能有斜線
+            let file_name = format!(
+                "{}/{}.xml",
+                dir_name,
+                g.get_node_name(bridge).replace('/', "_")
+            );
+            fs::write(&file_name, xml).or(Err(format!("寫檔失敗： {}", file_name)))?;
+        }
+    }
+    if let (Some(save_name), Some(saved)) = (outputs.save, saved) {
+        let txt = serde_json::to_string_pretty(&saved).unwrap();
+        fs::write(&save_name, txt).or(Err(format!("寫檔失敗： {}", save_name)))?;
+    }
+    if let (Some(diff_name), Some(gcl_diff)) = (outputs.gcl_diff, gcl_diff) {
+        let txt = serde_json::to_string_pretty(&gcl_diff).unwrap();
+        fs::write(&diff_name, txt).or(Err(format!("寫檔失敗： {}", diff_name)))?;
+    }
+    if let (Some(svg_name), Some(svg)) = (outputs.svg, svg) {
+        fs::write(&svg_name, svg).or(Err(format!("寫檔失敗： {}", svg_name)))?;
+    }
+    if let (Some(dir_name), Some(omnet)) = (outputs.omnet, omnet) {
+        fs::create_dir_all(&dir_name).or(Err(format!("無法建立資料夾： {}", dir_name)))?;
+        let files = [
+            (format!("{}/{}.ned", dir_name, OMNET_NETWORK), omnet.ned),
+            (format!("{}/omnetpp.ini", dir_name), omnet.ini),
+        ];
+        for (file_name, txt) in files.iter() {
+            fs::write(file_name, txt).or(Err(format!("寫檔失敗： {}", file_name)))?;
+        }
     }
     RoutingCost::show_brief(cost_list);
     println!(
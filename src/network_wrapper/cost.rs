@@ -1,4 +1,5 @@
-use super::{compute_avb_latency, NetworkWrapper, OldNew, OldNewTable};
+use super::{compute_avb_latency, compute_avb_latency_breakdown, LinkLatency};
+use super::{NetworkWrapper, OldNew, OldNewTable, ScheduleError};
 use crate::config::Config;
 use crate::flow::{AVBFlow, FlowEnum};
 use crate::recorder::flow_table::prelude::*;
@@ -6,9 +7,15 @@ use crate::recorder::flow_table::prelude::*;
 #[derive(Clone, Copy, Debug)]
 pub struct RoutingCost {
     pub tsn_schedule_fail: bool,
+    /// TT 排程失敗時，是哪個資料流在哪個連結上失敗（供 ACO 針對該資料流懲罰）
+    pub tsn_fail_cause: Option<ScheduleError>,
     pub avb_fail_cnt: u32,
+    /// 超額預留頻寬的連結數量
+    pub overload_link_cnt: u32,
     pub avb_wcd: f64,
     pub reroute_overhead: u32,
+    /// 排程被迫改動的舊 TT 資料流數量
+    pub tsn_disrupt_cnt: u32,
     pub avb_cnt: usize,
     pub tsn_cnt: usize,
 }
@@ -17,7 +24,9 @@ impl RoutingCost {
     pub fn compute(&self) -> f64 {
         let config = Config::get();
         let cost = self.compute_without_reroute_cost();
-        cost + config.w2 * self.reroute_overhead as f64 / (self.avb_cnt + self.tsn_cnt) as f64
+        let flow_cnt = (self.avb_cnt + self.tsn_cnt) as f64;
+        cost + config.w2 * self.reroute_overhead as f64 / flow_cnt
+            + config.w4 * self.tsn_disrupt_cnt as f64 / flow_cnt
     }
     pub fn compute_without_reroute_cost(&self) -> f64 {
         let config = Config::get();
@@ -26,9 +35,14 @@ impl RoutingCost {
             cost += config.w0;
         }
         cost += config.w1 * self.avb_fail_cnt as f64 / self.avb_cnt as f64;
+        cost += config.w5 * self.overload_link_cnt as f64;
         cost += config.w3 * self.avb_wcd / self.avb_cnt as f64;
         cost
     }
+    /// 所有 AVB 資料流皆趕上死線，且沒有任何連結超額預留頻寬
+    pub fn is_avb_feasible(&self) -> bool {
+        self.avb_fail_cnt == 0 && self.overload_link_cnt == 0
+    }
     pub fn show_brief(list: Vec<Self>) {
         let mut all_avb_fail_cnt = 0;
         let mut all_avb_wcd = 0.0;
@@ -41,7 +55,7 @@ impl RoutingCost {
         );
         for (i, cost) in list.iter().enumerate() {
             if cost.tsn_schedule_fail {
-                println!("#{}:\tTSN Schedule Fail!", i);
+                println!("#{}:\tTSN Schedule Fail! {:?}", i, cost.tsn_fail_cause);
             } else {
                 all_avb_fail_cnt += cost.avb_fail_cnt;
                 all_reroute_cnt += cost.reroute_overhead;
@@ -70,6 +84,8 @@ impl RoutingCost {
 
 pub trait Calculator<T: Clone + Eq> {
     fn _compute_avb_wcd(&self, flow: &AVBFlow, route: Option<&T>) -> u32;
+    fn _compute_avb_latency_breakdown(&self, flow: &AVBFlow, route: Option<&T>)
+        -> Vec<LinkLatency>;
     fn _compute_single_avb_cost(&self, flow: &AVBFlow) -> RoutingCost;
     fn _compute_all_cost(&self) -> RoutingCost;
 }
@@ -83,6 +99,18 @@ impl<T: Clone + Eq> Calculator<T> for NetworkWrapper<T> {
         };
         compute_avb_latency(&self.graph, flow, route, &self.flow_table, &self.gcl)
     }
+    fn _compute_avb_latency_breakdown(
+        &self,
+        flow: &AVBFlow,
+        route: Option<&T>,
+    ) -> Vec<LinkLatency> {
+        let route_t = route.unwrap_or(self.flow_table.get_info(flow.id).unwrap());
+        let route = unsafe {
+            let r = (self.get_route_func)(self.flow_table.get(flow.id).unwrap(), route_t);
+            &*r
+        };
+        compute_avb_latency_breakdown(&self.graph, flow, route, &self.flow_table, &self.gcl)
+    }
     fn _compute_single_avb_cost(&self, flow: &AVBFlow) -> RoutingCost {
         let avb_wcd = self._compute_avb_wcd(flow, None) as f64 / flow.max_delay as f64;
         let mut avb_fail_cnt = 0;
@@ -98,13 +126,23 @@ impl<T: Clone + Eq> Calculator<T> for NetworkWrapper<T> {
         ) {
             reroute_cnt += 1;
         }
+        // 只計算這個資料流經過的連結
+        let route = self.get_route(flow.id);
+        let overload_link_cnt = self
+            .get_overloaded_edges()
+            .into_iter()
+            .filter(|&(a, b)| (0..route.len() - 1).any(|i| route[i] == a && route[i + 1] == b))
+            .count() as u32;
         RoutingCost {
-            tsn_schedule_fail: self.tsn_fail,
+            tsn_schedule_fail: !self.tsn_fails.is_empty(),
+            tsn_fail_cause: self.get_tsn_fail(),
             avb_cnt: 1,
             tsn_cnt: 0,
             avb_fail_cnt,
+            overload_link_cnt,
             avb_wcd,
             reroute_overhead: reroute_cnt,
+            tsn_disrupt_cnt: 0,
         }
     }
     fn _compute_all_cost(&self) -> RoutingCost {
@@ -125,12 +163,15 @@ impl<T: Clone + Eq> Calculator<T> for NetworkWrapper<T> {
             }
         }
         RoutingCost {
-            tsn_schedule_fail: self.tsn_fail,
+            tsn_schedule_fail: !self.tsn_fails.is_empty(),
+            tsn_fail_cause: self.get_tsn_fail(),
             avb_cnt: self.flow_table.get_avb_cnt(),
             tsn_cnt: self.flow_table.get_tsn_cnt(),
             avb_fail_cnt: all_avb_fail_cnt,
+            overload_link_cnt: self.get_overloaded_edges().len() as u32,
             avb_wcd: all_avb_wcd,
             reroute_overhead: all_reroute_cnt,
+            tsn_disrupt_cnt: self.tsn_disrupted.len() as u32,
         }
     }
 }
@@ -1,6 +1,9 @@
+use crate::config::Config;
 use crate::flow::{AVBFlow, FlowEnum, FlowID, TSNFlow};
-use crate::graph_util::{Graph, MemorizingGraph, StreamAwareGraph};
-use crate::recorder::{flow_table::prelude::*, GCL};
+use crate::graph_util::{MemorizingGraph, StreamAwareGraph};
+use crate::recorder::{flow_table::prelude::*, GCLSnapshot, GCL};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::rc::Rc;
 
 mod cost;
@@ -9,10 +12,41 @@ pub use cost::RoutingCost;
 mod old_new_table;
 use old_new_table::{OldNew, OldNewTable};
 mod time_and_tide;
-use time_and_tide::{compute_avb_latency, schedule_online};
+use time_and_tide::MAX_AVB_SETTING;
+use time_and_tide::{compute_avb_latency, compute_avb_latency_breakdown, compute_jitter};
+pub use time_and_tide::{get_frame_sizes, FailReason, LinkLatency, ScheduleError, Violation};
+use time_and_tide::{get_guard_bands, get_tt_load, schedule_online, validate_schedule};
 
 type Route = Vec<usize>;
 
+/// 一條 AVB 資料流的延遲報告，可以直接輸出成 JSON
+#[derive(Clone, Debug, Serialize)]
+pub struct AVBLatencyReport {
+    pub flow_id: FlowID,
+    pub route: Route,
+    pub max_delay: u32,
+    pub latency: u32,
+    /// 路徑上每個連結的延遲組成
+    pub hops: Vec<LinkLatency>,
+}
+
+/// 一條已部署的資料流及其路徑
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeployedFlow<F> {
+    pub flow: F,
+    pub route: Route,
+}
+
+/// 已部署的排程，包含所有資料流、路徑及 GCL（含佇列分配與釋出時間）
+///
+/// 存成 JSON 後，重新啟動時可以用 `NetworkWrapper::load_deployment` 載回，作為下次重新配置的起點
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Deployment {
+    pub tsns: Vec<DeployedFlow<TSNFlow>>,
+    pub avbs: Vec<DeployedFlow<AVBFlow>>,
+    pub gcl: GCLSnapshot,
+}
+
 /// 這個結構預期會被複製很多次，因此其中的每個元件都應儘可能想辦法降低複製成本
 #[derive(Clone)]
 pub struct NetworkWrapper<T: Clone + Eq> {
@@ -21,19 +55,29 @@ pub struct NetworkWrapper<T: Clone + Eq> {
     get_route_func: Rc<dyn Fn(&FlowEnum, &T) -> *const Route>,
     gcl: GCL,
     graph: MemorizingGraph,
-    tsn_fail: bool,
+    /// 至今仍未排進 GCL 的 TT 資料流，及其失敗的原因，之後真的排進去才移除
+    tsn_fails: BTreeMap<FlowID, ScheduleError>,
+    /// 自上次插入資料流以來，排程被迫改動的舊 TT 資料流
+    tsn_disrupted: Vec<FlowID>,
 }
 
 impl<T: Clone + Eq> NetworkWrapper<T> {
-    pub fn new<F>(hyper_p: u32, graph: StreamAwareGraph, get_route_func: F) -> Self
+    /// 超週期一開始為 1，插入資料流時再依其週期延長
+    pub fn new<F>(graph: StreamAwareGraph, get_route_func: F) -> Self
     where
         F: 'static + Fn(&FlowEnum, &T) -> *const Route,
     {
+        let config = Config::get();
+        let mut gcl = GCL::with_port_configs(1, graph.get_port_configs());
+        if config.guard_band {
+            gcl.set_guard_bands(get_guard_bands(&graph, config.preemption));
+        }
         NetworkWrapper {
             flow_table: FlowTable::new(),
             old_new_table: None,
-            gcl: GCL::new(hyper_p, graph.get_edge_cnt()),
-            tsn_fail: false,
+            gcl,
+            tsn_fails: BTreeMap::new(),
+            tsn_disrupted: vec![],
             graph: MemorizingGraph::new(graph),
             get_route_func: Rc::new(get_route_func),
         }
@@ -42,6 +86,23 @@ impl<T: Clone + Eq> NetworkWrapper<T> {
     pub fn insert(&mut self, tsns: Vec<TSNFlow>, avbs: Vec<AVBFlow>, default_info: T) {
         // 釋放舊的表備份表
         self.old_new_table = None;
+        self.tsn_disrupted.clear();
+        // 延長超週期，已排好的 TT 資料流會在 GCL 中自動展開
+        let mut periods: Vec<u32> = tsns.iter().map(|flow| flow.period).collect();
+        if Config::get().hyper_p_with_avb {
+            periods.extend(avbs.iter().map(|flow| flow.period));
+        }
+        for period in periods.into_iter() {
+            if let Err(hyper_p) = self.gcl.update_hyper_p(period) {
+                // NOTE 此時週期無法整除超週期的 TT 資料流將會排程失敗
+                eprintln!(
+                    "警告：週期 {} 會使超週期延長為 {}，超過上限 {}，故忽略之",
+                    period,
+                    hyper_p,
+                    crate::MAX_HYPER_P
+                );
+            }
+        }
         // 插入
         let new_ids = self.flow_table.insert(tsns, avbs, default_info.clone());
         let mut reconf = self.flow_table.clone_as_diff();
@@ -62,6 +123,114 @@ impl<T: Clone + Eq> NetworkWrapper<T> {
         });
         self.old_new_table = Some(Rc::new(old_new_table));
     }
+    /// 目前的部署結果，資料流依編號排列
+    pub fn get_deployment(&self) -> Deployment {
+        Deployment {
+            tsns: self
+                .flow_table
+                .iter_tsn()
+                .map(|(flow, _)| DeployedFlow {
+                    flow: flow.clone(),
+                    route: self.get_route(flow.id).clone(),
+                })
+                .collect(),
+            avbs: self
+                .flow_table
+                .iter_avb()
+                .map(|(flow, _)| DeployedFlow {
+                    flow: flow.clone(),
+                    route: self.get_route(flow.id).clone(),
+                })
+                .collect(),
+            gcl: self.gcl.snapshot(),
+        }
+    }
+    /// 在還沒有任何資料流時載入已部署的排程，不重新排程。之後插入資料流時，這些資料流都被視為舊資料流
+    ///
+    /// 載入的資料流並不會被釘住：與其它舊資料流相同，新資料流排不進去時仍可能被重排（見 `schedule_online`），
+    /// 被改動者記在 `get_disrupted_tsns` 中，部署時要一併更新它們經過的交換機。
+    ///
+    /// 資料流編號必需由 0 開始連續，且與 GCL 中的編號一致。
+    /// `get_info` 把路徑轉換成演算法內部的路徑資訊，無法轉換者（如不在候選路徑中）回傳 None，此時載入失敗。
+    pub fn load_deployment<F>(&mut self, deployment: Deployment, get_info: F) -> Result<(), String>
+    where
+        F: Fn(&FlowEnum, &Route) -> Option<T>,
+    {
+        if self.flow_table.get_flow_cnt() > 0 {
+            return Err("只能在還沒有任何資料流時載入部署".to_owned());
+        }
+        let Deployment { tsns, avbs, gcl } = deployment;
+        let tsn_ids: HashSet<FlowID> = tsns.iter().map(|deployed| deployed.flow.id).collect();
+        let mut flows: Vec<(FlowID, FlowEnum, Route)> = tsns
+            .into_iter()
+            .map(|deployed| (deployed.flow.id, deployed.flow.into(), deployed.route))
+            .chain(
+                avbs.into_iter()
+                    .map(|deployed| (deployed.flow.id, deployed.flow.into(), deployed.route)),
+            )
+            .collect();
+        flows.sort_by_key(|(id, ..)| *id);
+        let links: HashSet<(usize, usize)> = self
+            .graph
+            .get_links()
+            .into_iter()
+            .map(|(_, pair, _)| pair)
+            .collect();
+        let mut infos = Vec::<T>::new();
+        for (i, (id, flow, route)) in flows.iter().enumerate() {
+            if id.0 != i {
+                return Err(format!("資料流編號必需由 0 開始連續，卻出現 {:?}", id));
+            }
+            let (src, dst) = match flow {
+                FlowEnum::TSN(flow) => (flow.src, flow.dst),
+                FlowEnum::AVB(flow) => (flow.src, flow.dst),
+            };
+            if route.len() < 2
+                || route[0] != src
+                || route[route.len() - 1] != dst
+                || route
+                    .windows(2)
+                    .any(|pair| !links.contains(&(pair[0], pair[1])))
+            {
+                return Err(format!("資料流 {:?} 的路徑不合法：{:?}", id, route));
+            }
+            match get_info(flow, route) {
+                Some(info) => infos.push(info),
+                None => return Err(format!("資料流 {:?} 的路徑不在候選路徑中：{:?}", id, route)),
+            }
+        }
+        let unknown_flow = gcl
+            .gate_events
+            .iter()
+            .flat_map(|evts| evts.iter().map(|evt| evt.3))
+            .chain(gcl.releases.iter().map(|&(flow_id, _)| flow_id))
+            .find(|flow_id| !tsn_ids.contains(flow_id));
+        if let Some(flow_id) = unknown_flow {
+            return Err(format!("GCL 中的 {:?} 不是已部署的 TT 資料流", flow_id));
+        }
+        self.gcl.restore(gcl)?;
+        for ((id, flow, route), info) in flows.into_iter().zip(infos) {
+            match flow {
+                FlowEnum::TSN(flow) => {
+                    let links = self.graph.get_links_id_bandwidth(&route);
+                    let load = get_tt_load(&flow, &links, &self.gcl);
+                    self.graph
+                        .update_reservation_on_route(true, id, load, false, &route);
+                    self.flow_table.insert(vec![flow], vec![], info);
+                }
+                FlowEnum::AVB(flow) => {
+                    let load = flow.size as f64 / flow.period as f64;
+                    self.graph.update_flowid_on_route(true, id, &route);
+                    self.graph
+                        .update_reservation_on_route(true, id, load, true, &route);
+                    self.flow_table.insert(vec![], vec![flow], info);
+                }
+            }
+        }
+        let old_new_table = self.flow_table.clone_as_type(|_, t| OldNew::Old(t.clone()));
+        self.old_new_table = Some(Rc::new(old_new_table));
+        Ok(())
+    }
     pub fn get_route(&self, flow_id: FlowID) -> &Route {
         let flow_enum = self.flow_table.get(flow_id).unwrap();
         let info = self.flow_table.get_info(flow_id).unwrap();
@@ -85,12 +254,15 @@ impl<T: Clone + Eq> NetworkWrapper<T> {
         // NOTE: 因為 self.graph 與 self.get_route 是平行所有權
         let graph = unsafe { &mut (*(self as *mut Self)).graph };
         let og_route = self.get_route(flow.id);
+        let load = flow.size as f64 / flow.period as f64;
         // 忘掉舊的
         graph.update_flowid_on_route(false, flow.id, og_route);
+        graph.update_reservation_on_route(false, flow.id, load, true, og_route);
         self.flow_table.update_info(flow.id, info);
         let new_route = self.get_route(flow.id);
         // 記憶新的
         graph.update_flowid_on_route(true, flow.id, new_route);
+        graph.update_reservation_on_route(true, flow.id, load, true, new_route);
     }
     /// 更新 AVB 資料流表與圖上資訊
     pub fn update_avb(&mut self, diff: &DiffFlowTable<T>) {
@@ -102,9 +274,12 @@ impl<T: Clone + Eq> NetworkWrapper<T> {
     pub fn update_tsn(&mut self, diff: &DiffFlowTable<T>) {
         // NOTE: 在 schedule_online 函式中就會更新資料流表（這當然是個不太好的實作……）
         //       因此在這裡就不用執行 self.flow_table.update_info()
+        // NOTE: 因為 self.graph 與 self.get_route 是平行所有權
+        let graph = unsafe { &mut (*(self as *mut Self)).graph };
         for (flow, _) in diff.iter_tsn() {
-            // NOTE: 拔除 GCL
+            // NOTE: 拔除 GCL 及預留的頻寬
             let route = self.get_route(flow.id);
+            graph.update_reservation_on_route(false, flow.id, 0.0, false, route);
             let links: Vec<usize> = self
                 .graph
                 .get_links_id_bandwidth(route)
@@ -122,20 +297,118 @@ impl<T: Clone + Eq> NetworkWrapper<T> {
                 (*_self).graph.get_links_id_bandwidth(route)
             }
         });
-        if result.is_err() {
-            self.tsn_fail = true;
-        } else {
-            // TODO: 應該如何處理 result = Ok(bool) ？
-            self.tsn_fail = false;
+        // NOTE: 大洗牌可能把先前失敗的資料流也排進去，故一併檢查；只替真的排進 GCL 的資料流預留頻寬
+        let mut flow_ids: Vec<FlowID> = diff.iter_tsn().map(|(flow, _)| flow.id).collect();
+        flow_ids.extend(self.tsn_fails.keys().filter(|&&id| !diff.check_exist(id)));
+        for flow_id in flow_ids.into_iter() {
+            if self.gcl.get_release(flow_id).is_some() {
+                self.tsn_fails.remove(&flow_id);
+                let flow = self.flow_table.get_tsn(flow_id).unwrap();
+                let route = self.get_route(flow_id);
+                let links = self.graph.get_links_id_bandwidth(route);
+                let load = get_tt_load(flow, &links, &self.gcl);
+                graph.update_reservation_on_route(true, flow_id, load, false, route);
+            } else if let Err(error) = result {
+                // 同一批改動的資料流一起排程，一起失敗，共用同一個原因
+                if diff.check_exist(flow_id) {
+                    self.tsn_fails.insert(flow_id, error);
+                }
+            }
+        }
+        match result {
+            Err(_) => (),
+            Ok(disrupted) => {
+                for flow_id in disrupted.into_iter() {
+                    if let Err(pos) = self.tsn_disrupted.binary_search(&flow_id) {
+                        self.tsn_disrupted.insert(pos, flow_id);
+                    }
+                }
+            }
         }
     }
+    /// 有 TT 資料流至今仍未排進去的話，編號最小者是在哪個連結上失敗，以及失敗的原因
+    pub fn get_tsn_fail(&self) -> Option<ScheduleError> {
+        self.tsn_fails.values().next().copied()
+    }
+    /// 至今仍未排進去的 TT 資料流及其失敗的原因，依編號排序
+    pub fn get_tsn_fails(&self) -> &BTreeMap<FlowID, ScheduleError> {
+        &self.tsn_fails
+    }
+    /// 自上次插入資料流以來，排程被迫改動的舊 TT 資料流（依編號排序）
+    pub fn get_disrupted_tsns(&self) -> &Vec<FlowID> {
+        &self.tsn_disrupted
+    }
     pub fn get_flow_table(&self) -> &FlowTable<T> {
         &self.flow_table
     }
+    /// 所有 TT 資料流及其路徑
+    pub fn get_routed_tsns(&self) -> Vec<(&TSNFlow, &Route)> {
+        self.flow_table
+            .iter_tsn()
+            .map(|(flow, _)| (flow, self.get_route(flow.id)))
+            .collect()
+    }
+    /// 所有 AVB 資料流及其路徑
+    pub fn get_routed_avbs(&self) -> Vec<(&AVBFlow, &Route)> {
+        self.flow_table
+            .iter_avb()
+            .map(|(flow, _)| (flow, self.get_route(flow.id)))
+            .collect()
+    }
+    pub fn get_gcl(&self) -> &GCL {
+        &self.gcl
+    }
+    pub fn get_graph(&self) -> &StreamAwareGraph {
+        &self.graph
+    }
     /// 路徑為可選參數，若不給代表照資料流表來走
     pub fn compute_avb_wcd(&self, flow: &AVBFlow, route: Option<&T>) -> u32 {
         self._compute_avb_wcd(flow, route)
     }
+    /// 路徑為可選參數，若不給代表照資料流表來走
+    pub fn compute_avb_latency_breakdown(
+        &self,
+        flow: &AVBFlow,
+        route: Option<&T>,
+    ) -> Vec<LinkLatency> {
+        self._compute_avb_latency_breakdown(flow, route)
+    }
+    /// 針對所有 AVB 資料流，列出延遲的組成
+    pub fn report_avb_latency(&self) -> Vec<AVBLatencyReport> {
+        let mut reports = vec![];
+        for (flow, _) in self.flow_table.iter_avb() {
+            reports.push(AVBLatencyReport {
+                flow_id: flow.id,
+                route: self.get_route(flow.id).clone(),
+                max_delay: flow.max_delay,
+                latency: self.compute_avb_wcd(flow, None),
+                hops: self.compute_avb_latency_breakdown(flow, None),
+            });
+        }
+        reports
+    }
+    /// TT 資料流實際排出來的端對端抖動
+    pub fn compute_tsn_jitter(&self, flow: &TSNFlow) -> u32 {
+        let route = self.get_route(flow.id);
+        let links = self.graph.get_links_id_bandwidth(route);
+        compute_jitter(flow, &links, &self.gcl, Config::get().preemption)
+    }
+    /// TT 資料流實際採用的釋出時間（可能是排程時在釋出窗口中選出來的），排程失敗者回傳 None
+    pub fn get_tsn_release(&self, flow_id: FlowID) -> Option<u32> {
+        self.gcl.get_release(flow_id)
+    }
+    /// 獨立檢查目前的 GCL 是否合法，回傳所有違反的規則
+    pub fn validate_schedule(&self) -> Vec<Violation> {
+        validate_schedule(&self.flow_table, &self.gcl, |flow, t| {
+            let route =
+                unsafe { &*(self.get_route_func)(self.flow_table.get(flow.id).unwrap(), t) };
+            self.graph.get_links_id_bandwidth(route)
+        })
+    }
+    /// 超額預留頻寬的連結：AVB 超過 Credit Base Shaper 的上限，或 TT 加 AVB 超過頻寬
+    pub fn get_overloaded_edges(&self) -> Vec<(usize, usize)> {
+        self.graph.get_overloaded_edges(MAX_AVB_SETTING)
+    }
     pub fn compute_all_cost(&self) -> RoutingCost {
         self._compute_all_cost()
     }
@@ -167,7 +440,7 @@ mod test {
     fn init() -> (NetworkWrapper<usize>, Vec<TSNFlow>) {
         let graph = read_topo_from_file("test_graph.json");
         let env = Env::new();
-        let wrapper = NetworkWrapper::new(600, graph, move |flow, k: &usize| match flow {
+        let wrapper = NetworkWrapper::new(graph, move |flow, k: &usize| match flow {
             FlowEnum::AVB(flow) => env.get_route(flow.src, flow.dst, *k),
             FlowEnum::TSN(flow) => env.get_route(flow.src, flow.dst, *k),
         });
@@ -179,7 +452,11 @@ mod test {
                 size: 100,
                 period: 100,
                 max_delay: 100,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
             TSNFlow {
                 id: 0.into(),
@@ -188,7 +465,11 @@ mod test {
                 size: 100,
                 period: 150,
                 max_delay: 150,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
             TSNFlow {
                 id: 0.into(),
@@ -197,7 +478,11 @@ mod test {
                 size: 100,
                 period: 200,
                 max_delay: 200,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
         ];
         (wrapper, flows)
@@ -241,6 +526,162 @@ mod test {
         assert_eq!(&OldNew::New, old_new);
     }
     #[test]
+    fn test_tsn_reservation() {
+        let (mut wrapper, flows) = init();
+        wrapper.insert(flows.clone(), vec![], 0);
+        assert_eq!(0, wrapper.validate_schedule().len());
+        // 資料流 0、1、2 都經過 0->4，每個 100 位元組的訊框在線路上佔 142 位元組
+        let (avb, tt) = wrapper.graph.get_reservation((0, 4));
+        assert_eq!(0.0, avb);
+        assert!((tt - (142.0 / 100.0 + 142.0 / 150.0 + 142.0 / 200.0)).abs() < 1e-9);
+        assert_eq!((0.0, 142.0 / 200.0), wrapper.graph.get_reservation((1, 0)));
+
+        let mut diff = wrapper.flow_table.clone_as_diff();
+        diff.update_info(1.into(), 1);
+        wrapper.update_tsn(&diff);
+        assert_eq!(0, wrapper.validate_schedule().len());
+        let (_, tt) = wrapper.graph.get_reservation((0, 4));
+        assert!((tt - (142.0 / 100.0 + 142.0 / 200.0)).abs() < 1e-9);
+        assert_eq!((0.0, 142.0 / 150.0), wrapper.graph.get_reservation((0, 5)));
+        assert_eq!(0, wrapper.get_overloaded_edges().len());
+    }
+    #[test]
+    fn test_load_deployment() {
+        let (mut wrapper, flows) = init();
+        wrapper.insert(flows.clone(), vec![], 0);
+        let mut diff = wrapper.flow_table.clone_as_diff();
+        diff.update_info(1.into(), 1);
+        wrapper.update_tsn(&diff);
+        let deployment = wrapper.get_deployment();
+        let txt = serde_json::to_string(&deployment).unwrap();
+        let deployment: Deployment = serde_json::from_str(&txt).unwrap();
+
+        let (mut loaded, _) = init();
+        let env = Env::new();
+        let get_info = |flow: &FlowEnum, route: &Route| {
+            let (src, dst) = match flow {
+                FlowEnum::TSN(flow) => (flow.src, flow.dst),
+                FlowEnum::AVB(flow) => (flow.src, flow.dst),
+            };
+            env.0[&(src, dst)].iter().position(|r| r == route)
+        };
+        loaded
+            .load_deployment(deployment.clone(), get_info)
+            .unwrap();
+        assert_eq!(&vec![0, 5, 4], loaded.get_route(1.into()));
+        assert_eq!(wrapper.gcl.snapshot(), loaded.gcl.snapshot());
+        let (_, tt) = loaded.graph.get_reservation((0, 4));
+        assert!((tt - wrapper.graph.get_reservation((0, 4)).1).abs() < 1e-9);
+        assert_eq!(0, loaded.validate_schedule().len());
+        // 載入的資料流都是舊的，新插入的資料流不會動到它們的閘門事件
+        loaded.insert(vec![flows[0].clone()], vec![], 0);
+        assert_eq!(
+            &OldNew::Old(1),
+            loaded
+                .old_new_table
+                .as_ref()
+                .unwrap()
+                .get_info(1.into())
+                .unwrap()
+        );
+        assert_eq!(None, loaded.get_tsn_fail());
+        let link_id = loaded.graph.get_links_id_bandwidth(&vec![0, 5])[0].0;
+        assert_eq!(
+            wrapper.gcl.get_flow_gate_events(link_id, 1.into()),
+            loaded.gcl.get_flow_gate_events(link_id, 1.into())
+        );
+        assert_eq!(0, loaded.validate_schedule().len());
+        // 但載入的資料流並沒有被釘住：新資料流排不進去時，它們跟一般的舊資料流一樣可能被重排
+        let (mut reloaded, _) = init();
+        reloaded
+            .load_deployment(deployment.clone(), get_info)
+            .unwrap();
+        let mut urgent = flows[0].clone();
+        urgent.max_delay = 3;
+        reloaded.insert(vec![urgent], vec![], 0);
+        assert_eq!(None, reloaded.get_tsn_fail());
+        assert_eq!(&vec![FlowID(0)], reloaded.get_disrupted_tsns());
+        let link_id = reloaded.graph.get_links_id_bandwidth(&vec![0, 4])[0].0;
+        assert_eq!(
+            Some(&(0, 2)),
+            reloaded.gcl.get_flow_gate_events(link_id, 3.into()).first()
+        );
+        assert_eq!(0, reloaded.validate_schedule().len());
+        // 怎麼重排都排不進去的話，載入的資料流維持本來的排程
+        let (mut reloaded, _) = init();
+        reloaded
+            .load_deployment(deployment.clone(), get_info)
+            .unwrap();
+        let mut impossible = flows[0].clone();
+        impossible.max_delay = 1;
+        reloaded.insert(vec![impossible], vec![], 0);
+        assert!(reloaded.get_tsn_fail().is_some());
+        let mut snapshot = reloaded.gcl.snapshot();
+        snapshot
+            .queue_assignments
+            .retain(|&(_, flow_id, _)| flow_id != 3.into());
+        assert_eq!(deployment.gcl, snapshot);
+        // 已經有資料流了，不能再載入
+        assert!(loaded
+            .load_deployment(deployment.clone(), get_info)
+            .is_err());
+        // 路徑不在候選路徑中
+        let (mut other, _) = init();
+        let mut bad = deployment;
+        bad.tsns[1].route = vec![0, 1, 3, 4];
+        assert!(other.load_deployment(bad, get_info).is_err());
+        assert_eq!(0, other.get_flow_table().get_flow_cnt());
+    }
+    #[test]
+    fn test_hyper_p() {
+        let (mut wrapper, mut flows) = init();
+        wrapper.insert(flows.clone(), vec![], 0);
+        assert_eq!(600, wrapper.gcl.get_hyper_p());
+        let link_id = wrapper.graph.get_links_id_bandwidth(&vec![0, 4])[0].0;
+        let evts = wrapper.gcl.get_gate_events(link_id).clone();
+        // 週期 400 的資料流使超週期延長，舊的事件也要跟著展開
+        flows[0].period = 400;
+        flows[0].max_delay = 400;
+        wrapper.insert(vec![flows[0].clone()], vec![], 0);
+        assert_eq!(1200, wrapper.gcl.get_hyper_p());
+        assert_eq!(None, wrapper.get_tsn_fail());
+        assert_eq!(0, wrapper.validate_schedule().len());
+        let flow_evts = wrapper.gcl.get_flow_gate_events(link_id, 0.into());
+        assert_eq!(12, flow_evts.len());
+        assert_eq!(flow_evts[0].0 + 600, flow_evts[6].0);
+        assert!(evts.len() < wrapper.gcl.get_gate_events(link_id).len());
+    }
+    #[test]
+    fn test_pathological_hyper_p() {
+        let (mut wrapper, mut flows) = init();
+        wrapper.insert(flows.clone(), vec![], 0);
+        let route = wrapper.get_route(0.into()).clone();
+        let (_, tt) = wrapper.graph.get_reservation((route[0], route[1]));
+        // 與 600 互質的大質數，超週期會爆掉，只能讓該資料流排程失敗
+        flows[0].period = 999_983;
+        flows[0].max_delay = 999_983;
+        wrapper.insert(vec![flows[0].clone()], vec![], 0);
+        assert_eq!(600, wrapper.gcl.get_hyper_p());
+        let error = wrapper.get_tsn_fail().unwrap();
+        // 新插入的資料流編號接在原本的 3 個資料流之後
+        assert_eq!(3, error.flow_id.0);
+        assert_eq!(None, error.link_id);
+        assert_eq!(FailReason::HyperPeriod, error.reason);
+        // 沒排進去的資料流不預留頻寬
+        assert_eq!(&route, wrapper.get_route(3.into()));
+        let (_, new_tt) = wrapper.graph.get_reservation((route[0], route[1]));
+        assert!((tt - new_tt).abs() < 1e-9);
+        // 之後插入的資料流排得進去，但先前失敗的紀錄仍在
+        wrapper.insert(vec![flows[1].clone()], vec![], 0);
+        assert!(wrapper.gcl.get_release(4.into()).is_some());
+        assert_eq!(Some(error), wrapper.get_tsn_fail());
+        assert_eq!(
+            vec![FlowID(3)],
+            wrapper.get_tsn_fails().keys().cloned().collect::<Vec<_>>()
+        );
+        assert!(wrapper.compute_all_cost().tsn_schedule_fail);
+    }
+    #[test]
     #[should_panic]
     fn test_clone_and_insert_should_panic() {
         let (mut wrapper, flows) = init();
@@ -1,5 +1,56 @@
 mod time_estimate;
-pub use time_estimate::compute_avb_latency;
+pub use time_estimate::{compute_avb_latency, compute_avb_latency_breakdown, LinkLatency};
 
 mod tt_scheduling;
-pub use tt_scheduling::schedule_online;
+pub use tt_scheduling::{
+    compute_jitter, get_frame_sizes, schedule_online, FailReason, ScheduleError,
+};
+
+use crate::flow::TSNFlow;
+use crate::graph_util::StreamAwareGraph;
+use crate::recorder::GCL;
+use tt_scheduling::get_flow_frames;
+
+mod validator;
+pub use validator::{validate_schedule, Violation};
+
+/// AVB 資料流最多可以佔用的資源百分比（模擬 Credit Base Shaper 的效果）
+pub const MAX_AVB_SETTING: f64 = 0.75;
+/// 線路上每個訊框額外佔用的位元組：前導碼 7、SFD 1、MAC 標頭 14、VLAN 標籤 4、FCS 4 及訊框間隙 12
+const FRAME_OVERHEAD: usize = 42;
+/// 帶有 VLAN 標籤的訊框，酬載至少要 42 位元組，不足者補齊
+const MIN_PAYLOAD: usize = 42;
+/// BE 訊框在線路上最多佔用幾個位元組（1500 位元組的酬載加上訊框開銷）
+const MAX_BE_SIZE: f64 = 1500.0 + FRAME_OVERHEAD as f64;
+/// 啟用訊框搶佔時，可被搶佔的訊框最多還會阻擋快速訊框這麼多位元組。
+///
+/// 片段至少要 64 位元組，剩餘不足 64 位元組者也不能再切，故最壞情形是 127 位元組。
+const MAX_FRAGMENT_SIZE: f64 = 127.0;
+
+/// 快速訊框（TT 或 A 類 AVB）在單一連結上，最多會被正在傳輸的低優先度訊框阻擋多久
+/// * `bandwidth` - 該連結的頻寬
+/// * `preemption` - 是否啟用訊框搶佔
+fn max_blocking_time(bandwidth: f64, preemption: bool) -> f64 {
+    if preemption {
+        MAX_FRAGMENT_SIZE / bandwidth
+    } else {
+        MAX_BE_SIZE / bandwidth
+    }
+}
+
+/// 每個連結上 TT 時段前的保護帶長度，依連結編號排列
+///
+/// 保護帶恰好讓正在傳輸的低優先度訊框傳完（見 `max_blocking_time`），故啟用訊框搶佔時只需一個片段的長度
+pub fn get_guard_bands(graph: &StreamAwareGraph, preemption: bool) -> Vec<u32> {
+    let mut guard_bands = vec![0; graph.get_port_configs().len()];
+    for (link_id, _, bandwidth) in graph.get_links().into_iter() {
+        guard_bands[link_id] = max_blocking_time(bandwidth, preemption).ceil() as u32;
+    }
+    guard_bands
+}
+
+/// TT 資料流在路徑上要預留的頻寬：每個週期在線路上佔用的位元組（含每個訊框的開銷）除以週期
+pub fn get_tt_load(flow: &TSNFlow, links: &Vec<(usize, f64)>, gcl: &GCL) -> f64 {
+    let wire_size: usize = get_flow_frames(flow, links, gcl).iter().sum();
+    wire_size as f64 / flow.period as f64
+}
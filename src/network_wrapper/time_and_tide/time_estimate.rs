@@ -1,11 +1,50 @@
-use crate::flow::{AVBFlow, FlowID};
-use crate::graph_util::MemorizingGraph;
+use super::{max_blocking_time, MAX_AVB_SETTING};
+use crate::config::Config;
+use crate::flow::{data::AVBClass, AVBFlow, FlowID};
+use crate::graph_util::{MemorizingGraph, PortConfig};
 use crate::recorder::{flow_table::prelude::*, GCL};
+use serde::Serialize;
 
-/// AVB 資料流最多可以佔用的資源百分比（模擬 Credit Base Shaper 的效果）
-const MAX_AVB_SETTING: f64 = 0.75;
-/// BE 資料流最多可以多大
-const MAX_BE_SIZE: f64 = 1500.0;
+/// AVB 資料流在單一連結上的延遲組成，單位皆為微秒
+#[derive(Clone, Debug, Serialize)]
+pub struct LinkLatency {
+    pub link_id: usize,
+    /// 該連結在起點上的埠口，如 `switch-3/eth2`
+    pub port: String,
+    /// 資料流本身的傳輸時間（受 Credit Base Shaper 限制）
+    pub transmission: f64,
+    /// 被正在傳輸的低優先度訊框阻擋的時間
+    pub be_blocking: f64,
+    /// 同級或更高級的 AVB 資料流造成的干擾
+    pub avb_interference: f64,
+    /// 造成上述干擾的 AVB 資料流
+    pub interfering_flows: Vec<FlowID>,
+    /// TT 閘門關閉造成的干擾
+    pub tt_interference: f64,
+}
+impl LinkLatency {
+    /// 不計 TT 干擾的延遲
+    fn wcd_without_tt(&self) -> f64 {
+        self.be_blocking + self.transmission + self.avb_interference
+    }
+    pub fn total(&self) -> f64 {
+        self.wcd_without_tt() + self.tt_interference
+    }
+}
+impl std::fmt::Display for LinkLatency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: trans = {:.2}, be = {:.2}, avb = {:.2} by {:?}, tt = {:.2}",
+            self.port,
+            self.transmission,
+            self.be_blocking,
+            self.avb_interference,
+            self.interfering_flows,
+            self.tt_interference
+        )
+    }
+}
 
 /// 計算 AVB 資料流的端對端延遲（包含 TT、BE 及其它 AVB 所造成的延遲）
 /// * `g` - 全局網路拓撲，每條邊上記錄其承載哪些資料流
@@ -21,41 +60,122 @@ pub fn compute_avb_latency<T: Clone + Eq>(
     flow_table: &FlowTable<T>,
     gcl: &GCL,
 ) -> u32 {
-    let overlap_flow_id = g.get_overlap_flows(route);
     let mut end_to_end_lanency = 0.0;
-    for (i, (link_id, bandwidth)) in g.get_links_id_bandwidth(route).into_iter().enumerate() {
-        let wcd = wcd_on_single_link(flow, bandwidth, flow_table, &overlap_flow_id[i]);
-        end_to_end_lanency += wcd + tt_interfere_avb_single_link(link_id, wcd as f64, gcl) as f64;
+    for link_latency in latency_per_link(g, flow, route, flow_table, gcl).iter() {
+        end_to_end_lanency += link_latency.total();
     }
     end_to_end_lanency as u32
 }
+/// 同 `compute_avb_latency`，但回傳路徑上每個連結的延遲組成，用來解釋延遲從何而來
+pub fn compute_avb_latency_breakdown<T: Clone + Eq>(
+    g: &MemorizingGraph,
+    flow: &AVBFlow,
+    route: &Vec<usize>,
+    flow_table: &FlowTable<T>,
+    gcl: &GCL,
+) -> Vec<LinkLatency> {
+    let mut breakdown = latency_per_link(g, flow, route, flow_table, gcl);
+    for link_latency in breakdown.iter_mut() {
+        link_latency.interfering_flows.sort();
+        link_latency.port = g.get_link_name(link_latency.link_id);
+    }
+    breakdown
+}
+fn latency_per_link<T: Clone + Eq>(
+    g: &MemorizingGraph,
+    flow: &AVBFlow,
+    route: &Vec<usize>,
+    flow_table: &FlowTable<T>,
+    gcl: &GCL,
+) -> Vec<LinkLatency> {
+    let overlap_flow_id = g.get_overlap_flows(route);
+    let preemption = Config::get().preemption;
+    let mut breakdown = vec![];
+    for (i, (link_id, bandwidth)) in g.get_links_id_bandwidth(route).into_iter().enumerate() {
+        let mut link_latency = latency_on_single_link(
+            flow,
+            bandwidth,
+            flow_table,
+            &overlap_flow_id[i],
+            preemption,
+            gcl.get_port_config(link_id),
+        );
+        link_latency.link_id = link_id;
+        let wcd = link_latency.wcd_without_tt();
+        link_latency.tt_interference = tt_interfere_avb_single_link(link_id, wcd, gcl) as f64;
+        breakdown.push(link_latency);
+    }
+    breakdown
+}
+#[cfg(test)]
 fn wcd_on_single_link<T: Clone + Eq>(
     flow: &AVBFlow,
     bandwidth: f64,
     flow_table: &FlowTable<T>,
     overlap_flow_id: &Vec<FlowID>,
+    preemption: bool,
 ) -> f64 {
-    let mut wcd = 0.0;
+    latency_on_single_link(
+        flow,
+        bandwidth,
+        flow_table,
+        overlap_flow_id,
+        preemption,
+        &PortConfig::default(),
+    )
+    .wcd_without_tt()
+}
+/// 該等級的 AVB 資料流在這個埠口上使用的佇列
+fn avb_queue(avb_class: AVBClass, port: &PortConfig) -> u8 {
+    if avb_class.is_class_a() {
+        port.avb_a_queue
+    } else {
+        port.avb_b_queue
+    }
+}
+/// 計算 TT 以外的延遲，回傳值中的 `link_id`、`port` 及 `tt_interference` 尚未填入
+fn latency_on_single_link<T: Clone + Eq>(
+    flow: &AVBFlow,
+    bandwidth: f64,
+    flow_table: &FlowTable<T>,
+    overlap_flow_id: &[FlowID],
+    preemption: bool,
+    port: &PortConfig,
+) -> LinkLatency {
     // MAX None AVB
-    wcd += MAX_BE_SIZE / bandwidth;
+    // NOTE: 啟用搶佔時 A 類是快速訊框，只會被一個片段阻擋；B 類本身可被搶佔，仍要等完整的 BE 訊框
+    let express = preemption && flow.spec_data.avb_class.is_class_a();
+    let be_blocking = max_blocking_time(bandwidth, express);
     // AVB 資料流最多只能佔用這樣的頻寬
     let bandwidth = MAX_AVB_SETTING * bandwidth;
     // On link
-    wcd += flow.size as f64 / bandwidth;
+    let transmission = flow.size as f64 / bandwidth;
     // Ohter AVB
+    let mut avb_interference = 0.0;
+    let mut interfering_flows = vec![];
     for &other_flow_id in overlap_flow_id.iter() {
         if other_flow_id != flow.id {
             let other_flow = flow_table.get_avb(other_flow_id).unwrap();
-            // 自己是 B 類或別人是 A 類，就有機會要等……換句話說，只有自己是 A 而別人是 B 不用等
-            let self_type = flow.spec_data.avb_class;
-            let other_type = other_flow.spec_data.avb_class;
-            if self_type.is_class_b() || other_type.is_class_a() {
-                wcd += other_flow.size as f64 / bandwidth;
+            // 別人的佇列優先度不低於自己的，就有機會要等……預設的對應下，只有自己是 A 而別人是 B 不用等
+            let self_queue = avb_queue(flow.spec_data.avb_class, port);
+            let other_queue = avb_queue(other_flow.spec_data.avb_class, port);
+            if other_queue >= self_queue {
+                avb_interference += other_flow.size as f64 / bandwidth;
+                interfering_flows.push(other_flow_id);
             }
         }
     }
-    wcd
+    LinkLatency {
+        link_id: 0,
+        port: String::new(),
+        transmission,
+        be_blocking,
+        avb_interference,
+        interfering_flows,
+        tt_interference: 0.0,
+    }
 }
+/// TT 閘門關閉（包含其前的保護帶）對 AVB 造成的最大干擾
 fn tt_interfere_avb_single_link(link_id: usize, wcd: f64, gcl: &GCL) -> u32 {
     let mut i_max = 0;
     let all_gce = gcl.get_gate_events(link_id);
@@ -79,6 +199,7 @@ fn tt_interfere_avb_single_link(link_id: usize, wcd: f64, gcl: &GCL) -> u32 {
 
 #[cfg(test)]
 mod test {
+    use super::super::{MAX_BE_SIZE, MAX_FRAGMENT_SIZE};
     use super::*;
     use crate::graph_util::*;
 
@@ -141,7 +262,8 @@ mod test {
                 route_table.get_avb(0.into()).unwrap(),
                 100.0,
                 &route_table,
-                &build_flowid_vec(vec![0, 2])
+                &build_flowid_vec(vec![0, 2]),
+                false
             ),
             (MAX_BE_SIZE / 100.0 + 1.0)
         );
@@ -150,7 +272,8 @@ mod test {
                 route_table.get_avb(0.into()).unwrap(),
                 100.0,
                 &route_table,
-                &build_flowid_vec(vec![1, 0, 2])
+                &build_flowid_vec(vec![1, 0, 2]),
+                false
             ),
             (MAX_BE_SIZE / 100.0 + 1.0 + 2.0)
         );
@@ -159,7 +282,8 @@ mod test {
                 route_table.get_avb(1.into()).unwrap(),
                 100.0,
                 &route_table,
-                &build_flowid_vec(vec![1, 0, 2])
+                &build_flowid_vec(vec![1, 0, 2]),
+                false
             ),
             (MAX_BE_SIZE / 100.0 + 1.0 + 2.0)
         );
@@ -169,7 +293,63 @@ mod test {
                 route_table.get_avb(2.into()).unwrap(),
                 100.0,
                 &route_table,
-                &build_flowid_vec(vec![1, 0, 2])
+                &build_flowid_vec(vec![1, 0, 2]),
+                false
+            ),
+            (MAX_BE_SIZE / 100.0 + 1.0 + 2.0 + 1.0)
+        );
+    }
+    #[test]
+    fn test_single_link_avb_queue_mapping() {
+        let (_, flows, mut route_table, _) = init_settings();
+        route_table.insert(vec![], flows, 0);
+        let single_link = |id: usize, port: &PortConfig| {
+            latency_on_single_link(
+                route_table.get_avb(id.into()).unwrap(),
+                100.0,
+                &route_table,
+                &build_flowid_vec(vec![1, 0, 2]),
+                false,
+                port,
+            )
+            .interfering_flows
+        };
+        // A、B 兩類共用同一個佇列，互相干擾
+        let mut port = PortConfig::with_queue_cnt(4).unwrap();
+        port.avb_b_queue = port.avb_a_queue;
+        assert_eq!(build_flowid_vec(vec![1, 2]), single_link(0, &port));
+        // B 類的優先度比 A 類高，就換 A 類要等 B 類
+        let mut port = PortConfig::with_queue_cnt(4).unwrap();
+        port.avb_a_queue = 1;
+        port.avb_b_queue = 2;
+        assert_eq!(build_flowid_vec(vec![1, 2]), single_link(0, &port));
+        assert_eq!(Vec::<FlowID>::new(), single_link(2, &port));
+    }
+    #[test]
+    fn test_single_link_avb_with_preemption() {
+        let (_, flows, mut route_table, _) = init_settings();
+
+        route_table.insert(vec![], flows, 0);
+
+        // A 類是快速訊框，只會被一個片段阻擋
+        assert_eq!(
+            wcd_on_single_link(
+                route_table.get_avb(0.into()).unwrap(),
+                100.0,
+                &route_table,
+                &build_flowid_vec(vec![1, 0, 2]),
+                true
+            ),
+            (MAX_FRAGMENT_SIZE / 100.0 + 1.0 + 2.0)
+        );
+        // B 類可被搶佔，仍要等完整的 BE 訊框
+        assert_eq!(
+            wcd_on_single_link(
+                route_table.get_avb(2.into()).unwrap(),
+                100.0,
+                &route_table,
+                &build_flowid_vec(vec![1, 0, 2]),
+                true
             ),
             (MAX_BE_SIZE / 100.0 + 1.0 + 2.0 + 1.0)
         );
@@ -192,6 +372,57 @@ mod test {
         );
     }
     #[test]
+    fn test_avb_latency_breakdown() {
+        let (mut g, flows, mut flow_table, mut gcl) = init_settings();
+        flow_table.insert(vec![], flows, 0);
+        for i in 0..3 {
+            g.update_flowid_on_route(true, i.into(), &vec![0, 1, 2]);
+        }
+        gcl.insert_gate_evt(2, 99.into(), 0, 100, 100);
+
+        let breakdown = compute_avb_latency_breakdown(
+            &g,
+            flow_table.get_avb(0.into()).unwrap(),
+            &vec![0, 1, 2],
+            &flow_table,
+            &gcl,
+        );
+        assert_eq!(2, breakdown.len());
+        assert_eq!(0, breakdown[0].link_id);
+        assert_eq!(2, breakdown[1].link_id);
+        assert_eq!("host-1/eth1", breakdown[1].port);
+        assert_eq!(MAX_BE_SIZE / 100.0, breakdown[0].be_blocking);
+        assert_eq!(1.0, breakdown[0].transmission);
+        // 只有同為 A 類的資料流 1 會造成干擾
+        assert_eq!(2.0, breakdown[0].avb_interference);
+        assert_eq!(build_flowid_vec(vec![1]), breakdown[0].interfering_flows);
+        assert_eq!(0.0, breakdown[0].tt_interference);
+        assert_eq!(100.0, breakdown[1].tt_interference);
+
+        let sum: f64 = breakdown.iter().map(|l| l.total()).sum();
+        assert_eq!(
+            compute_avb_latency(
+                &g,
+                flow_table.get_avb(0.into()).unwrap(),
+                &vec![0, 1, 2],
+                &flow_table,
+                &gcl
+            ),
+            sum as u32
+        );
+    }
+    #[test]
+    fn test_tt_interference_with_guard_band() {
+        let mut gcl = GCL::new(1000, 1);
+        gcl.insert_gate_evt(0, 98.into(), 3, 100, 100);
+        gcl.insert_gate_evt(0, 99.into(), 3, 215, 10);
+        // 兩個時段間的空檔夠大，AVB 只會被一個時段擋住
+        assert_eq!(100, tt_interfere_avb_single_link(0, 5.0, &gcl));
+        // 加上保護帶後空檔被填滿，兩個時段連成一片
+        gcl.set_guard_bands(vec![15]);
+        assert_eq!(140, tt_interfere_avb_single_link(0, 5.0, &gcl));
+    }
+    #[test]
     fn test_endtoend_avb_with_gcl() {
         // 其實已經接近整合測試了 @@
         let (mut g, flows, mut flow_table, mut gcl) = init_settings();
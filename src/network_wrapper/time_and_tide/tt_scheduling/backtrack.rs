@@ -0,0 +1,368 @@
+use super::{fit_gcl_len, get_blocking_time, get_flow_frames, get_trans_time, insert_flow};
+use super::{get_release_candidates, miss_deadline, sort_flows};
+use super::{FailReason, Links, ScheduleError};
+use crate::config::Config;
+use crate::flow::{FlowID, TSNFlow};
+use crate::recorder::{flow_table::prelude::*, GCL};
+
+/// 回溯搜索最多嘗試幾個候選時間點，超過即視為排程失敗
+const MAX_SEARCH_NODE: usize = 200_000;
+
+/// 以回溯搜索排程 TT 資料流，可取代 `schedule_fixed_og`
+///
+/// 資料流的順序與貪婪算法相同（見 `Config::tt_order`），但某個資料流排不進去時，會回頭改動先前資料流的封包時間與佇列。
+/// 佇列只考慮保留給 TT 且已被使用的佇列，加上一個全新的佇列（全新的佇列之間沒有差別）。
+/// 時間則分兩輪搜索：
+/// 1. 每個封包在每個連結上只考慮「左對齊」的候選時間：
+///    最早可以傳的時間，以及該連結上閘門事件（或下個埠口所選佇列的佔用事件）結束的時間點。
+/// 2. 第一輪試完仍失敗且還沒達到搜索上限的話，改試下界之後的每個整數時間點（微秒），
+///    如緊接在某個閘門時段之前傳、讓閘門控制清單合併的時間點。
+///
+/// 時間都是整數，故第二輪試完所有候選仍失敗，就代表在這些路徑下沒有可行解；
+/// 但兩輪共用同一個搜索上限，大的實例可能在找到解之前就放棄。
+///
+/// 每個候選時間都會先往前檢查（forward checking）：剩下的連結與封包至少要花的時間若已趕不上死線，
+/// 或下個埠口所選的佇列在那時被佔住，就直接剪掉。排完一個資料流後，若有埠口的閘門控制清單超過 `max_gcl_len`，也會回頭。
+///
+/// 失敗時回報搜索過程中排得最遠的資料流，以及它卡住的連結；
+/// 兩輪都試完所有候選為 `SearchExhausted`，達到搜索上限（`MAX_SEARCH_NODE`）則為 `BudgetExhausted`。
+pub fn schedule_backtrack<
+    T: Eq + Clone,
+    TABLE: IFlowTable<INFO = T>,
+    F: Fn(&TSNFlow, &T) -> Links,
+>(
+    table: &TABLE,
+    gcl: &mut GCL,
+    get_links: F,
+) -> Result<(), ScheduleError> {
+    schedule_backtrack_with_budget(table, gcl, get_links, MAX_SEARCH_NODE)
+}
+
+/// 同 `schedule_backtrack`，但最多只嘗試 `budget` 個候選時間點
+pub(super) fn schedule_backtrack_with_budget<
+    T: Eq + Clone,
+    TABLE: IFlowTable<INFO = T>,
+    F: Fn(&TSNFlow, &T) -> Links,
+>(
+    table: &TABLE,
+    gcl: &mut GCL,
+    get_links: F,
+    budget: usize,
+) -> Result<(), ScheduleError> {
+    let mut tsn_ids = Vec::<FlowID>::new();
+    for (flow, _) in table.iter_tsn() {
+        tsn_ids.push(flow.id);
+    }
+    sort_flows(&mut tsn_ids, Config::get().tt_order, table, &get_links);
+    let mut flows = Vec::<(&TSNFlow, Links, Vec<usize>)>::with_capacity(tsn_ids.len());
+    for flow_id in tsn_ids.into_iter() {
+        let flow = table.get_tsn(flow_id).unwrap();
+        if !gcl.get_hyper_p().is_multiple_of(flow.period) {
+            // 超週期延長失敗，這個資料流不可能在每個超週期裡都排得一樣
+            return Err(ScheduleError::new(flow_id, None, FailReason::HyperPeriod));
+        }
+        let links = get_links(flow, table.get_info(flow_id).unwrap());
+        let frames = get_flow_frames(flow, &links, gcl);
+        flows.push((flow, links, frames));
+    }
+    let mut search = Search {
+        flows: &flows,
+        gcl,
+        preemption: Config::get().preemption,
+        node_cnt: 0,
+        budget,
+        deepest: (0, 0),
+        exhaustive: false,
+    };
+    let mut found = search.place_flow(0);
+    if !found && search.node_cnt <= search.budget {
+        search.exhaustive = true;
+        found = search.place_flow(0);
+    }
+    if found {
+        Ok(())
+    } else {
+        let (j, i) = search.deepest;
+        let (flow, links, _) = &flows[j];
+        let link_id = links[std::cmp::min(i, links.len() - 1)].0;
+        let reason = if search.node_cnt > search.budget {
+            FailReason::BudgetExhausted
+        } else {
+            FailReason::SearchExhausted
+        };
+        Err(ScheduleError::new(flow.id, Some(link_id), reason))
+    }
+}
+
+struct Search<'a, 'b> {
+    /// 依排程順序排列的 (資料流, 路徑上的連結, 每個訊框在線路上的大小)
+    flows: &'a Vec<(&'a TSNFlow, Links, Vec<usize>)>,
+    gcl: &'b mut GCL,
+    preemption: bool,
+    node_cnt: usize,
+    /// 最多嘗試幾個候選時間點
+    budget: usize,
+    /// 搜索過程中排得最遠的位置：(第幾個資料流, 第幾個連結)
+    deepest: (usize, usize),
+    /// 是否試下界之後的每個整數時間點，而非只試左對齊的時間點
+    exhaustive: bool,
+}
+
+/// 決定某個封包在某個連結上的傳輸時間時，會用到的資訊
+struct Hop<'c> {
+    flow: &'c TSNFlow,
+    links: &'c Links,
+    frames: &'c Vec<usize>,
+    /// 第幾個封包
+    m: usize,
+    /// 第幾個連結
+    i: usize,
+    /// 這個埠口所選的佇列
+    queue: u8,
+    /// 下個埠口所選的佇列，最後一個連結則為 `None`
+    next_queue: Option<u8>,
+    trans_time: u32,
+    blocking_time: u32,
+    /// 封包在前一個連結上的傳輸時間，第一個連結則為 `None`
+    prev_offset: Option<u32>,
+}
+
+impl<'a, 'b> Search<'a, 'b> {
+    /// 排第 j 個資料流，成功的話會一路排到最後一個資料流
+    fn place_flow(&mut self, j: usize) -> bool {
+        if j == self.flows.len() {
+            return true;
+        }
+        let (_, links, frames) = &self.flows[j];
+        let mut offsets = vec![vec![0; links.len()]; frames.len()];
+        let mut ro: Vec<u8> = links
+            .iter()
+            .map(|&(link_id, _)| self.gcl.get_port_config(link_id).tt_queues[0])
+            .collect();
+        self.place_hop(j, 0, 0, &mut offsets, &mut ro)
+    }
+    /// 決定第 j 個資料流的第 m 個封包在第 i 個連結上的傳輸時間
+    fn place_hop(
+        &mut self,
+        j: usize,
+        m: usize,
+        i: usize,
+        offsets: &mut Vec<Vec<u32>>,
+        ro: &mut Vec<u8>,
+    ) -> bool {
+        let (flow, links, _) = &self.flows[j];
+        if m == offsets.len() {
+            // 整個資料流都排好了，塞進 GCL 再往下一個資料流；閘門控制清單放不下的話也要回頭
+            if insert_flow(flow, links, ro, offsets, self.gcl, self.preemption)
+                .and_then(|_| fit_gcl_len(flow, links, self.gcl))
+                .is_err()
+            {
+                return false;
+            }
+            if self.place_flow(j + 1) {
+                return true;
+            }
+            let link_ids = links.iter().map(|(id, _)| *id).collect();
+            self.gcl.delete_flow(&link_ids, flow.id);
+            return false;
+        }
+        if i == links.len() {
+            return self.place_hop(j, m + 1, 0, offsets, ro);
+        }
+        if m == 0 && i + 1 < links.len() {
+            // NOTE 一個資料流的每個封包，在單一埠口上必需採用同一個佇列，故只在第一個封包決定
+            let next_link = links[i + 1].0;
+            let mut fresh_tried = false;
+            let tt_queues = self.gcl.get_port_config(next_link).tt_queues.clone();
+            for queue_id in tt_queues.into_iter() {
+                if !self.gcl.is_queue_in_use(next_link, queue_id) {
+                    if fresh_tried {
+                        continue;
+                    }
+                    fresh_tried = true;
+                }
+                ro[i + 1] = queue_id;
+                if self.place_time(j, m, i, offsets, ro) {
+                    return true;
+                }
+            }
+            false
+        } else {
+            self.place_time(j, m, i, offsets, ro)
+        }
+    }
+    fn place_time(
+        &mut self,
+        j: usize,
+        m: usize,
+        i: usize,
+        offsets: &mut Vec<Vec<u32>>,
+        ro: &mut Vec<u8>,
+    ) -> bool {
+        let (flow, links, frames) = &self.flows[j];
+        self.deepest = std::cmp::max(self.deepest, (j, i));
+        let hop = Hop {
+            flow,
+            links,
+            frames,
+            m,
+            i,
+            queue: ro[i],
+            next_queue: ro.get(i + 1).copied(),
+            trans_time: get_trans_time(frames[m], links[i].1),
+            blocking_time: get_blocking_time(&links[i], self.gcl, self.preemption),
+            prev_offset: if i == 0 {
+                None
+            } else {
+                Some(offsets[m][i - 1])
+            },
+        };
+        // 與 calculate_offsets 相同的下界
+        let mut lower_bound = if i == 0 {
+            flow.spec_data.offset
+        } else {
+            offsets[m][i - 1]
+                + get_trans_time(frames[m], links[i - 1].1)
+                + get_blocking_time(&links[i - 1], self.gcl, self.preemption)
+        };
+        if m > 0 {
+            let prev_trans_time = get_trans_time(frames[m - 1], links[i].1);
+            lower_bound = std::cmp::max(lower_bound, offsets[m - 1][i] + prev_trans_time);
+        }
+        lower_bound = self.wait_for_next_queue(&hop, lower_bound);
+        let remaining_time = self.get_remaining_time(&hop);
+        for time in self.candidate_times(&hop, lower_bound) {
+            self.node_cnt += 1;
+            if self.node_cnt > self.budget {
+                return false;
+            }
+            // 第一個封包在第一個連結上的傳輸時間決定了釋出時間
+            let first_offset = if m == 0 && i == 0 {
+                time
+            } else {
+                offsets[0][0]
+            };
+            // 往前檢查：剩下的連結與封包都不受阻擋也趕不上死線，更晚的時間點也不可能
+            if miss_deadline(time, remaining_time, first_offset, flow) {
+                break;
+            }
+            if !self.is_feasible(&hop, time) {
+                continue;
+            }
+            offsets[m][i] = time;
+            if self.place_hop(j, m, i + 1, offsets, ro) {
+                return true;
+            }
+        }
+        false
+    }
+    /// 下個埠口所選的佇列在封包抵達時必需是空的，在第一個週期中等到它空出來，得到更緊的下界
+    fn wait_for_next_queue(&self, hop: &Hop, lower_bound: u32) -> u32 {
+        let queue_id = match hop.next_queue {
+            Some(queue_id) => queue_id,
+            None => return lower_bound,
+        };
+        let next_link = hop.links[hop.i + 1].0;
+        let delay = hop.trans_time + hop.blocking_time;
+        let mut arrive_time = lower_bound + delay;
+        while let Some(time) = self
+            .gcl
+            .get_next_queue_empty_time(next_link, queue_id, arrive_time)
+        {
+            arrive_time = time;
+        }
+        arrive_time - delay
+    }
+    /// 從這個封包在這個連結上開始傳輸，到最後一個封包在最後一個連結上傳完（含阻擋時間），至少要多久
+    ///
+    /// 這個封包要一路傳到最後一個連結，其後的封包也都要在最後一個連結上傳完
+    fn get_remaining_time(&self, hop: &Hop) -> u32 {
+        let last = hop.links.len() - 1;
+        let mut remaining_time = 0;
+        for link in hop.links[hop.i..].iter() {
+            remaining_time += get_trans_time(hop.frames[hop.m], link.1)
+                + get_blocking_time(link, self.gcl, self.preemption);
+        }
+        for &frame in hop.frames[hop.m + 1..].iter() {
+            remaining_time += get_trans_time(frame, hop.links[last].1);
+        }
+        remaining_time
+    }
+    /// 下界，以及下界之後所有閘門事件與佇列佔用事件結束的時間點（已換算到第一個週期），由小到大排列
+    ///
+    /// 第二輪搜索則是下界之後的每個整數時間點，直到最晚的死線，或剩下的搜索額度用完
+    fn candidate_times(&self, hop: &Hop, lower_bound: u32) -> Vec<u32> {
+        let flow = hop.flow;
+        if self.exhaustive {
+            let deadline = flow
+                .spec_data
+                .get_latest_offset()
+                .saturating_add(flow.max_delay);
+            let quota = self.budget.saturating_sub(self.node_cnt) as u32 + 1;
+            let upper = std::cmp::min(deadline, lower_bound.saturating_add(quota));
+            return (lower_bound..upper).collect();
+        }
+        let mut ends: Vec<u32> = self
+            .gcl
+            .get_gate_events(hop.links[hop.i].0)
+            .iter()
+            .map(|&(start, duration)| start + duration)
+            .collect();
+        if let Some(queue_id) = hop.next_queue {
+            for (start, duration) in self.gcl.get_queue_events(hop.links[hop.i + 1].0, queue_id) {
+                ends.push(start + duration);
+            }
+        }
+        let mut times = vec![lower_bound];
+        if hop.i == 0 && hop.m == 0 {
+            // 第一個封包的第一跳決定了釋出時間，釋出窗口中值得一試的時間點都要考慮
+            for time in
+                get_release_candidates(flow, hop.links, hop.frames, self.gcl, self.preemption)
+            {
+                if time >= lower_bound {
+                    times.push(time);
+                }
+            }
+        }
+        for end in ends.into_iter() {
+            let time = end % flow.period;
+            if time > lower_bound {
+                times.push(time);
+            }
+        }
+        times.sort();
+        times.dedup();
+        times
+    }
+    /// 在超週期中的每個週期，這個時間點都能傳，下個埠口所選的佇列是空的，
+    /// 且封包從前一個埠口開始傳輸（`prev_offset`）以來，這個埠口所選的佇列沒有其它資料流
+    fn is_feasible(&self, hop: &Hop, time: u32) -> bool {
+        let link_id = hop.links[hop.i].0;
+        for time_shift in (0..self.gcl.get_hyper_p()).step_by(hop.flow.period as usize) {
+            if self
+                .gcl
+                .get_next_empty_time(link_id, time_shift + time, hop.trans_time)
+                .is_some()
+            {
+                return false;
+            }
+            if let Some(queue_id) = hop.next_queue {
+                let arrive_time = time_shift + time + hop.trans_time + hop.blocking_time;
+                if self
+                    .gcl
+                    .get_next_queue_empty_time(hop.links[hop.i + 1].0, queue_id, arrive_time)
+                    .is_some()
+                {
+                    return false;
+                }
+            }
+            if let Some(prev_offset) = hop.prev_offset {
+                let (start, end) = (time_shift + prev_offset, time_shift + time);
+                if !self.gcl.is_queue_free(link_id, hop.queue, start, end) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
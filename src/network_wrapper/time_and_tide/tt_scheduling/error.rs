@@ -0,0 +1,39 @@
+use crate::flow::FlowID;
+
+/// TT 資料流排不進去的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailReason {
+    /// 超週期延長失敗，這個資料流不可能在每個超週期裡都排得一樣
+    HyperPeriod,
+    /// 在這個連結上等到有空檔時，已經趕不上死線
+    DeadlineMiss,
+    /// 下個埠口上保留給 TT 的佇列都被其它資料流佔住了
+    NoFreeQueue,
+    /// 排得進去，但端對端抖動超過 `max_jitter`
+    JitterExceeded,
+    /// 回溯搜索試完所有時間點仍排不進去，在這些路徑下沒有可行解
+    SearchExhausted,
+    /// 回溯搜索達到搜索上限，還有候選沒試過
+    BudgetExhausted,
+    /// 排得進去，但某個埠口壓縮後的閘門控制清單超過交換機的上限（`max_gcl_len`）
+    GclOverflow,
+}
+
+/// 排程失敗時，是哪個資料流在哪個連結上失敗，以及失敗的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScheduleError {
+    pub flow_id: FlowID,
+    /// 失敗的連結，與特定連結無關的失敗（如超週期）則為 `None`
+    pub link_id: Option<usize>,
+    pub reason: FailReason,
+}
+
+impl ScheduleError {
+    pub fn new(flow_id: FlowID, link_id: Option<usize>, reason: FailReason) -> Self {
+        ScheduleError {
+            flow_id,
+            link_id,
+            reason,
+        }
+    }
+}
@@ -1,78 +1,205 @@
+use super::{max_blocking_time, FRAME_OVERHEAD, MIN_PAYLOAD};
+use crate::config::{Config, TTOrder, TTScheduler};
 use crate::flow::{FlowID, TSNFlow};
+use crate::graph_util::DEFAULT_MTU;
 use crate::recorder::{flow_table::prelude::*, GCL};
-use crate::MAX_QUEUE;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
 type FT<T> = FlowTable<T>;
 type DT<T> = DiffFlowTable<T>;
 type Links = Vec<(usize, f64)>;
 
-const MTU: usize = 1500;
+mod backtrack;
+use backtrack::schedule_backtrack;
+#[cfg(test)]
+use backtrack::schedule_backtrack_with_budget;
+mod error;
+pub use error::{FailReason, ScheduleError};
+mod order;
+#[cfg(test)]
+use order::cmp_flow;
+use order::sort_flows;
 
-/// 一個大小為 size 的資料流要切成幾個封包才夠？
-#[inline(always)]
-fn get_frame_cnt(size: usize) -> usize {
-    if size % MTU == 0 {
-        size / MTU
-    } else {
-        size / MTU + 1
+/// 把大小為 size 的資料流切成數個酬載不超過 `mtu` 的訊框，回傳每個訊框在線路上佔用幾個位元組
+///
+/// 除了最後一個訊框外，酬載皆為 `mtu`；最後一個訊框只裝剩下的部份，但不能小於 `MIN_PAYLOAD`。
+pub fn get_frame_sizes(size: usize, mtu: usize) -> Vec<usize> {
+    let mut frames = vec![mtu + FRAME_OVERHEAD; size / mtu];
+    if !size.is_multiple_of(mtu) {
+        frames.push(std::cmp::max(size % mtu, MIN_PAYLOAD) + FRAME_OVERHEAD);
     }
+    frames
 }
-
-use std::cmp::Ordering;
-/// 排序的標準：
-/// * `deadline` - 時間較緊的要排前面
-/// * `period` - 週期短的要排前面
-/// * `route length` - 路徑長的要排前面
-fn cmp_flow<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
-    id1: FlowID,
-    id2: FlowID,
-    table: &TABLE,
-    get_links: F,
-) -> Ordering {
-    let flow1 = table.get_tsn(id1).unwrap();
-    let flow2 = table.get_tsn(id2).unwrap();
-    if flow1.max_delay < flow2.max_delay {
-        Ordering::Less
-    } else if flow1.max_delay > flow2.max_delay {
-        Ordering::Greater
-    } else {
-        if flow1.period < flow2.period {
-            Ordering::Less
-        } else if flow1.period > flow2.period {
-            Ordering::Greater
-        } else {
-            let k = table.get_info(flow1.id).unwrap();
-            let rlen_1 = get_links(&flow1, k).len();
-            let k = table.get_info(flow2.id).unwrap();
-            let rlen_2 = get_links(&flow2, k).len();
-            if rlen_1 > rlen_2 {
-                Ordering::Less
-            } else {
-                Ordering::Greater
-            }
-        }
-    }
+/// 資料流在這條路徑上的訊框：一路上都不能切割，故以路徑上最小的 MTU 來切
+pub(super) fn get_flow_frames(flow: &TSNFlow, links: &Links, gcl: &GCL) -> Vec<usize> {
+    let mtu = links
+        .iter()
+        .map(|&(link_id, _)| gcl.get_port_config(link_id).mtu)
+        .min()
+        .unwrap_or(DEFAULT_MTU);
+    get_frame_sizes(flow.size, mtu)
+}
+/// 在線路上佔用 `wire_size` 個位元組的訊框，傳輸要花多久
+#[inline(always)]
+fn get_trans_time(wire_size: usize, bandwidth: f64) -> u32 {
+    (wire_size as f64 / bandwidth).ceil() as u32
 }
 
 /// 動態計算 TT 資料流的 Gate Control List
 /// * `og_table` - 本來的資料流表（排程之後，TT部份會與 changed_table 合併）
 /// * `changed_table` - 被改動到的那部份資料流，包含新增與換路徑
 /// * `gcl` - 本來的 Gate Control List
-/// * 回傳 - Ok(false) 代表沒事發生，Ok(true) 代表發生大洗牌
+/// * 回傳 - 排程被迫改動的舊資料流（不包含 changed_table 中的資料流），依編號排序；
+///   失敗時回傳最後一次重排中，是哪個資料流在哪裡失敗
+///
+/// 若無法在不動到舊資料流的情況下排進去，會先拔掉與改動資料流共用連結的舊資料流，跟改動資料流一起重排；
+/// 再失敗才清空 GCL，所有資料流一起重排（大洗牌）。大洗牌也失敗時，GCL 還原為只有舊資料流本來的排程。
 pub fn schedule_online<T: Eq + Clone, F: Fn(&TSNFlow, &T) -> Links>(
     og_table: &mut FT<T>,
     changed_table: &DT<T>,
     gcl: &mut GCL,
     get_links: F,
-) -> Result<bool, ()> {
-    let result = schedule_fixed_og(changed_table, gcl, &get_links);
+) -> Result<Vec<FlowID>, ScheduleError> {
+    let result = schedule_offline(changed_table, gcl, &get_links);
     og_table.apply_diff(true, changed_table);
-    if !result.is_ok() {
+    if result.is_ok() {
+        return Ok(vec![]);
+    }
+    // 記下舊資料流本來的排程，之後才知道誰被改動了
+    let mut og_gcl = gcl.clone();
+    let mut og_schedules = HashMap::<FlowID, Vec<Vec<(u32, u32)>>>::new();
+    for (flow, info) in og_table.iter_tsn() {
+        let links = get_links(flow, info);
+        if changed_table.check_exist(flow.id) {
+            let link_ids = links.iter().map(|(id, _)| *id).collect();
+            og_gcl.delete_flow(&link_ids, flow.id);
+        } else {
+            og_schedules.insert(flow.id, get_flow_schedule(flow.id, &links, gcl));
+        }
+    }
+    if reschedule_conflicts(og_table, changed_table, gcl, &get_links).is_err() {
         gcl.clear();
-        schedule_fixed_og(og_table, gcl, &get_links)?;
-        Ok(true)
-    } else {
-        Ok(false)
+        if let Err(error) = schedule_offline(og_table, gcl, &get_links) {
+            // 大洗牌也失敗的話，舊資料流維持本來的排程，只有改動的資料流沒排進去
+            *gcl = og_gcl;
+            return Err(error);
+        }
+    }
+    let mut disrupted = vec![];
+    for (flow, info) in og_table.iter_tsn() {
+        if let Some(og_schedule) = og_schedules.get(&flow.id) {
+            let links = get_links(flow, info);
+            if *og_schedule != get_flow_schedule(flow.id, &links, gcl) {
+                disrupted.push(flow.id);
+            }
+        }
+    }
+    disrupted.sort();
+    Ok(disrupted)
+}
+
+/// 拔掉被改動的資料流，以及與它們共用連結的舊資料流，再把這些資料流一起重排
+///
+/// 失敗時 GCL 中可能留有排到一半的資料流，呼叫者應自行清理
+fn reschedule_conflicts<T: Eq + Clone, F: Fn(&TSNFlow, &T) -> Links>(
+    og_table: &FT<T>,
+    changed_table: &DT<T>,
+    gcl: &mut GCL,
+    get_links: F,
+) -> Result<(), ScheduleError> {
+    let mut changed_links = HashSet::<usize>::new();
+    for (flow, info) in changed_table.iter_tsn() {
+        for (link_id, _) in get_links(flow, info).into_iter() {
+            changed_links.insert(link_id);
+        }
+    }
+    let mut rip_up = og_table.clone_as_diff();
+    for (flow, info) in og_table.iter_tsn() {
+        let link_ids: Vec<usize> = get_links(flow, info)
+            .into_iter()
+            .map(|(link_id, _)| link_id)
+            .collect();
+        if changed_table.check_exist(flow.id)
+            || link_ids
+                .iter()
+                .any(|link_id| changed_links.contains(link_id))
+        {
+            gcl.delete_flow(&link_ids, flow.id);
+            rip_up.update_info_force(flow.id, info.clone());
+        }
+    }
+    schedule_offline(&rip_up, gcl, get_links)
+}
+
+/// 一個資料流在路徑上每個連結的閘門事件
+fn get_flow_schedule(flow_id: FlowID, links: &Links, gcl: &GCL) -> Vec<Vec<(u32, u32)>> {
+    links
+        .iter()
+        .map(|&(link_id, _)| gcl.get_flow_gate_events(link_id, flow_id))
+        .collect()
+}
+
+/// 依設定檔選擇排程算法，把表中的資料流排進 GCL（GCL 中既有的事件不會被改動）
+///
+/// 排好之後，這些資料流經過的埠口，其閘門控制清單都要放得進交換機
+fn schedule_offline<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
+    table: &TABLE,
+    gcl: &mut GCL,
+    get_links: F,
+) -> Result<(), ScheduleError> {
+    match Config::get().tt_scheduler {
+        TTScheduler::Greedy => schedule_fixed_og(table, gcl, &get_links),
+        TTScheduler::Backtrack => schedule_backtrack(table, gcl, &get_links),
+    }?;
+    check_gcl_len(table, gcl, get_links)
+}
+
+/// 確認表中資料流經過的埠口，壓縮後的閘門控制清單不超過 `max_gcl_len`
+///
+/// 超過者回報第一個經過該埠口的資料流
+fn check_gcl_len<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
+    table: &TABLE,
+    gcl: &GCL,
+    get_links: F,
+) -> Result<(), ScheduleError> {
+    let mut checked = HashSet::<usize>::new();
+    for (flow, info) in table.iter_tsn() {
+        for (link_id, _) in get_links(flow, info).into_iter() {
+            if checked.insert(link_id) && is_gcl_overflow(link_id, gcl) {
+                return Err(ScheduleError::new(
+                    flow.id,
+                    Some(link_id),
+                    FailReason::GclOverflow,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 剛排進 GCL 的資料流若使路徑上某個埠口的閘門控制清單超過 `max_gcl_len`，就把它拔掉並回傳錯誤
+fn fit_gcl_len(flow: &TSNFlow, links: &Links, gcl: &mut GCL) -> Result<(), ScheduleError> {
+    if let Some(&(link_id, _)) = links
+        .iter()
+        .find(|&&(link_id, _)| is_gcl_overflow(link_id, gcl))
+    {
+        let link_ids = links.iter().map(|(id, _)| *id).collect();
+        gcl.delete_flow(&link_ids, flow.id);
+        return Err(ScheduleError::new(
+            flow.id,
+            Some(link_id),
+            FailReason::GclOverflow,
+        ));
+    }
+    Ok(())
+}
+
+/// 該埠口壓縮後的閘門控制清單是否超過 `max_gcl_len`
+fn is_gcl_overflow(link_id: usize, gcl: &GCL) -> bool {
+    match gcl.get_port_config(link_id).max_gcl_len {
+        Some(max_gcl_len) => gcl.get_compact_gate_list(link_id).len() > max_gcl_len,
+        None => false,
     }
 }
 
@@ -81,102 +208,362 @@ fn schedule_fixed_og<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow,
     table: &TABLE,
     gcl: &mut GCL,
     get_links: F,
-) -> Result<(), ()> {
+) -> Result<(), ScheduleError> {
+    let config = Config::get();
+    schedule_with_retry(
+        table,
+        gcl,
+        get_links,
+        config.tt_order,
+        config.tt_retry_budget,
+    )
+}
+
+/// 依 `order` 的順序逐一排程；若失敗且 `retry_budget`（微秒）大於零，則還原 GCL，依序改用其它順序重試，
+/// 固定的順序都試過後就不斷以隨機順序重試，直到時間用完為止。
+/// 重試全部失敗時，GCL 會還原為排程前的狀態。
+fn schedule_with_retry<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
+    table: &TABLE,
+    gcl: &mut GCL,
+    get_links: F,
+    order: TTOrder,
+    retry_budget: u128,
+) -> Result<(), ScheduleError> {
     let mut tsn_ids = Vec::<FlowID>::new();
     for (flow, _) in table.iter_tsn() {
         tsn_ids.push(flow.id);
     }
-    tsn_ids.sort_by(|&id1, &id2| cmp_flow(id1, id2, table, &get_links));
-    for flow_id in tsn_ids.into_iter() {
+    sort_flows(&mut tsn_ids, order, table, &get_links);
+    if retry_budget == 0 {
+        return schedule_in_order(&tsn_ids, table, gcl, &get_links);
+    }
+    let timer = Instant::now();
+    let og_gcl = gcl.clone();
+    // NOTE 重試全部失敗時，回報以設定的順序排程時的失敗原因
+    let error = match schedule_in_order(&tsn_ids, table, gcl, &get_links) {
+        Ok(_) => return Ok(()),
+        Err(error) => error,
+    };
+    let fixed_orders = [
+        TTOrder::DeadlineMonotonic,
+        TTOrder::Edf,
+        TTOrder::Utilization,
+        TTOrder::MostConstrained,
+    ];
+    let retry_orders = fixed_orders
+        .iter()
+        .cloned()
+        .filter(|&fixed_order| fixed_order != order)
+        .chain(std::iter::repeat(TTOrder::Random));
+    for retry_order in retry_orders {
+        if timer.elapsed().as_micros() >= retry_budget {
+            break;
+        }
+        *gcl = og_gcl.clone();
+        sort_flows(&mut tsn_ids, retry_order, table, &get_links);
+        if schedule_in_order(&tsn_ids, table, gcl, &get_links).is_ok() {
+            return Ok(());
+        }
+    }
+    *gcl = og_gcl;
+    Err(error)
+}
+
+/// 依 `tsn_ids` 的順序逐一把資料流排進 GCL
+fn schedule_in_order<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
+    tsn_ids: &[FlowID],
+    table: &TABLE,
+    gcl: &mut GCL,
+    get_links: F,
+) -> Result<(), ScheduleError> {
+    let preemption = Config::get().preemption;
+    for &flow_id in tsn_ids.iter() {
         let flow = table.get_tsn(flow_id).unwrap();
+        if !gcl.get_hyper_p().is_multiple_of(flow.period) {
+            // 超週期延長失敗，這個資料流不可能在每個超週期裡都排得一樣
+            return Err(ScheduleError::new(flow_id, None, FailReason::HyperPeriod));
+        }
         let links = get_links(flow, table.get_info(flow_id).unwrap());
-        let mut all_offsets: Vec<Vec<u32>> = vec![];
-        // NOTE 一個資料流的每個封包，在單一埠口上必需採用同一個佇列
-        let mut ro: Vec<u8> = vec![0; links.len()];
-        let k = get_frame_cnt(flow.size);
-        let mut m = 0;
-        while m < k {
-            let offsets = calculate_offsets(flow, &all_offsets, &links, &ro, gcl);
-            if offsets.len() == links.len() {
-                m += 1;
-                all_offsets.push(offsets);
-            } else {
-                m = 0;
-                all_offsets.clear();
-                assign_new_queues(&mut ro)?;
+        let frames = get_flow_frames(flow, &links, gcl);
+        // NOTE 一個資料流的每個封包，在單一埠口上必需採用同一個佇列，且只能用保留給 TT 的佇列
+        let tt_queues: Vec<Vec<u8>> = links
+            .iter()
+            .map(|&(link_id, _)| gcl.get_port_config(link_id).tt_queues.clone())
+            .collect();
+        // NOTE 閘門控制清單放不下時也換下一個釋出時間，讓閘門時段與既有的時段對齊而合併；
+        //      每個釋出時間都失敗的話，回報最早的釋出時間失敗的原因
+        let mut error = None;
+        for earliest in get_release_candidates(flow, &links, &frames, gcl, preemption) {
+            let plan = FlowPlan {
+                flow,
+                earliest,
+                frames: &frames,
+                links: &links,
+                preemption,
+            };
+            match assign_offsets(&plan, &tt_queues, gcl)
+                .and_then(|_| fit_gcl_len(flow, &links, gcl))
+            {
+                Ok(_) => {
+                    error = None;
+                    break;
+                }
+                Err(e) => {
+                    error = error.or(Some(e));
+                }
             }
         }
+        if let Some(error) = error {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// 排程一個資料流時，不論換了哪些佇列都不會變的輸入
+#[derive(Clone, Copy)]
+struct FlowPlan<'a> {
+    flow: &'a TSNFlow,
+    /// 第一個封包最早在這時開始傳輸，釋出時間見 `get_release`
+    earliest: u32,
+    /// 每個訊框在線路上的大小，見 `get_flow_frames`
+    frames: &'a Vec<usize>,
+    links: &'a Links,
+    preemption: bool,
+}
 
-        // 把上面算好的結果塞進 GCL
-        for i in 0..links.len() {
-            let link_id = links[i].0;
-            let queue_id = ro[i];
-            let trans_time = ((MTU as f64) / links[i].1).ceil() as u32;
-            gcl.set_queueid(queue_id, link_id, flow_id);
-            // 考慮 hyper period 中每個狀況
-            let p = flow.period as usize;
-            for time_shift in (0..gcl.get_hyper_p()).step_by(p) {
-                for m in 0..k {
-                    // insert gate evt
-                    gcl.insert_gate_evt(
-                        link_id,
-                        flow_id,
-                        queue_id,
-                        time_shift + all_offsets[m][i],
-                        trans_time,
-                    );
-                    // insert queue evt
-                    let queue_evt_start = if i == 0 {
-                        flow.spec_data.offset
-                    } else {
-                        all_offsets[m][i - 1] // 前一個埠口一開始傳即視為開始佔用
-                    };
-                    /*println!("===link={} flow={} queue={} {} {}===",
-                    link_id, flow_id , queue_id, all_offsets[m][i], queue_evt_start); */
-                    let queue_evt_duration = all_offsets[m][i] - queue_evt_start;
-                    gcl.insert_queue_evt(
-                        link_id,
-                        flow_id,
-                        queue_id,
-                        time_shift + queue_evt_start,
-                        queue_evt_duration,
-                    );
+/// 依 `plan` 算出每個封包的時間與每個埠口的佇列，並塞進 GCL
+///
+/// 抖動超標也視為失敗（卡在最後一個連結），與其它失敗一樣換佇列重試。
+/// 佇列都換過一輪仍排不進去時，回報最後一次嘗試在哪個連結上、因為什麼而失敗
+fn assign_offsets(
+    plan: &FlowPlan,
+    tt_queues: &[Vec<u8>],
+    gcl: &mut GCL,
+) -> Result<(), ScheduleError> {
+    let FlowPlan {
+        flow,
+        frames,
+        links,
+        preemption,
+        ..
+    } = *plan;
+    let mut all_offsets: Vec<Vec<u32>> = vec![];
+    let mut ro: Vec<u8> = tt_queues.iter().map(|queues| queues[0]).collect();
+    loop {
+        let result = calculate_offsets(plan, &all_offsets, &ro, gcl);
+        let (fail_hop, reason) = match result {
+            Ok(offsets) => {
+                all_offsets.push(offsets);
+                if all_offsets.len() < frames.len() {
+                    continue;
+                }
+                // 把上面算好的結果塞進 GCL
+                match insert_flow(flow, links, &ro, &all_offsets, gcl, preemption) {
+                    Ok(_) => return Ok(()),
+                    Err(error) => (links.len() - 1, error.reason),
                 }
             }
+            Err((offsets, reason)) => (offsets.len(), reason),
+        };
+        all_offsets.clear();
+        assign_new_queues(&mut ro, fail_hop, tt_queues).map_err(|_| {
+            // NOTE 佇列被佔住時，卡住的是下個埠口的佇列
+            let link_id = match reason {
+                FailReason::NoFreeQueue => links[fail_hop + 1].0,
+                _ => links[fail_hop].0,
+            };
+            ScheduleError::new(flow.id, Some(link_id), reason)
+        })?;
+    }
+}
+
+/// 釋出窗口中值得一試的釋出時間，由小到大排列
+///
+/// 除了最早的釋出時間外，只考慮「左對齊」的時間點：第一個封包不受阻擋地一路傳下去，
+/// 恰在某個連結上的閘門事件結束時抵達該連結，其閘門時段與既有的時段相接，壓縮後的閘門控制清單也比較短。
+/// 沒有釋出窗口的資料流只有 `offset` 一個候選。
+fn get_release_candidates(
+    flow: &TSNFlow,
+    links: &Links,
+    frames: &[usize],
+    gcl: &GCL,
+    preemption: bool,
+) -> Vec<u32> {
+    let (earliest, latest) = (flow.spec_data.offset, flow.spec_data.get_latest_offset());
+    let mut candidates = vec![earliest];
+    // 第一個封包從釋出到抵達第 i 個連結，最少要花多久
+    let mut travel_time = 0;
+    for &(link_id, bandwidth) in links.iter() {
+        for &(start, duration) in gcl.get_gate_events(link_id).iter() {
+            let end = (start + duration) % flow.period;
+            if end >= travel_time {
+                let release = end - travel_time;
+                if release > earliest && release <= latest {
+                    candidates.push(release);
+                }
+            }
+        }
+        travel_time += get_trans_time(frames[0], bandwidth)
+            + get_blocking_time(&(link_id, bandwidth), gcl, preemption);
+    }
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// 把算好的封包時間與佇列塞進 GCL，若抖動超標則拔掉並回傳錯誤
+fn insert_flow(
+    flow: &TSNFlow,
+    links: &Links,
+    ro: &[u8],
+    all_offsets: &[Vec<u32>],
+    gcl: &mut GCL,
+    preemption: bool,
+) -> Result<(), ScheduleError> {
+    let flow_id = flow.id;
+    let k = all_offsets.len();
+    let frames = get_flow_frames(flow, links, gcl);
+    let release = get_release(flow, all_offsets[0][0]);
+    gcl.set_release(release, flow_id);
+    for i in 0..links.len() {
+        let link_id = links[i].0;
+        let queue_id = ro[i];
+        gcl.set_queueid(queue_id, link_id, flow_id);
+        // 考慮 hyper period 中每個狀況
+        let p = flow.period as usize;
+        for time_shift in (0..gcl.get_hyper_p()).step_by(p) {
+            for m in 0..k {
+                // insert gate evt
+                gcl.insert_gate_evt(
+                    link_id,
+                    flow_id,
+                    queue_id,
+                    time_shift + all_offsets[m][i],
+                    get_trans_time(frames[m], links[i].1),
+                );
+                // insert queue evt
+                let queue_evt_start = if i == 0 {
+                    release
+                } else {
+                    all_offsets[m][i - 1] // 前一個埠口一開始傳即視為開始佔用
+                };
+                let queue_evt_duration = all_offsets[m][i] - queue_evt_start;
+                gcl.insert_queue_evt(
+                    link_id,
+                    flow_id,
+                    queue_id,
+                    time_shift + queue_evt_start,
+                    queue_evt_duration,
+                );
+            }
+        }
+    }
+
+    // 抖動超標的話，這個排程不能用
+    if let Some(max_jitter) = flow.spec_data.max_jitter {
+        if compute_jitter(flow, links, gcl, preemption) > max_jitter {
+            let link_ids = links.iter().map(|(id, _)| *id).collect();
+            gcl.delete_flow(&link_ids, flow_id);
+            let last_link = links[links.len() - 1].0;
+            return Err(ScheduleError::new(
+                flow_id,
+                Some(last_link),
+                FailReason::JitterExceeded,
+            ));
         }
     }
     Ok(())
 }
 
-/// 回傳值為為一個陣列，若其長度小於路徑長，代表排一排爆開
+/// 計算 TT 資料流的端對端抖動：同一個封包（每個週期中的第 m 個）抵達終點的延遲，在超週期的各個週期之間最多差多少，
+/// 取所有封包中最大者。同一個週期中不同封包的延遲本來就不同，不算抖動。
+///
+/// 閘門在固定的時間開啟，中途被低優先度訊框阻擋所造成的延誤，會在下一個埠口等閘門時被吸收；
+/// 只有最後一個連結上的阻擋（見 `get_blocking_time`）會讓抵達時間再晚一些，故也算進抖動
+/// * `links` - 資料流的路徑，該資料流必需已經排進 GCL
+pub fn compute_jitter(flow: &TSNFlow, links: &Links, gcl: &GCL, preemption: bool) -> u32 {
+    let last_link = links[links.len() - 1];
+    let evts = gcl.get_flow_gate_events(last_link.0, flow.id);
+    if evts.is_empty() {
+        return 0;
+    }
+    let offset = gcl.get_release(flow.id).unwrap_or(flow.spec_data.offset);
+    // 每個週期的封包數
+    let k = std::cmp::max(1, evts.len() / (gcl.get_hyper_p() / flow.period) as usize);
+    let mut jitter = 0;
+    for m in 0..k {
+        let (mut min_latency, mut max_latency) = (u32::MAX, 0);
+        // NOTE 同個資料流的封包依序抵達，第 j 個封包屬於第 j / k 個週期
+        for (j, &(start, duration)) in evts.iter().enumerate().skip(m).step_by(k) {
+            let release = (j / k) as u32 * flow.period + offset;
+            let latency = start + duration - release;
+            min_latency = std::cmp::min(min_latency, latency);
+            max_latency = std::cmp::max(max_latency, latency);
+        }
+        jitter = std::cmp::max(jitter, max_latency - min_latency);
+    }
+    jitter + get_blocking_time(&last_link, gcl, preemption)
+}
+
+/// 排程時要為 TT 封包在該連結上預留多少被低優先度訊框阻擋的時間
+///
+/// 未啟用訊框搶佔時沿用原本的模型，不預留；啟用時預留一個片段（見 `max_blocking_time`）。
+/// 連結上有保護帶的話，低優先度的訊框不可能延續到 TT 的時段中，故不會被阻擋
+#[inline(always)]
+fn get_blocking_time(link: &(usize, f64), gcl: &GCL, preemption: bool) -> u32 {
+    if !preemption || gcl.get_guard_band(link.0) > 0 {
+        0
+    } else {
+        max_blocking_time(link.1, preemption).ceil() as u32
+    }
+}
+
+/// 算出下一個封包（前面的封包已排在 `all_offsets`）在每個連結上的傳輸時間；
+/// 排一排爆開的話，回傳已排好的部份（其長度小於路徑長）與原因
+///
+/// 第一個封包最早在 `plan.earliest` 時開始傳輸，釋出時間見 `get_release`
+///
+/// 啟用訊框搶佔（`plan.preemption`）時，每個埠口上都要預留被一個片段阻擋的時間；
+/// 未啟用或有保護帶的埠口則不預留（見 `get_blocking_time`）
 fn calculate_offsets(
-    flow: &TSNFlow,
+    plan: &FlowPlan,
     all_offsets: &Vec<Vec<u32>>,
-    links: &Vec<(usize, f64)>,
     ro: &Vec<u8>,
     gcl: &GCL,
-) -> Vec<u32> {
+) -> Result<Vec<u32>, (Vec<u32>, FailReason)> {
+    let FlowPlan {
+        flow,
+        earliest,
+        frames,
+        links,
+        preemption,
+    } = *plan;
     let mut offsets = Vec::<u32>::with_capacity(links.len());
     let hyper_p = gcl.get_hyper_p();
+    let m = all_offsets.len();
     for i in 0..links.len() {
-        let trans_time = (MTU as f64 / links[i].1).ceil() as u32;
+        let trans_time = get_trans_time(frames[m], links[i].1);
+        let blocking_time = get_blocking_time(&links[i], gcl, preemption);
         let arrive_time = if i == 0 {
             // 路徑起始
-            if all_offsets.len() == 0 {
+            if m == 0 {
                 // 資料流的第一個封包
-                flow.spec_data.offset
+                earliest
             } else {
                 // #m-1 封包完整送出，且經過處理時間
-                all_offsets[all_offsets.len() - 1][i] + trans_time
+                all_offsets[m - 1][i] + get_trans_time(frames[m - 1], links[i].1)
             }
         } else {
-            // #m 封包送達，且經過處理時間
-            let a = offsets[i - 1] + (MTU as f64 / links[i - 1].1).ceil() as u32;
-            if all_offsets.len() == 0 {
+            // #m 封包送達（可能先被低優先度訊框阻擋），且經過處理時間
+            let a = offsets[i - 1]
+                + get_trans_time(frames[m], links[i - 1].1)
+                + get_blocking_time(&links[i - 1], gcl, preemption);
+            if m == 0 {
                 a
             } else {
                 // #m-1 封包完整送出，且經過處理時間
-                let b = all_offsets[all_offsets.len() - 1][i] + trans_time;
+                let b = all_offsets[m - 1][i] + get_trans_time(frames[m - 1], links[i].1);
                 if a > b {
                     a
                 } else {
@@ -185,69 +572,136 @@ fn calculate_offsets(
             }
         };
         let mut cur_offset = arrive_time;
+        // 第一個封包在第一個連結上的傳輸時間，用來決定釋出時間（若還沒決定，就是 cur_offset 本身）
+        let first_offset = if m > 0 {
+            Some(all_offsets[0][0])
+        } else if i > 0 {
+            Some(offsets[0])
+        } else {
+            None
+        };
         let p = flow.period as usize;
-        for time_shift in (0..hyper_p).step_by(p) {
-            // 考慮 hyper period 中每種狀況
-            /*
-             * 1. 每個連結一個時間只能傳輸一個封包
-             * 2. 同個佇列一個時間只能容納一個資料流（但可能容納該資料流的數個封包）
-             * 3. 要符合 max_delay 的需求
-             */
-            // QUESTION 搞清楚第二點是為什麼？
-            loop {
-                // NOTE 確認沒有其它封包在這個連線上傳輸
-                let option =
-                    gcl.get_next_empty_time(links[i].0, time_shift + cur_offset, trans_time);
-                if let Some(time) = option {
-                    cur_offset = time - time_shift;
-                    if miss_deadline(cur_offset, trans_time, flow) {
-                        return offsets;
-                    }
-                    continue;
-                }
-                // NOTE 確認傳輸到下個地方時，下個連線的佇列是空的（沒有其它的資料流）
-                if i < links.len() - 1 {
-                    // 還不到最後一個節點
-                    let option = gcl.get_next_queue_empty_time(
-                        links[i + 1].0,
-                        ro[i],
-                        time_shift + (cur_offset + trans_time),
-                    );
+        // NOTE 在後面的週期被推遲後，前面的週期也要重新確認，直到所有週期都不再推遲為止
+        loop {
+            let og_offset = cur_offset;
+            for time_shift in (0..hyper_p).step_by(p) {
+                // 考慮 hyper period 中每種狀況
+                /*
+                 * 1. 每個連結一個時間只能傳輸一個封包
+                 * 2. 同個佇列一個時間只能容納一個資料流（但可能容納該資料流的數個封包）
+                 * 3. 要符合 max_delay 的需求
+                 */
+                // QUESTION 搞清楚第二點是為什麼？
+                loop {
+                    // NOTE 確認沒有其它封包在這個連線上傳輸
+                    let option =
+                        gcl.get_next_empty_time(links[i].0, time_shift + cur_offset, trans_time);
                     if let Some(time) = option {
                         cur_offset = time - time_shift;
-                        if miss_deadline(cur_offset, trans_time, flow) {
-                            return offsets;
+                        if miss_deadline(
+                            cur_offset,
+                            trans_time + blocking_time,
+                            first_offset.unwrap_or(cur_offset),
+                            flow,
+                        ) {
+                            return Err((offsets, FailReason::DeadlineMiss));
                         }
                         continue;
                     }
+                    // NOTE 確認傳輸到下個地方時，下個埠口上所選的佇列是空的（沒有其它的資料流）
+                    if i < links.len() - 1 {
+                        // 還不到最後一個節點
+                        let option = gcl.get_next_queue_empty_time(
+                            links[i + 1].0,
+                            ro[i + 1],
+                            time_shift + (cur_offset + trans_time + blocking_time),
+                        );
+                        if let Some(time) = option {
+                            cur_offset = time - time_shift;
+                            if miss_deadline(
+                                cur_offset,
+                                trans_time + blocking_time,
+                                first_offset.unwrap_or(cur_offset),
+                                flow,
+                            ) {
+                                return Err((offsets, FailReason::NoFreeQueue));
+                            }
+                            continue;
+                        }
+                    }
+                    if miss_deadline(
+                        cur_offset,
+                        trans_time + blocking_time,
+                        first_offset.unwrap_or(cur_offset),
+                        flow,
+                    ) {
+                        return Err((offsets, FailReason::DeadlineMiss));
+                    }
+                    break;
                 }
-                if miss_deadline(cur_offset, trans_time, flow) {
-                    return offsets;
-                }
+                // QUESTION 是否要檢查 arrive_time ~ cur_offset+trans_time 這段時間中有沒有發生同個佇列被佔用的事件？
+            }
+            if cur_offset == og_offset {
                 break;
             }
-            // QUESTION 是否要檢查 arrive_time ~ cur_offset+trans_time 這段時間中有沒有發生同個佇列被佔用的事件？
+        }
+        // NOTE 從前一個埠口開始傳輸，直到在這個埠口開始傳輸為止，所選的佇列都不能有其它資料流
+        if i > 0 {
+            for time_shift in (0..hyper_p).step_by(p) {
+                let (start, end) = (time_shift + offsets[i - 1], time_shift + cur_offset);
+                if !gcl.is_queue_free(links[i].0, ro[i], start, end) {
+                    // 視為卡在前一個連結，以便換掉這個埠口的佇列
+                    offsets.pop();
+                    return Err((offsets, FailReason::NoFreeQueue));
+                }
+            }
         }
         offsets.push(cur_offset);
     }
-    offsets
+    Ok(offsets)
 }
 
-fn assign_new_queues(ro: &mut Vec<u8>) -> Result<(), ()> {
-    // TODO 好好實作這個函式（目前一個資料流只安排個佇列，但在不同埠口上應該可以安排給不同佇列）
-    if ro[0] == MAX_QUEUE - 1 {
-        Err(())
-    } else {
-        for i in 0..ro.len() {
-            ro[i] += 1;
+/// 排程失敗時，換掉某個埠口上使用的佇列
+///
+/// 把各埠口的佇列視為一個里程表，越前面的埠口位數越高，依序列舉所有組合。
+/// `calculate_offsets` 在第 `fail_hop` 個連結上失敗，只與第 `fail_hop + 1` 個埠口（含）以前的佇列有關，
+/// 故直接進位該埠口，並把其後的埠口都重設回第一個佇列，跳過前綴相同、註定失敗的組合；
+/// 該埠口的佇列用完時，同樣重設並往前進位。
+/// 路徑的第一個埠口位於終端上，不會與其它資料流衝突，故不列入考慮。
+///
+/// 每個埠口只能使用 `tt_queues` 中保留給 TT 的佇列，並依其順序嘗試。全部組合用完即回傳錯誤。
+fn assign_new_queues(ro: &mut [u8], fail_hop: usize, tt_queues: &[Vec<u8>]) -> Result<(), ()> {
+    if ro.len() < 2 {
+        return Err(());
+    }
+    let target = std::cmp::min(fail_hop + 1, ro.len() - 1);
+    for port in target + 1..ro.len() {
+        ro[port] = tt_queues[port][0];
+    }
+    for port in (1..=target).rev() {
+        let queues = &tt_queues[port];
+        let pos = queues.iter().position(|&q| q == ro[port]).unwrap();
+        if pos + 1 < queues.len() {
+            ro[port] = queues[pos + 1];
+            return Ok(());
         }
-        Ok(())
+        ro[port] = queues[0];
     }
+    Err(())
+}
+
+/// 資料流的釋出時間，由第一個封包在第一個連結上開始傳輸的時間 `first_offset` 決定
+///
+/// 若 `first_offset` 落在釋出窗口內，就在那時釋出；否則在最晚的釋出時間釋出，並在終端上等待
+#[inline(always)]
+fn get_release(flow: &TSNFlow, first_offset: u32) -> u32 {
+    std::cmp::min(first_offset, flow.spec_data.get_latest_offset())
 }
 
+/// 死線是釋出時間加上 `max_delay`，釋出時間見 `get_release`
 #[inline(always)]
-fn miss_deadline(cur_offset: u32, trans_time: u32, flow: &TSNFlow) -> bool {
-    if cur_offset + trans_time >= flow.spec_data.offset + flow.max_delay {
+fn miss_deadline(cur_offset: u32, trans_time: u32, first_offset: u32, flow: &TSNFlow) -> bool {
+    if cur_offset + trans_time >= get_release(flow, first_offset) + flow.max_delay {
         // 死線爆炸！
         true
     } else {
@@ -0,0 +1,78 @@
+use super::Links;
+use crate::config::TTOrder;
+use crate::flow::{FlowID, TSNFlow};
+use crate::recorder::flow_table::prelude::*;
+use rand::Rng;
+use std::cmp::Ordering;
+
+/// 依排序策略決定 TT 資料流的排程順序，順序在貪婪排程中大大影響能否排得進去
+pub(super) fn sort_flows<
+    T: Eq + Clone,
+    TABLE: IFlowTable<INFO = T>,
+    F: Fn(&TSNFlow, &T) -> Links,
+>(
+    tsn_ids: &mut Vec<FlowID>,
+    order: TTOrder,
+    table: &TABLE,
+    get_links: F,
+) {
+    // NOTE 先依編號排好，同分者的順序才不會受到資料流表的影響
+    tsn_ids.sort();
+    match order {
+        TTOrder::DeadlineMonotonic => {
+            tsn_ids.sort_by(|&id1, &id2| cmp_flow(id1, id2, table, &get_links))
+        }
+        TTOrder::Edf => tsn_ids.sort_by_key(|&id| {
+            let flow = table.get_tsn(id).unwrap();
+            (flow.spec_data.offset + flow.max_delay, flow.period)
+        }),
+        TTOrder::Utilization => sort_by_metric(tsn_ids, |id| {
+            let flow = table.get_tsn(id).unwrap();
+            let links = get_links(flow, table.get_info(id).unwrap());
+            -get_route_trans_time(flow, &links) / flow.period as f64
+        }),
+        TTOrder::MostConstrained => sort_by_metric(tsn_ids, |id| {
+            let flow = table.get_tsn(id).unwrap();
+            let links = get_links(flow, table.get_info(id).unwrap());
+            flow.max_delay as f64 - get_route_trans_time(flow, &links)
+        }),
+        TTOrder::Random => rand::thread_rng().shuffle(tsn_ids),
+    }
+}
+
+/// 依 `metric` 由小到大排序（穩定排序，同分者維持原本的順序）
+fn sort_by_metric(tsn_ids: &mut Vec<FlowID>, metric: impl Fn(FlowID) -> f64) {
+    let mut keyed: Vec<(f64, FlowID)> = tsn_ids.iter().map(|&id| (metric(id), id)).collect();
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    *tsn_ids = keyed.into_iter().map(|(_, id)| id).collect();
+}
+
+/// 整個資料流在路徑上每個連結傳一次，總共要花多久（不計訊框開銷，只用來比較）
+fn get_route_trans_time(flow: &TSNFlow, links: &Links) -> f64 {
+    links
+        .iter()
+        .map(|&(_, bandwidth)| flow.size as f64 / bandwidth)
+        .sum()
+}
+
+/// 排序的標準：
+/// * `deadline` - 時間較緊的要排前面
+/// * `period` - 週期短的要排前面
+/// * `route length` - 路徑長的要排前面
+/// * `id` - 以上皆相同時，編號小的排前面，使其成為全序
+pub(super) fn cmp_flow<T: Eq + Clone, TABLE: IFlowTable<INFO = T>, F: Fn(&TSNFlow, &T) -> Links>(
+    id1: FlowID,
+    id2: FlowID,
+    table: &TABLE,
+    get_links: F,
+) -> Ordering {
+    let flow1 = table.get_tsn(id1).unwrap();
+    let flow2 = table.get_tsn(id2).unwrap();
+    let route_len = |flow: &TSNFlow| get_links(flow, table.get_info(flow.id).unwrap()).len();
+    flow1
+        .max_delay
+        .cmp(&flow2.max_delay)
+        .then(flow1.period.cmp(&flow2.period))
+        .then_with(|| route_len(flow2).cmp(&route_len(flow1)))
+        .then(id1.cmp(&id2))
+}
@@ -1,5 +1,10 @@
+use super::super::{validate_schedule, Violation};
 use super::*;
+use crate::config::TTOrder;
 use crate::flow::data::TSNData;
+use crate::graph_util::{PortConfig, DEFAULT_MTU as MTU};
+use crate::MAX_QUEUE;
+use std::cmp::Ordering;
 
 type Info = Vec<(usize, usize)>;
 type FT = FlowTable<Info>;
@@ -20,8 +25,11 @@ type FT = FlowTable<Info>;
  * flow3(0->4): route=[0, 2, 3, 5, 4], links = [1, 5, 6, 7]
  */
 
+/// 頻寬恰使一個完整的訊框（含開銷）傳輸一單位時間
 fn gen_links(ids: Vec<usize>) -> Info {
-    ids.into_iter().map(|id| (id, MTU)).collect()
+    ids.into_iter()
+        .map(|id| (id, MTU + FRAME_OVERHEAD))
+        .collect()
 }
 fn gen_flow_table() -> FT {
     let mut ft = FlowTable::new();
@@ -34,7 +42,11 @@ fn gen_flow_table() -> FT {
                 size: MTU,
                 period: 100,
                 max_delay: 100,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
             TSNFlow {
                 id: 0.into(),
@@ -43,7 +55,11 @@ fn gen_flow_table() -> FT {
                 size: MTU * 3,
                 period: 150,
                 max_delay: 150,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
             TSNFlow {
                 id: 0.into(),
@@ -52,7 +68,11 @@ fn gen_flow_table() -> FT {
                 size: MTU * 2,
                 period: 200,
                 max_delay: 200,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
             TSNFlow {
                 id: 0.into(),
@@ -61,7 +81,11 @@ fn gen_flow_table() -> FT {
                 size: MTU * 3,
                 period: 300,
                 max_delay: 300,
-                spec_data: TSNData { offset: 0 },
+                spec_data: TSNData {
+                    offset: 0,
+                    max_jitter: None,
+                    latest_offset: None,
+                },
             },
         ],
         vec![],
@@ -76,6 +100,27 @@ fn gen_flow_table() -> FT {
 fn to_links(vec: &Vec<(usize, usize)>) -> Vec<(usize, f64)> {
     vec.iter().map(|(a, b)| (*a, *b as f64)).collect()
 }
+fn gen_links_f64(ids: Vec<usize>) -> Links {
+    to_links(&gen_links(ids))
+}
+fn plan<'a>(
+    flow: &'a TSNFlow,
+    frames: &'a Vec<usize>,
+    links: &'a Links,
+    preemption: bool,
+) -> FlowPlan<'a> {
+    FlowPlan {
+        flow,
+        earliest: 0,
+        frames,
+        links,
+        preemption,
+    }
+}
+fn assert_valid<TABLE: IFlowTable<INFO = Info>>(table: &TABLE, gcl: &GCL) {
+    let violations = validate_schedule(table, gcl, |_, info| to_links(info));
+    assert_eq!(Vec::<Violation>::new(), violations);
+}
 
 #[test]
 fn simple_calculate_offset() {
@@ -83,13 +128,229 @@ fn simple_calculate_offset() {
     let ft = gen_flow_table();
     let flow = ft.get_tsn(0.into()).unwrap();
     let links = to_links(ft.get_info(0.into()).unwrap());
-    let a = calculate_offsets(&flow, &vec![], &links, &vec![0; 2], &gcl);
-    assert_eq!(vec![0, 1], a);
+    let frames = get_flow_frames(flow, &links, &gcl);
+    let a = calculate_offsets(
+        &plan(&flow, &frames, &links, false),
+        &vec![],
+        &vec![0; 2],
+        &gcl,
+    );
+    assert_eq!(Ok(vec![0, 1]), a);
 
     let flow = ft.get_tsn(2.into()).unwrap();
     let links = to_links(ft.get_info(2.into()).unwrap());
-    let a = calculate_offsets(&flow, &vec![], &links, &vec![0; 3], &gcl);
-    assert_eq!(vec![0, 1, 2], a);
+    let frames = get_flow_frames(flow, &links, &gcl);
+    let a = calculate_offsets(
+        &plan(&flow, &frames, &links, false),
+        &vec![],
+        &vec![0; 3],
+        &gcl,
+    );
+    assert_eq!(Ok(vec![0, 1, 2]), a);
+}
+#[test]
+fn test_calculate_offset_with_preemption() {
+    let gcl = GCL::new(60, 16);
+    let ft = gen_flow_table();
+    let flow = ft.get_tsn(2.into()).unwrap();
+    let links = to_links(ft.get_info(2.into()).unwrap());
+    let frames = get_flow_frames(flow, &links, &gcl);
+    // 每個埠口都要預留被一個片段阻擋的時間
+    let a = calculate_offsets(
+        &plan(&flow, &frames, &links, true),
+        &vec![],
+        &vec![0; 3],
+        &gcl,
+    );
+    assert_eq!(Ok(vec![0, 2, 4]), a);
+    // 有保護帶的埠口不必預留
+    let mut gcl = GCL::new(60, 16);
+    gcl.set_guard_bands(vec![1; 16]);
+    let a = calculate_offsets(
+        &plan(&flow, &frames, &links, true),
+        &vec![],
+        &vec![0; 3],
+        &gcl,
+    );
+    assert_eq!(Ok(vec![0, 1, 2]), a);
+}
+#[test]
+fn test_frame_sizes() {
+    assert_eq!(vec![1542, 1542], get_frame_sizes(3000, 1500));
+    // 最後一個訊框只裝剩下的部份
+    assert_eq!(vec![1542, 542], get_frame_sizes(2000, 1500));
+    // 但酬載太小時要補齊
+    assert_eq!(vec![1542, 84], get_frame_sizes(1510, 1500));
+    assert_eq!(vec![84], get_frame_sizes(1, 1500));
+}
+#[test]
+fn test_partial_frame_and_link_mtu() {
+    let mut flow = gen_flow_table().get_tsn(0.into()).unwrap().clone();
+    flow.size = 2000;
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], vec![(2, 100), (6, 100)]);
+    let mut gcl = GCL::new(100, 16);
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    // 1542 及 542 位元組的訊框，且第二個訊框要等第一個訊框傳完
+    assert_eq!(
+        vec![(0, 16), (16, 6)],
+        gcl.get_flow_gate_events(2, 0.into())
+    );
+    assert_eq!(
+        vec![(16, 16), (32, 6)],
+        gcl.get_flow_gate_events(6, 0.into())
+    );
+
+    // 連結 6 的 MTU 較小，整條路徑都要切成 1000 位元組的訊框
+    let mut configs = vec![PortConfig::default(); 16];
+    configs[6].mtu = 1000;
+    let mut gcl = GCL::with_port_configs(100, configs);
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(
+        vec![(0, 11), (11, 11)],
+        gcl.get_flow_gate_events(2, 0.into())
+    );
+}
+#[test]
+fn test_release_window() {
+    let mut flow = gen_flow_table().get_tsn(0.into()).unwrap().clone();
+    flow.max_delay = 5;
+    let gen_gcl = || {
+        let mut gcl = GCL::new(100, 16);
+        // 連結 6 在 0~10 之間被其它資料流佔用
+        gcl.insert_gate_evt(6, 9.into(), 0, 0, 10);
+        gcl
+    };
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow.clone()], vec![], gen_links(vec![2, 6]));
+    // 固定在時間 0 釋出，等連結 6 空出來就來不及了
+    let mut gcl = gen_gcl();
+    assert!(schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).is_err());
+
+    flow.spec_data.latest_offset = Some(20);
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], gen_links(vec![2, 6]));
+    // 延到時間 9 釋出，恰好在連結 6 空出來時抵達
+    let mut gcl = gen_gcl();
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(Some(9), gcl.get_release(0.into()));
+    assert_eq!(vec![(9, 1)], gcl.get_flow_gate_events(2, 0.into()));
+    assert_eq!(vec![(10, 1)], gcl.get_flow_gate_events(6, 0.into()));
+
+    let mut gcl = gen_gcl();
+    schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(Some(9), gcl.get_release(0.into()));
+}
+/// 三個資料流，在各排序策略下的順序都不同
+fn gen_order_table() -> FT {
+    let gen_flow = |size: usize, period: u32, max_delay: u32, offset: u32| TSNFlow {
+        id: 0.into(),
+        src: 0,
+        dst: 0,
+        size,
+        period,
+        max_delay,
+        spec_data: TSNData {
+            offset,
+            max_jitter: None,
+            latest_offset: None,
+        },
+    };
+    let bandwidth = MTU + FRAME_OVERHEAD;
+    let mut ft = FlowTable::new();
+    ft.insert(
+        vec![
+            // 傳輸時間 20、餘裕 30、頻寬比例 0.2、絕對死線 50
+            gen_flow(bandwidth * 10, 100, 50, 0),
+            // 傳輸時間 1、餘裕 39、頻寬比例 0.01、絕對死線 60
+            gen_flow(bandwidth, 100, 40, 20),
+            // 傳輸時間 6、餘裕 42、頻寬比例 0.12、絕對死線 48
+            gen_flow(bandwidth * 2, 50, 48, 0),
+        ],
+        vec![],
+        vec![],
+    );
+    ft.update_info(0.into(), gen_links(vec![0, 4]));
+    ft.update_info(1.into(), gen_links(vec![2]));
+    ft.update_info(2.into(), gen_links(vec![1, 5, 6]));
+    ft
+}
+#[test]
+fn test_sort_flows() {
+    let ft = gen_order_table();
+    let sorted = |order: TTOrder| {
+        let mut ids: Vec<FlowID> = vec![2.into(), 0.into(), 1.into()];
+        sort_flows(&mut ids, order, &ft, |_, info| to_links(info));
+        ids.into_iter().map(|id| id.into()).collect::<Vec<usize>>()
+    };
+    assert_eq!(vec![1, 2, 0], sorted(TTOrder::DeadlineMonotonic));
+    assert_eq!(vec![2, 0, 1], sorted(TTOrder::Edf));
+    assert_eq!(vec![0, 2, 1], sorted(TTOrder::Utilization));
+    assert_eq!(vec![0, 1, 2], sorted(TTOrder::MostConstrained));
+    let mut random = sorted(TTOrder::Random);
+    random.sort();
+    assert_eq!(vec![0, 1, 2], random);
+}
+#[test]
+fn test_cmp_flow_total_order() {
+    // 死線、週期與路徑長度都相同時，依編號排序
+    let flow = gen_flow_table().get_tsn(0.into()).unwrap().clone();
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow.clone(), flow], vec![], gen_links(vec![0, 4]));
+    ft.update_info(1.into(), gen_links(vec![2, 6]));
+    let cmp =
+        |id1: usize, id2: usize| cmp_flow(id1.into(), id2.into(), &ft, |_, info| to_links(info));
+    assert_eq!(Ordering::Less, cmp(0, 1));
+    assert_eq!(Ordering::Greater, cmp(1, 0));
+    assert_eq!(Ordering::Equal, cmp(0, 0));
+}
+#[test]
+fn test_retry_with_other_orders() {
+    let flow = gen_flow_table().get_tsn(0.into()).unwrap().clone();
+    // X 的死線較短，但比 Y 晚釋出；先排 X 的話，Y 的最後一個封包會來不及
+    let mut flow_x = flow.clone();
+    flow_x.max_delay = 5;
+    flow_x.spec_data.offset = 3;
+    let mut flow_y = flow;
+    flow_y.size = MTU * 4;
+    flow_y.max_delay = 6;
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow_x, flow_y], vec![], gen_links(vec![2]));
+    let schedule = |order: TTOrder, budget: u128| {
+        let mut gcl = GCL::new(100, 16);
+        let result = schedule_with_retry(&ft, &mut gcl, |_, info| to_links(info), order, budget);
+        (result, gcl)
+    };
+    assert!(schedule(TTOrder::DeadlineMonotonic, 0).0.is_err());
+    assert!(schedule(TTOrder::Edf, 0).0.is_ok());
+    // 失敗後改用 EDF 重試，且重試前會把排到一半的 GCL 還原
+    let (result, gcl) = schedule(TTOrder::DeadlineMonotonic, 1_000_000);
+    assert!(result.is_ok());
+    assert_valid(&ft, &gcl);
+    assert_eq!(vec![(4, 1)], gcl.get_flow_gate_events(2, 0.into()));
+    // 兩個資料流都只能在時間 0 傳，不論順序，後排的一定失敗；重試全部失敗時，GCL 還原為排程前的狀態
+    let mut flow_z = ft.get_tsn(0.into()).unwrap().clone();
+    flow_z.max_delay = 2;
+    flow_z.spec_data.offset = 0;
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow_z.clone(), flow_z], vec![], gen_links(vec![2]));
+    let mut gcl = GCL::new(100, 16);
+    gcl.insert_gate_evt(6, 9.into(), 3, 0, 1);
+    let og_gcl = gcl.clone();
+    let result = schedule_with_retry(
+        &ft,
+        &mut gcl,
+        |_, info| to_links(info),
+        TTOrder::DeadlineMonotonic,
+        1_000,
+    );
+    assert!(result.is_err());
+    assert_eq!(og_gcl.get_gate_events(6), gcl.get_gate_events(6));
+    assert_eq!(0, gcl.get_gate_events(2).len());
 }
 #[test]
 fn test_online_schedule() {
@@ -97,9 +358,390 @@ fn test_online_schedule() {
     let ft = gen_flow_table();
 
     schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
     //schedule_online(&ft, &ft, &mut gcl, |_, info| info);
     let ans: Vec<(u32, u32)> = vec![(0, 5), (150, 3), (203, 2), (300, 3), (403, 2), (450, 3)];
     assert_eq!(gcl.get_gate_events(2), &ans);
     //panic!("{:?}", gcl.get_gate_events(7));
     //panic!("{:?}", gcl.get_gate_events(6));
 }
+#[test]
+fn test_jitter() {
+    let mut gcl = GCL::new(600, 16);
+    let ft = gen_flow_table();
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    // 每個週期排得都一樣，三個封包依序抵達也不算抖動
+    for id in 0..4 {
+        let flow = ft.get_tsn(id.into()).unwrap();
+        let links = to_links(ft.get_info(id.into()).unwrap());
+        assert_eq!(0, compute_jitter(flow, &links, &gcl, false));
+    }
+    // 啟用搶佔時，最後一個連結上可能被一個片段阻擋
+    let flow = ft.get_tsn(1.into()).unwrap();
+    let links = to_links(ft.get_info(1.into()).unwrap());
+    assert_eq!(1, compute_jitter(flow, &links, &gcl, true));
+
+    // 兩個週期中同一個封包的延遲不同：第一個封包是 1 與 6，第二個封包是 2 與 8
+    let flow = ft.get_tsn(0.into()).unwrap();
+    let mut gcl = GCL::new(200, 16);
+    for &start in [0, 1, 105, 107].iter() {
+        gcl.insert_gate_evt(2, 0.into(), 0, start, 1);
+    }
+    assert_eq!(
+        6,
+        compute_jitter(flow, &gen_links_f64(vec![2]), &gcl, false)
+    );
+}
+#[test]
+fn test_jitter_constraint() {
+    let og_table = gen_flow_table();
+    let mut flow0 = og_table.get_tsn(0.into()).unwrap().clone();
+    let mut flow1 = og_table.get_tsn(1.into()).unwrap().clone();
+    flow0.spec_data.max_jitter = Some(0);
+    flow1.spec_data.max_jitter = Some(0);
+
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow0], vec![], gen_links(vec![0, 4]));
+    let mut gcl = GCL::new(600, 16);
+    assert!(schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).is_ok());
+
+    // 三個封包依序抵達，但每個週期都一樣，沒有抖動
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow1.clone()], vec![], gen_links(vec![2, 6]));
+    let mut gcl = GCL::new(600, 16);
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(3 * 4, gcl.get_flow_gate_events(6, 0.into()).len());
+
+    // 啟用搶佔時，最後一個連結上的阻擋使抖動超標；換過所有佇列仍不行，回報最後一個連結，且不留下任何閘門事件
+    let mut flow1 = ft.get_tsn(0.into()).unwrap().clone();
+    let links = to_links(ft.get_info(0.into()).unwrap());
+    let frames = get_flow_frames(&flow1, &links, &gcl);
+    let tt_queues = vec![PortConfig::default().tt_queues; links.len()];
+    let mut gcl = GCL::new(600, 16);
+    let error = ScheduleError::new(0.into(), Some(6), FailReason::JitterExceeded);
+    assert_eq!(
+        Err(error),
+        assign_offsets(&plan(&flow1, &frames, &links, true), &tt_queues, &mut gcl)
+    );
+    assert_eq!(0, gcl.get_gate_events(2).len());
+    assert_eq!(0, gcl.get_gate_events(6).len());
+    flow1.spec_data.max_jitter = Some(1);
+    assert_eq!(
+        Ok(()),
+        assign_offsets(&plan(&flow1, &frames, &links, true), &tt_queues, &mut gcl)
+    );
+}
+#[test]
+fn test_assign_new_queues() {
+    let all_queues: Vec<Vec<u8>> = vec![(0..MAX_QUEUE).collect(); 3];
+    let mut ro = vec![0, 0, 0];
+    // 卡在第 0 個連結，代表下一個埠口的佇列有衝突
+    assign_new_queues(&mut ro, 0, &all_queues).unwrap();
+    assert_eq!(vec![0, 1, 0], ro);
+    assign_new_queues(&mut ro, 2, &all_queues).unwrap();
+    assert_eq!(vec![0, 1, 1], ro);
+    // 目標埠口的佇列用完了，就重設它並往前進位
+    ro[2] = MAX_QUEUE - 1;
+    assign_new_queues(&mut ro, 2, &all_queues).unwrap();
+    assert_eq!(vec![0, 2, 0], ro);
+    // 進位時，目標埠口之後的埠口也要重設
+    let mut ro = vec![0, 0, 3];
+    assign_new_queues(&mut ro, 0, &all_queues).unwrap();
+    assert_eq!(vec![0, 1, 0], ro);
+    // 目標埠口及其前面都用完了，後面的埠口怎麼換都沒用
+    let mut ro = vec![0, MAX_QUEUE - 1, 0];
+    assert!(assign_new_queues(&mut ro, 0, &all_queues).is_err());
+    // 全部用完
+    let mut ro = vec![0, MAX_QUEUE - 1, MAX_QUEUE - 1];
+    assert!(assign_new_queues(&mut ro, 1, &all_queues).is_err());
+    // 只有一個埠口時，換佇列也沒有用
+    assert!(assign_new_queues(&mut vec![0], 0, &all_queues).is_err());
+    // 只能換到保留給 TT 的佇列
+    let tt_queues = vec![vec![3], vec![3, 5], vec![3]];
+    let mut ro = vec![3, 3, 3];
+    assign_new_queues(&mut ro, 0, &tt_queues).unwrap();
+    assert_eq!(vec![3, 5, 3], ro);
+    assert!(assign_new_queues(&mut ro, 0, &tt_queues).is_err());
+}
+#[test]
+fn test_assign_queue_combination() {
+    let og_table = gen_flow_table();
+    let mut flow = og_table.get_tsn(0.into()).unwrap().clone();
+    flow.max_delay = 10;
+    let links = gen_links_f64(vec![0, 4, 5]);
+    let tt_queues = vec![vec![0], vec![0, 1], vec![0, 1]];
+    let mut gcl = GCL::new(100, 16);
+    // 連結 4 的 0 號佇列短暫被佔住，用它會讓封包晚到連結 5，那時連結 5 的兩個佇列都被佔住
+    gcl.insert_queue_evt(4, 9.into(), 0, 1, 2);
+    gcl.insert_queue_evt(5, 9.into(), 0, 5, 10);
+    gcl.insert_queue_evt(5, 9.into(), 1, 0, 20);
+    // 唯一可行的是連結 4 用 1 號、連結 5 用 0 號佇列：連結 5 的佇列用完後要重設，不能直接跳過
+    let frames = get_flow_frames(&flow, &links, &gcl);
+    assert_eq!(
+        Ok(()),
+        assign_offsets(&plan(&flow, &frames, &links, false), &tt_queues, &mut gcl)
+    );
+    assert_eq!(1, gcl.get_queueid(4, flow.id));
+    assert_eq!(0, gcl.get_queueid(5, flow.id));
+    assert_eq!(&vec![(2, 1)], gcl.get_gate_events(5));
+}
+#[test]
+fn test_per_port_queue() {
+    let og_table = gen_flow_table();
+    let flow = og_table.get_tsn(0.into()).unwrap().clone();
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], gen_links(vec![2, 6]));
+    let mut gcl = GCL::new(100, 16);
+    // 其它資料流長期佔住連結 6 的 3 號佇列（第一個保留給 TT 的佇列），等它空出來就來不及了
+    gcl.insert_queue_evt(6, 9.into(), 3, 0, 99);
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    // 只有被佔住的埠口換佇列
+    assert_eq!(3, gcl.get_queueid(2, 0.into()));
+    assert_eq!(4, gcl.get_queueid(6, 0.into()));
+    assert_eq!(&vec![(0, 1)], gcl.get_gate_events(2));
+}
+#[test]
+fn test_tt_queue_reservation() {
+    let og_table = gen_flow_table();
+    let flow = og_table.get_tsn(0.into()).unwrap().clone();
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], gen_links(vec![2, 6]));
+    // 連結 6 位於 5 個佇列的交換機上，只有 3、4 號佇列保留給 TT
+    let mut configs = vec![PortConfig::default(); 16];
+    configs[6] = PortConfig::with_queue_cnt(5).unwrap();
+    let mut gcl = GCL::with_port_configs(100, configs.clone());
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_eq!(3, gcl.get_queueid(2, 0.into()));
+    assert_eq!(3, gcl.get_queueid(6, 0.into()));
+    // 3 號佇列被佔住，只能換到 4 號
+    let mut gcl = GCL::with_port_configs(100, configs.clone());
+    gcl.insert_queue_evt(6, 9.into(), 3, 0, 99);
+    schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_eq!(4, gcl.get_queueid(6, 0.into()));
+    // 4 個佇列的交換機只有一個 TT 佇列，被佔住就排不進去
+    configs[6] = PortConfig::with_queue_cnt(4).unwrap();
+    let mut gcl = GCL::with_port_configs(100, configs);
+    gcl.insert_queue_evt(6, 9.into(), 3, 0, 99);
+    assert!(schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).is_err());
+    assert!(schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).is_err());
+}
+#[test]
+fn test_schedule_error() {
+    let og_table = gen_flow_table();
+    let flow0 = og_table.get_tsn(0.into()).unwrap().clone();
+    let flow1 = og_table.get_tsn(1.into()).unwrap().clone();
+    let schedule = |ft: &FT, gcl: &mut GCL| schedule_fixed_og(ft, gcl, |_, info| to_links(info));
+    // 連結 0 長期被佔住，等到空檔就來不及了
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow0.clone()], vec![], gen_links(vec![0]));
+    let mut gcl = GCL::new(100, 16);
+    gcl.insert_gate_evt(0, 9.into(), 0, 0, 99);
+    let error = ScheduleError::new(0.into(), Some(0), FailReason::DeadlineMiss);
+    assert_eq!(Err(error), schedule(&ft, &mut gcl));
+    // 連結 6 唯一的 TT 佇列被佔住
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow0], vec![], gen_links(vec![2, 6]));
+    let mut configs = vec![PortConfig::default(); 16];
+    configs[6] = PortConfig::with_queue_cnt(4).unwrap();
+    let mut gcl = GCL::with_port_configs(100, configs);
+    gcl.insert_queue_evt(6, 9.into(), 3, 0, 99);
+    let error = ScheduleError::new(0.into(), Some(6), FailReason::NoFreeQueue);
+    assert_eq!(Err(error), schedule(&ft, &mut gcl));
+    // 回溯搜索不分辨原因，只回報封包卡在哪個連結上送不出去
+    let error = ScheduleError::new(0.into(), Some(2), FailReason::SearchExhausted);
+    assert_eq!(
+        Err(error),
+        schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info))
+    );
+}
+#[test]
+fn test_reschedule_conflicts_only() {
+    let og_table = gen_flow_table();
+    let flow_a = og_table.get_tsn(0.into()).unwrap().clone();
+    let flow_c = og_table.get_tsn(1.into()).unwrap().clone();
+    let mut flow_b = flow_a.clone();
+    flow_b.max_delay = 2;
+
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow_a, flow_c, flow_b], vec![], gen_links(vec![0, 4]));
+    ft.update_info(1.into(), gen_links(vec![2, 6]));
+    ft.update_info(2.into(), gen_links(vec![0]));
+    let mut gcl = GCL::new(600, 16);
+    let mut init = ft.clone_as_diff();
+    init.update_info_force(0.into(), gen_links(vec![0, 4]));
+    init.update_info_force(1.into(), gen_links(vec![2, 6]));
+    schedule_fixed_og(&init, &mut gcl, |_, info| to_links(info)).unwrap();
+    let og_evts_c = gcl.get_gate_events(6).clone();
+
+    // 資料流 B 的死線很緊，只能搶走 A 的時段
+    let mut changed = ft.clone_as_diff();
+    changed.update_info_force(2.into(), gen_links(vec![0]));
+    let disrupted = schedule_online(&mut ft, &changed, &mut gcl, |_, info| to_links(info));
+    assert_eq!(Ok(vec![0.into()]), disrupted);
+    assert_valid(&ft, &gcl);
+    assert_eq!(0, gcl.get_flow_gate_events(0, 2.into())[0].0);
+    assert_eq!(1, gcl.get_flow_gate_events(0, 0.into())[0].0);
+    // 與 B 不共用連結的 C 不受影響
+    assert_eq!(&og_evts_c, gcl.get_gate_events(6));
+}
+#[test]
+fn test_backtrack_finds_what_greedy_misses() {
+    let og_table = gen_flow_table();
+    // A 只走連結 0，B 從連結 0 出發，共經過 7 個連結；A 的死線較緊所以先排
+    let mut flow_a = og_table.get_tsn(0.into()).unwrap().clone();
+    flow_a.max_delay = 7;
+    let mut flow_b = flow_a.clone();
+    flow_b.max_delay = 8;
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow_a, flow_b], vec![], gen_links(vec![0]));
+    ft.update_info(1.into(), gen_links(vec![0, 4, 5, 6, 7, 8, 9]));
+    let gen_gcl = || {
+        let mut gcl = GCL::new(100, 16);
+        // 連結 0 在 2~4 之間被其它資料流佔用
+        gcl.insert_gate_evt(0, 9.into(), 0, 2, 2);
+        gcl
+    };
+
+    // 貪婪算法讓 A 搶了時間 0，B 就來不及了
+    let mut gcl = gen_gcl();
+    assert!(schedule_fixed_og(&ft, &mut gcl, |_, info| to_links(info)).is_err());
+
+    // A 其實可以讓到時間 4
+    let mut gcl = gen_gcl();
+    schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(vec![(4, 1)], gcl.get_flow_gate_events(0, 0.into()));
+    assert_eq!(vec![(0, 1)], gcl.get_flow_gate_events(0, 1.into()));
+    assert_eq!(vec![(1, 1)], gcl.get_flow_gate_events(4, 1.into()));
+    assert_eq!(vec![(6, 1)], gcl.get_flow_gate_events(9, 1.into()));
+
+    // A 在時間 0 時，B 從時間 1 出發必定趕不上死線，往前檢查直接剪掉，共只需試 11 個時間點
+    let mut gcl = gen_gcl();
+    let result = schedule_backtrack_with_budget(&ft, &mut gcl, |_, info| to_links(info), 11);
+    assert_eq!(Ok(()), result);
+    // 達到搜索上限時，與試完所有候選分開回報
+    let mut gcl = gen_gcl();
+    let result = schedule_backtrack_with_budget(&ft, &mut gcl, |_, info| to_links(info), 10);
+    let error = ScheduleError::new(1.into(), Some(9), FailReason::BudgetExhausted);
+    assert_eq!(Err(error), result);
+}
+#[test]
+fn test_backtrack_schedule() {
+    // 貪婪算法排得出來的，回溯搜索也要排得出來
+    let mut gcl = GCL::new(600, 16);
+    let ft = gen_flow_table();
+    schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    for (flow, info) in ft.iter_tsn() {
+        let links = to_links(info);
+        let k = get_flow_frames(flow, &links, &gcl).len();
+        for &(link_id, _) in links.iter() {
+            let evts = gcl.get_flow_gate_events(link_id, flow.id);
+            assert_eq!(k * (600 / flow.period) as usize, evts.len());
+        }
+    }
+}
+#[test]
+fn test_gcl_len_limit() {
+    let og_table = gen_flow_table();
+    let flow = og_table.get_tsn(0.into()).unwrap().clone();
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], gen_links(vec![0, 4]));
+    // 在連結 4 上的閘門與頭尾的空檔，壓縮後共兩筆
+    let mut configs = vec![PortConfig::default(); 16];
+    configs[4].max_gcl_len = Some(1);
+    let mut gcl = GCL::with_port_configs(100, configs.clone());
+    let error = ScheduleError::new(0.into(), Some(4), FailReason::GclOverflow);
+    assert_eq!(
+        Err(error),
+        schedule_offline(&ft, &mut gcl, |_, info| to_links(info))
+    );
+    configs[4].max_gcl_len = Some(2);
+    let mut gcl = GCL::with_port_configs(100, configs);
+    schedule_offline(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_eq!(2, gcl.get_compact_gate_list(4).len());
+}
+#[test]
+fn test_gcl_len_alignment() {
+    let og_table = gen_flow_table();
+    let mut flow = og_table.get_tsn(0.into()).unwrap().clone();
+    flow.spec_data.latest_offset = Some(60);
+    let mut ft = FlowTable::new();
+    ft.insert(vec![flow], vec![], gen_links(vec![4]));
+    let mut configs = vec![PortConfig::default(); 16];
+    configs[4].max_gcl_len = Some(2);
+    for backtrack in [false, true] {
+        let mut gcl = GCL::with_port_configs(100, configs.clone());
+        gcl.insert_gate_evt(4, 9.into(), 3, 50, 1);
+        // 最早在 0 開始傳的話，清單為 TT、非 TT、TT、非 TT 共四筆；
+        // 緊接在既有的閘門時段之後傳，則兩個 TT 時段合併，頭尾的非 TT 時段也合併，只剩兩筆
+        if backtrack {
+            schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+        } else {
+            schedule_offline(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+        }
+        assert_eq!(vec![(51, 1)], gcl.get_flow_gate_events(4, 0.into()));
+        assert_eq!(2, gcl.get_compact_gate_list(4).len());
+    }
+    // 怎麼對齊都放不下，GCL 維持原狀
+    configs[4].max_gcl_len = Some(1);
+    let mut gcl = GCL::with_port_configs(100, configs);
+    gcl.insert_gate_evt(4, 9.into(), 3, 50, 1);
+    let error = ScheduleError::new(0.into(), Some(4), FailReason::GclOverflow);
+    assert_eq!(
+        Err(error),
+        schedule_offline(&ft, &mut gcl, |_, info| to_links(info))
+    );
+    assert!(gcl.get_flow_gate_events(4, 0.into()).is_empty());
+}
+#[test]
+fn test_backtrack_not_left_aligned() {
+    // A 只走連結 0，B 從連結 0 出發，共經過 7 個連結且必需在時間 0 出發；A 的死線較緊所以先排
+    let gen_table = |max_delay_a| {
+        let og_table = gen_flow_table();
+        let mut flow_a = og_table.get_tsn(0.into()).unwrap().clone();
+        flow_a.max_delay = max_delay_a;
+        let mut flow_b = flow_a.clone();
+        flow_b.max_delay = 8;
+        let mut ft = FlowTable::new();
+        ft.insert(vec![flow_a, flow_b], vec![], gen_links(vec![0]));
+        ft.update_info(1.into(), gen_links(vec![0, 4, 5, 6, 7, 8, 9]));
+        ft
+    };
+    let ft = gen_table(4);
+
+    // 連結上沒有任何閘門事件，A 左對齊的候選只有時間 0，B 就來不及了；
+    // A 必需往後讓出空檔，這個時間點要逐一嘗試才找得到
+    let mut gcl = GCL::new(100, 16);
+    schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info)).unwrap();
+    assert_valid(&ft, &gcl);
+    assert_eq!(vec![(0, 1)], gcl.get_flow_gate_events(0, 1.into()));
+    let evts_a = gcl.get_flow_gate_events(0, 0.into());
+    assert_eq!(1, evts_a.len());
+    assert!(evts_a[0].0 > 0);
+
+    // 真的沒有可行解時，試完所有時間點就回報
+    let ft = gen_table(1);
+    let mut gcl = GCL::new(100, 16);
+    let result = schedule_backtrack(&ft, &mut gcl, |_, info| to_links(info));
+    assert_eq!(FailReason::SearchExhausted, result.unwrap_err().reason);
+}
+#[test]
+fn test_guard_band_offsets() {
+    let mut gcl = GCL::new(60, 16);
+    gcl.set_guard_bands(vec![1; 16]);
+    let ft = gen_flow_table();
+    // 有保護帶就不會被低優先度的訊框阻擋，封包一抵達即可轉送
+    let flow = ft.get_tsn(2.into()).unwrap();
+    let links = to_links(ft.get_info(2.into()).unwrap());
+    let frames = get_flow_frames(flow, &links, &gcl);
+    let a = calculate_offsets(
+        &plan(&flow, &frames, &links, false),
+        &vec![],
+        &vec![0; 3],
+        &gcl,
+    );
+    assert_eq!(Ok(vec![0, 1, 2]), a);
+}
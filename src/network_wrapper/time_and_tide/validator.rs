@@ -0,0 +1,448 @@
+use super::{FRAME_OVERHEAD, MIN_PAYLOAD};
+use crate::flow::{FlowID, TSNFlow};
+use crate::graph_util::DEFAULT_MTU;
+use crate::recorder::{flow_table::prelude::*, GCL};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type Links = Vec<(usize, f64)>;
+
+/// 排程違反的規則
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Violation {
+    /// 同一個連結上，兩個閘門事件的時間重疊
+    GateOverlap {
+        link_id: usize,
+        first: (u32, u32, FlowID),
+        second: (u32, u32, FlowID),
+    },
+    /// 超週期中某個連結上的封包數量不對（週期實例缺漏或多出來）
+    InstanceCount {
+        flow_id: FlowID,
+        link_id: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// 閘門開啟的時間不夠把封包傳完
+    GateTooShort {
+        flow_id: FlowID,
+        link_id: usize,
+        instance: usize,
+        frame: usize,
+        duration: u32,
+        trans_time: u32,
+    },
+    /// 封包還沒抵達（或還沒釋出）就被送出
+    SentBeforeArrival {
+        flow_id: FlowID,
+        link_id: usize,
+        instance: usize,
+        frame: usize,
+        send_time: u32,
+        arrive_time: u32,
+    },
+    /// 封包抵達終點的時間超過死線
+    DeadlineMiss {
+        flow_id: FlowID,
+        instance: usize,
+        frame: usize,
+        finish_time: u32,
+        deadline: u32,
+    },
+    /// 資料流採用的釋出時間不在其釋出窗口內
+    ReleaseOutOfWindow {
+        flow_id: FlowID,
+        release: u32,
+        window: (u32, u32),
+    },
+    /// 兩個資料流同時佔用同一個埠口的同一個佇列
+    QueueConflict {
+        link_id: usize,
+        queue_id: u8,
+        flows: (FlowID, FlowID),
+        time: u32,
+    },
+}
+
+impl Violation {
+    /// 違反規則的連結，與特定連結無關者（如死線、釋出窗口）為 `None`
+    pub fn get_link_id(&self) -> Option<usize> {
+        match *self {
+            Violation::GateOverlap { link_id, .. }
+            | Violation::InstanceCount { link_id, .. }
+            | Violation::GateTooShort { link_id, .. }
+            | Violation::SentBeforeArrival { link_id, .. }
+            | Violation::QueueConflict { link_id, .. } => Some(link_id),
+            Violation::DeadlineMiss { .. } | Violation::ReleaseOutOfWindow { .. } => None,
+        }
+    }
+}
+
+/// 獨立於排程算法，只從 GCL 的閘門事件檢查排程是否合法，回傳所有違反的規則
+///
+/// 訊框的切割也不沿用排程算法，而是由路徑上最小的 MTU 自行推得（見 `get_frame_trans_times`）。
+/// 佇列的佔用時間由閘門事件及其佇列推得：封包從前一個埠口開始傳輸起，直到在這個埠口開始傳輸為止。
+/// 路徑的第一個埠口位於終端上，不在交換機的排程範圍內，故不檢查其佇列。
+pub fn validate_schedule<
+    T: Eq + Clone,
+    TABLE: IFlowTable<INFO = T>,
+    F: Fn(&TSNFlow, &T) -> Links,
+>(
+    table: &TABLE,
+    gcl: &GCL,
+    get_links: F,
+) -> Vec<Violation> {
+    let mut violations = vec![];
+    let hyper_p = gcl.get_hyper_p();
+    // (link_id, queue_id) -> 所有佔用區間 (開始, 結束, 資料流)
+    let mut queue_occupy = HashMap::<(usize, u8), Vec<(u32, u32, FlowID)>>::new();
+    let mut all_links = vec![];
+    for (flow, info) in table.iter_tsn() {
+        let links = get_links(flow, info);
+        // 每個連結上該資料流的閘門事件 (開始, 持續, 佇列)
+        let evts: Vec<Vec<(u32, u32, u8)>> = links
+            .iter()
+            .map(|&(link_id, _)| {
+                gcl.get_raw_gate_events(link_id)
+                    .into_iter()
+                    .filter(|evt| evt.3 == flow.id)
+                    .map(|(start, duration, queue_id, _)| (start, duration, queue_id))
+                    .collect()
+            })
+            .collect();
+        all_links.extend(links.iter().map(|&(link_id, _)| link_id));
+
+        let trans_times = get_frame_trans_times(flow, &links, gcl);
+        let frame_cnt = trans_times.len();
+        let expected = (hyper_p / flow.period) as usize * frame_cnt;
+        let mut complete = hyper_p.is_multiple_of(flow.period);
+        for (i, &(link_id, _)) in links.iter().enumerate() {
+            if evts[i].len() != expected {
+                complete = false;
+                violations.push(Violation::InstanceCount {
+                    flow_id: flow.id,
+                    link_id,
+                    expected,
+                    found: evts[i].len(),
+                });
+            }
+        }
+        if !complete {
+            // 封包對不起來，無法再往下檢查
+            continue;
+        }
+
+        let offset = gcl.get_release(flow.id).unwrap_or(flow.spec_data.offset);
+        let window = (flow.spec_data.offset, flow.spec_data.get_latest_offset());
+        if offset < window.0 || offset > window.1 {
+            violations.push(Violation::ReleaseOutOfWindow {
+                flow_id: flow.id,
+                release: offset,
+                window,
+            });
+        }
+        for (j, &(start, _, _)) in evts[0].iter().enumerate() {
+            let (instance, frame) = (j / frame_cnt, j % frame_cnt);
+            let release = instance as u32 * flow.period + offset;
+            if start < release {
+                violations.push(Violation::SentBeforeArrival {
+                    flow_id: flow.id,
+                    link_id: links[0].0,
+                    instance,
+                    frame,
+                    send_time: start,
+                    arrive_time: release,
+                });
+            }
+            for (i, &(link_id, _)) in links.iter().enumerate() {
+                let duration = evts[i][j].1;
+                if duration < trans_times[frame][i] {
+                    violations.push(Violation::GateTooShort {
+                        flow_id: flow.id,
+                        link_id,
+                        instance,
+                        frame,
+                        duration,
+                        trans_time: trans_times[frame][i],
+                    });
+                }
+            }
+            for i in 1..links.len() {
+                let (prev_start, prev_duration, _) = evts[i - 1][j];
+                let (cur_start, _, queue_id) = evts[i][j];
+                if cur_start < prev_start + prev_duration {
+                    violations.push(Violation::SentBeforeArrival {
+                        flow_id: flow.id,
+                        link_id: links[i].0,
+                        instance,
+                        frame,
+                        send_time: cur_start,
+                        arrive_time: prev_start + prev_duration,
+                    });
+                }
+                queue_occupy
+                    .entry((links[i].0, queue_id))
+                    .or_insert(vec![])
+                    .push((prev_start, cur_start, flow.id));
+            }
+            let (last_start, last_duration, _) = evts[links.len() - 1][j];
+            let deadline = release + flow.max_delay;
+            if last_start + last_duration > deadline {
+                violations.push(Violation::DeadlineMiss {
+                    flow_id: flow.id,
+                    instance,
+                    frame,
+                    finish_time: last_start + last_duration,
+                    deadline,
+                });
+            }
+        }
+    }
+
+    all_links.sort();
+    all_links.dedup();
+    for link_id in all_links.into_iter() {
+        let mut evts = gcl.get_raw_gate_events(link_id);
+        evts.sort();
+        // 目前為止結束得最晚的事件，一個長事件可能與其後好幾個事件重疊
+        let mut latest: Option<(u32, u32, FlowID)> = None;
+        for &(start, duration, _, flow_id) in evts.iter() {
+            if let Some((s1, d1, f1)) = latest {
+                if s1 + d1 > start {
+                    violations.push(Violation::GateOverlap {
+                        link_id,
+                        first: (s1, d1, f1),
+                        second: (start, duration, flow_id),
+                    });
+                }
+                if start + duration <= s1 + d1 {
+                    continue;
+                }
+            }
+            latest = Some((start, duration, flow_id));
+        }
+    }
+
+    let mut queue_keys: Vec<(usize, u8)> = queue_occupy.keys().cloned().collect();
+    queue_keys.sort();
+    for key in queue_keys.into_iter() {
+        let intervals = queue_occupy.get_mut(&key).unwrap();
+        intervals.sort();
+        // 目前為止結束得最晚的區間
+        let mut latest: Option<(u32, FlowID)> = None;
+        for &(start, end, flow_id) in intervals.iter() {
+            if let Some((latest_end, latest_flow)) = latest {
+                if latest_flow != flow_id && start < latest_end {
+                    violations.push(Violation::QueueConflict {
+                        link_id: key.0,
+                        queue_id: key.1,
+                        flows: (latest_flow, flow_id),
+                        time: start,
+                    });
+                }
+                if end <= latest_end {
+                    continue;
+                }
+            }
+            latest = Some((end, flow_id));
+        }
+    }
+    violations
+}
+
+/// 資料流每個訊框在路徑上每個連結的傳輸時間（`[訊框][連結]`）
+///
+/// 一路上都不能切割，故以路徑上最小的 MTU 來切；最後一個訊框的酬載不足 `MIN_PAYLOAD` 時會補齊。
+fn get_frame_trans_times(flow: &TSNFlow, links: &Links, gcl: &GCL) -> Vec<Vec<u32>> {
+    let mtu = links
+        .iter()
+        .map(|&(link_id, _)| gcl.get_port_config(link_id).mtu)
+        .min()
+        .unwrap_or(DEFAULT_MTU);
+    let mut trans_times = vec![];
+    let mut remaining = flow.size;
+    while remaining > 0 {
+        let payload = std::cmp::min(remaining, mtu);
+        remaining -= payload;
+        let wire_size = (std::cmp::max(payload, MIN_PAYLOAD) + FRAME_OVERHEAD) as f64;
+        let times = links
+            .iter()
+            .map(|&(_, bandwidth)| (wire_size / bandwidth).ceil() as u32)
+            .collect();
+        trans_times.push(times);
+    }
+    trans_times
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flow::data::TSNData;
+    use crate::graph_util::PortConfig;
+
+    fn gen_table() -> FlowTable<Vec<usize>> {
+        let flow = TSNFlow {
+            id: 0.into(),
+            src: 0,
+            dst: 1,
+            size: 1500,
+            period: 100,
+            max_delay: 10,
+            spec_data: TSNData {
+                offset: 0,
+                max_jitter: None,
+                latest_offset: None,
+            },
+        };
+        let mut table = FlowTable::new();
+        table.insert(vec![flow.clone(), flow], vec![], vec![0, 1]);
+        table.update_info(1.into(), vec![2, 1]);
+        table
+    }
+    fn validate(table: &FlowTable<Vec<usize>>, gcl: &GCL) -> Vec<Violation> {
+        validate_schedule(table, gcl, |_, links| {
+            links.iter().map(|&id| (id, 1542.0)).collect()
+        })
+    }
+    #[test]
+    fn test_valid_schedule() {
+        let table = gen_table();
+        let mut gcl = GCL::new(200, 3);
+        for &(start, link_id, flow_id, queue_id) in
+            [(0, 0, 0, 0), (2, 1, 0, 1), (0, 2, 1, 0), (3, 1, 1, 2)].iter()
+        {
+            for &shift in [0, 100].iter() {
+                gcl.insert_gate_evt(link_id, flow_id.into(), queue_id, shift + start, 1);
+            }
+        }
+        assert_eq!(Vec::<Violation>::new(), validate(&table, &gcl));
+    }
+    #[test]
+    fn test_queue_conflict() {
+        let table = gen_table();
+        let mut gcl = GCL::new(100, 3);
+        // 資料流 1 在連結 1 的佇列 1 中時，資料流 0 也還在裡面等
+        gcl.insert_gate_evt(0, 0.into(), 0, 0, 1);
+        gcl.insert_gate_evt(1, 0.into(), 1, 5, 1);
+        gcl.insert_gate_evt(2, 1.into(), 0, 0, 1);
+        gcl.insert_gate_evt(1, 1.into(), 1, 1, 1);
+        assert_eq!(
+            vec![Violation::QueueConflict {
+                link_id: 1,
+                queue_id: 1,
+                flows: (1.into(), 0.into()),
+                time: 0
+            }],
+            validate(&table, &gcl)
+        );
+    }
+    #[test]
+    fn test_timing_violations() {
+        let table = gen_table();
+        let mut gcl = GCL::new(200, 3);
+        // 資料流 0 的第二個週期缺漏
+        gcl.insert_gate_evt(0, 0.into(), 0, 0, 1);
+        gcl.insert_gate_evt(1, 0.into(), 0, 2, 1);
+        // 資料流 1 第一個週期在連結 1 上還沒抵達就送出，第二個週期超過死線
+        for &(link_id, start) in [(2, 0), (1, 0), (2, 100), (1, 150)].iter() {
+            gcl.insert_gate_evt(link_id, 1.into(), 1, start, 1);
+        }
+        assert_eq!(
+            vec![
+                Violation::InstanceCount {
+                    flow_id: 0.into(),
+                    link_id: 0,
+                    expected: 2,
+                    found: 1
+                },
+                Violation::InstanceCount {
+                    flow_id: 0.into(),
+                    link_id: 1,
+                    expected: 2,
+                    found: 1
+                },
+                Violation::SentBeforeArrival {
+                    flow_id: 1.into(),
+                    link_id: 1,
+                    instance: 0,
+                    frame: 0,
+                    send_time: 0,
+                    arrive_time: 1
+                },
+                Violation::DeadlineMiss {
+                    flow_id: 1.into(),
+                    instance: 1,
+                    frame: 0,
+                    finish_time: 151,
+                    deadline: 110
+                },
+            ],
+            validate(&table, &gcl)
+        );
+    }
+    #[test]
+    fn test_gate_overlap() {
+        let table = gen_table();
+        let mut gcl = GCL::new(100, 3);
+        gcl.insert_gate_evt(1, 0.into(), 0, 2, 1);
+        // GCL 只檢查與前一個事件是否重疊，插在前面的事件就檢查不到
+        gcl.insert_gate_evt(1, 1.into(), 0, 1, 3);
+        let violations = validate(&table, &gcl);
+        assert!(violations.contains(&Violation::GateOverlap {
+            link_id: 1,
+            first: (1, 3, 1.into()),
+            second: (2, 1, 0.into())
+        }));
+        // 長事件與其後不相鄰的事件重疊
+        let mut gcl = GCL::new(100, 3);
+        gcl.insert_gate_evt(2, 8.into(), 0, 5, 1);
+        gcl.insert_gate_evt(2, 9.into(), 0, 2, 1);
+        gcl.insert_gate_evt(2, 1.into(), 0, 0, 10);
+        let violations = validate(&table, &gcl);
+        for &second in [(2, 1, 9.into()), (5, 1, 8.into())].iter() {
+            assert!(violations.contains(&Violation::GateOverlap {
+                link_id: 2,
+                first: (0, 10, 1.into()),
+                second
+            }));
+        }
+    }
+    #[test]
+    fn test_frame_split() {
+        // 連結 1 的 MTU 較小，資料流一路上都要切成兩個訊框（酬載 1000 與 500）
+        let flow = gen_table().get_tsn(0.into()).unwrap().clone();
+        let mut table = FlowTable::new();
+        table.insert(vec![flow], vec![], vec![0, 1]);
+        let mut configs = vec![PortConfig::default(); 3];
+        configs[1].mtu = 1000;
+        let mut gcl = GCL::with_port_configs(100, configs.clone());
+        for &(link_id, start) in [(0, 0), (0, 1), (1, 2), (1, 3)].iter() {
+            gcl.insert_gate_evt(link_id, 0.into(), 0, start, 1);
+        }
+        assert_eq!(Vec::<Violation>::new(), validate(&table, &gcl));
+        // 頻寬減半時，第一個訊框要傳兩單位時間
+        let violations = validate_schedule(&table, &gcl, |_, links| {
+            links.iter().map(|&id| (id, 771.0)).collect()
+        });
+        let too_short = |link_id| Violation::GateTooShort {
+            flow_id: 0.into(),
+            link_id,
+            instance: 0,
+            frame: 0,
+            duration: 1,
+            trans_time: 2,
+        };
+        assert_eq!(vec![too_short(0), too_short(1)], violations);
+        // 只排了一個訊框
+        let mut gcl = GCL::with_port_configs(100, configs);
+        gcl.insert_gate_evt(0, 0.into(), 0, 0, 1);
+        gcl.insert_gate_evt(1, 0.into(), 0, 1, 1);
+        let missing = |link_id| Violation::InstanceCount {
+            flow_id: 0.into(),
+            link_id,
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!(vec![missing(0), missing(1)], validate(&table, &gcl));
+    }
+}
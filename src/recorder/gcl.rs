@@ -1,9 +1,13 @@
 use super::super::flow::FlowID;
+use super::interval_map::IntervalMap;
+use crate::graph_util::PortConfig;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::MAX_QUEUE;
+use crate::MAX_HYPER_P;
 
-fn gcd(a: u32, b: u32) -> u32 {
+fn gcd(a: u64, b: u64) -> u64 {
     if a < b {
         gcd(b, a)
     } else if b == 0 {
@@ -12,83 +16,466 @@ fn gcd(a: u32, b: u32) -> u32 {
         gcd(b, a % b)
     }
 }
-fn lcm(a: u32, b: u32) -> u32 {
+/// 以 u64 計算，避免兩個互質的大週期相乘後溢位
+pub(crate) fn lcm(a: u64, b: u64) -> u64 {
     let g = gcd(a, b);
     (a / g) * b
 }
 
 #[cfg(test)]
 mod test {
-    use super::lcm;
+    use super::*;
     #[test]
     fn test_lcm() {
         assert_eq!(36, lcm(4, 9));
         assert_eq!(81, lcm(27, 81));
         assert_eq!(84, lcm(12, 21));
     }
+    #[test]
+    fn test_update_hyper_p() {
+        let mut gcl = GCL::new(100, 2);
+        gcl.insert_gate_evt(0, 0.into(), 0, 10, 5);
+        gcl.insert_queue_evt(1, 0.into(), 3, 0, 10);
+        gcl.update_hyper_p(150).unwrap();
+        assert_eq!(300, gcl.get_hyper_p());
+        assert_eq!(&vec![(10, 5), (110, 5), (210, 5)], gcl.get_gate_events(0));
+        assert_eq!(Some(110), gcl.get_next_queue_empty_time(1, 3, 105));
+        assert_eq!(None, gcl.get_next_queue_empty_time(1, 3, 150));
+        assert!(!gcl.is_queue_free(1, 3, 95, 101));
+        assert!(gcl.is_queue_free(1, 3, 110, 200));
+        // 本來就是倍數，不用延長
+        gcl.update_hyper_p(100).unwrap();
+        assert_eq!(300, gcl.get_hyper_p());
+        // 延長後太大，拒絕之且不動到 GCL
+        assert_eq!(Err(300 * 999_983), gcl.update_hyper_p(999_983));
+        assert_eq!(300, gcl.get_hyper_p());
+        assert_eq!(3, gcl.get_gate_events(0).len());
+    }
+    #[test]
+    fn test_port_queue_count() {
+        let configs = vec![
+            PortConfig::default(),
+            PortConfig::with_queue_cnt(4).unwrap(),
+        ];
+        let mut gcl = GCL::with_port_configs(100, configs);
+        assert_eq!(4, gcl.get_port_config(1).queue_cnt);
+        gcl.insert_queue_evt(1, 0.into(), 3, 0, 10);
+        gcl.insert_queue_evt(0, 0.into(), 7, 0, 10);
+        assert!(gcl.is_queue_in_use(1, 3));
+        gcl.delete_flow(&vec![0, 1], 0.into());
+        assert!(!gcl.is_queue_in_use(1, 3));
+        assert!(!gcl.is_queue_in_use(0, 7));
+    }
+    #[test]
+    fn test_snapshot() {
+        let mut gcl = GCL::new(100, 2);
+        gcl.insert_gate_evt(0, 0.into(), 3, 10, 5);
+        gcl.insert_gate_evt(1, 1.into(), 4, 0, 5);
+        gcl.insert_queue_evt(1, 1.into(), 4, 0, 20);
+        gcl.set_queueid(4, 1, 1.into());
+        gcl.set_release(10, 0.into());
+        let snapshot = gcl.snapshot();
+        let txt = serde_json::to_string(&snapshot).unwrap();
+        let mut restored = GCL::new(1, 2);
+        restored
+            .restore(serde_json::from_str(&txt).unwrap())
+            .unwrap();
+        assert_eq!(snapshot, restored.snapshot());
+        assert_eq!(100, restored.get_hyper_p());
+        assert_eq!(&vec![(10, 5)], restored.get_gate_events(0));
+        assert!(!restored.is_queue_free(1, 4, 10, 11));
+        assert_eq!(4, restored.get_queueid(1, 1.into()));
+        assert_eq!(Some(10), restored.get_release(0.into()));
+        // 連結數量不符者不載入
+        let mut other = GCL::new(1, 3);
+        assert!(other.restore(snapshot.clone()).is_err());
+        assert_eq!(1, other.get_hyper_p());
+        // 佇列不存在者不載入
+        let mut bad = snapshot;
+        bad.gate_events[0][0].2 = 8;
+        assert!(restored.restore(bad).is_err());
+        assert_eq!(&vec![(10, 5)], restored.get_gate_events(0));
+    }
+    #[test]
+    fn test_adjacent_queue_events() {
+        let mut gcl = GCL::new(100, 1);
+        gcl.insert_queue_evt(0, 0.into(), 3, 0, 10);
+        gcl.insert_queue_evt(0, 1.into(), 3, 10, 10);
+        gcl.insert_queue_evt(0, 1.into(), 3, 15, 3);
+        // 不同資料流首尾相接的事件不合併，同個資料流的則合併，且不會因此縮短
+        assert_eq!(vec![(0, 10), (10, 10)], gcl.get_queue_events(0, 3));
+        gcl.delete_flow(&vec![0], 0.into());
+        assert_eq!(vec![(10, 10)], gcl.get_queue_events(0, 3));
+    }
+    #[test]
+    fn test_compact_gate_list() {
+        let mut gcl = GCL::new(100, 1);
+        assert_eq!(vec![(0, 100, 0b111)], gcl.get_compact_gate_list(0));
+        gcl.insert_gate_evt(0, 0.into(), 3, 10, 5);
+        gcl.insert_gate_evt(0, 0.into(), 3, 15, 5);
+        gcl.insert_gate_evt(0, 1.into(), 4, 20, 5);
+        gcl.insert_gate_evt(0, 2.into(), 5, 25, 5);
+        gcl.insert_gate_evt(0, 2.into(), 5, 50, 5);
+        // 5 號佇列在 1 號資料流的時段中有封包在等，不能提早開啟
+        gcl.insert_queue_evt(0, 2.into(), 5, 22, 3);
+        // 頭尾的空檔合併，清單的起點對齊到 55
+        assert_eq!(
+            vec![
+                (55, 55, 0b111),
+                (10, 15, 0b11000),
+                (25, 5, 0b100000),
+                (30, 20, 0b111),
+                (50, 5, 0b100000)
+            ],
+            gcl.get_compact_gate_list(0)
+        );
+        // 頭尾同為 TT 的閘門也能合併
+        let mut gcl = GCL::new(100, 1);
+        gcl.insert_gate_evt(0, 0.into(), 3, 0, 10);
+        gcl.insert_gate_evt(0, 0.into(), 3, 90, 10);
+        assert_eq!(
+            vec![(90, 20, 0b1000), (10, 80, 0b111)],
+            gcl.get_compact_gate_list(0)
+        );
+    }
+    #[test]
+    fn test_guard_band() {
+        let mut gcl = GCL::new(100, 1);
+        gcl.set_guard_bands(vec![3]);
+        gcl.insert_gate_evt(0, 0.into(), 5, 1, 2);
+        gcl.insert_gate_evt(0, 1.into(), 3, 10, 5);
+        gcl.insert_gate_evt(0, 2.into(), 4, 16, 4);
+        // 第一個時段的保護帶有一部份接到超週期的結尾；兩個時段間的空檔比保護帶短，整段都不能給非 TT 流量
+        assert_eq!(&vec![(0, 3), (7, 13), (98, 2)], gcl.get_gate_events(0));
+        assert_eq!(
+            vec![
+                (98, 3, 0),
+                (1, 2, 0b100000),
+                (3, 4, 0b111),
+                (7, 3, 0),
+                (10, 5, 0b1000),
+                (15, 1, 0),
+                (16, 4, 0b10000),
+                (20, 78, 0b111)
+            ],
+            gcl.get_compact_gate_list(0)
+        );
+        // 保護帶不影響 TT 之間的衝突檢查
+        assert_eq!(None, gcl.get_next_empty_time(0, 4, 5));
+    }
+    #[test]
+    fn test_gate_lookup_updates() {
+        let mut gcl = GCL::new(100, 1);
+        gcl.insert_gate_evt(0, 0.into(), 3, 50, 5);
+        gcl.insert_gate_evt(0, 1.into(), 3, 30, 10);
+        gcl.insert_gate_evt(0, 2.into(), 3, 20, 10);
+        assert_eq!(&vec![(20, 20), (50, 5)], gcl.get_gate_events(0));
+        // 填滿兩個區段之間的空檔，三者合併為一
+        gcl.insert_gate_evt(0, 3.into(), 3, 40, 10);
+        assert_eq!(&vec![(20, 35)], gcl.get_gate_events(0));
+        gcl.delete_flow(&vec![0], 1.into());
+        assert_eq!(&vec![(20, 10), (40, 15)], gcl.get_gate_events(0));
+        gcl.set_guard_bands(vec![5]);
+        assert_eq!(&vec![(15, 15), (35, 20)], gcl.get_gate_events(0));
+    }
+}
+
+/// 把 `entry` 代表的 (開始時間, 結束時間, 閘門狀態, 是否為 TT 時段) 換成以持續時間表示並接在清單後面，長度為 0 者略過
+fn push_gate_entry(list: &mut Vec<(u32, u32, u8, bool)>, entry: (u32, u32, u8, bool)) {
+    let (start, end, states, is_tt) = entry;
+    if end > start {
+        list.push((start, end - start, states, is_tt));
+    }
+}
+
+/// 把一個週期 `og_p` 內的事件複製到延長後的超週期 `hyper_p` 中的每一段
+pub(crate) fn repeat_events<V: Copy>(evts: &mut IntervalMap<V>, og_p: u32, hyper_p: u32) {
+    let og_evts: Vec<_> = evts.iter().collect();
+    for shift in (og_p..hyper_p).step_by(og_p as usize) {
+        for &(start, duration, flow_id, value) in og_evts.iter() {
+            evts.insert(start + shift, duration, flow_id, value);
+        }
+    }
+}
+
+/// 閘門事件加上其前的保護帶後，對非 TT 流量而言閘門關閉的區段 (開始時間, 持續時間)；
+/// 保護帶超出超週期開頭的部份，另外接到超週期的結尾
+fn get_closed_blocks(
+    start: u32,
+    duration: u32,
+    guard_band: u32,
+    hyper_p: u32,
+) -> ((u32, u32), Option<(u32, u32)>) {
+    if start >= guard_band {
+        ((start - guard_band, duration + guard_band), None)
+    } else {
+        let wrapped = std::cmp::min(guard_band - start, hyper_p);
+        ((0, start + duration), Some((hyper_p - wrapped, wrapped)))
+    }
+}
+
+/// 把一個區段併進依時間排列且互不相接的區段清單，與之重疊或首尾相接者合併為一個區段
+fn merge_block(lookup: &mut Vec<(u32, u32)>, block: (u32, u32)) {
+    let (mut start, mut end) = (block.0, block.0 + block.1);
+    // NOTE 清單中的區段互不相接，故結束時間也是遞增的
+    let lo = lookup.partition_point(|&(s, d)| s + d < start);
+    let hi = lookup.partition_point(|&(s, _)| s <= end);
+    if lo < hi {
+        start = std::cmp::min(start, lookup[lo].0);
+        end = std::cmp::max(end, lookup[hi - 1].0 + lookup[hi - 1].1);
+    }
+    lookup.splice(lo..hi, std::iter::once((start, end - start)));
+}
+
+/// GCL 中的排程結果，不含埠口設定與保護帶（這些取自拓撲及設定檔），可以存成 JSON 後再以 `GCL::restore` 載回
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GCLSnapshot {
+    pub hyper_p: u32,
+    /// 依連結編號排列，每個連結上所有的閘門事件 (開始時間, 持續時間, 佇列, 資料流)
+    pub gate_events: Vec<Vec<(u32, u32, u8, FlowID)>>,
+    /// 依連結及佇列編號排列，每個佇列所有的佔用事件 (開始時間, 持續時間, 資料流)
+    pub queue_events: Vec<Vec<Vec<(u32, u32, FlowID)>>>,
+    /// 每個資料流在各連結上分配到的佇列 (連結, 資料流, 佇列)
+    pub queue_assignments: Vec<(usize, FlowID, u8)>,
+    /// 每個 TT 資料流實際採用的釋出時間
+    pub releases: Vec<(FlowID, u32)>,
 }
 
 #[derive(Clone, Debug)]
 pub struct GCL {
     hyper_p: u32,
-    // TODO 這個資料結構有優化的空間
-    gate_evt: Vec<Vec<(u32, u32, u8, FlowID)>>,
-    queue_occupy_evt: Vec<[Vec<(u32, u32, FlowID)>; MAX_QUEUE as usize]>,
+    /// 每個埠口上的閘門事件，值為佇列編號
+    gate_evt: Vec<IntervalMap<u8>>,
+    /// 每個埠口上每個佇列的佔用事件，佇列數量依埠口設定而定
+    queue_occupy_evt: Vec<Vec<IntervalMap<()>>>,
     queue_map: HashMap<(usize, FlowID), u8>,
-    gate_evt_lookup: Vec<Option<Vec<(u32, u32)>>>,
+    /// 每個 TT 資料流實際採用的釋出時間
+    release_map: HashMap<FlowID, u32>,
+    /// 每個埠口上對非 TT 流量而言閘門關閉的區段（見 `get_gate_events`），隨閘門事件一起更新
+    gate_evt_lookup: Vec<Vec<(u32, u32)>>,
+    port_configs: Rc<Vec<PortConfig>>, // 在排程過程中不會變動，故用 RC 來包
+    /// 每個埠口上 TT 時段前的保護帶長度，0 代表沒有保護帶
+    guard_bands: Rc<Vec<u32>>,
 }
 impl GCL {
+    /// 所有埠口都採用預設的佇列設定
     pub fn new(hyper_p: u32, edge_count: usize) -> Self {
-        GCL {
-            gate_evt: vec![vec![]; edge_count],
-            gate_evt_lookup: vec![None; edge_count],
-            queue_occupy_evt: vec![Default::default(); edge_count],
+        GCL::with_port_configs(hyper_p, vec![PortConfig::default(); edge_count])
+    }
+    /// `port_configs` 依連結編號排列，每個連結一份佇列設定
+    pub fn with_port_configs(hyper_p: u32, port_configs: Vec<PortConfig>) -> Self {
+        let edge_count = port_configs.len();
+        let mut gcl = GCL {
+            gate_evt: vec![],
+            gate_evt_lookup: vec![],
+            queue_occupy_evt: vec![],
             queue_map: HashMap::new(),
+            release_map: HashMap::new(),
+            port_configs: Rc::new(port_configs),
+            guard_bands: Rc::new(vec![0; edge_count]),
             hyper_p,
+        };
+        gcl.clear();
+        gcl
+    }
+    pub fn get_port_config(&self, link_id: usize) -> &PortConfig {
+        &self.port_configs[link_id]
+    }
+    /// 設定每個埠口的保護帶長度，依連結編號排列
+    ///
+    /// 每個 TT 時段開始前的這段時間中，非 TT 的閘門都要關閉，讓正在傳輸的低優先度訊框不會延續到 TT 時段中
+    pub fn set_guard_bands(&mut self, guard_bands: Vec<u32>) {
+        assert_eq!(
+            self.gate_evt.len(),
+            guard_bands.len(),
+            "GCL: 保護帶數量與邊數不符"
+        );
+        self.guard_bands = Rc::new(guard_bands);
+        for link_id in 0..self.gate_evt.len() {
+            self.rebuild_gate_lookup(link_id);
         }
     }
-    pub fn update_hyper_p(&mut self, new_p: u32) {
-        self.hyper_p = lcm(self.hyper_p, new_p);
+    pub fn get_guard_band(&self, link_id: usize) -> u32 {
+        self.guard_bands[link_id]
+    }
+    /// 將超週期延長為 `new_p` 的倍數（取最小公倍數），既有的事件會在延長出來的每一段中重覆出現
+    ///
+    /// 若延長後會超過 `MAX_HYPER_P`，則不動到 GCL，並回傳延長後的超週期
+    pub fn update_hyper_p(&mut self, new_p: u32) -> Result<(), u64> {
+        let hyper_p = lcm(self.hyper_p as u64, new_p as u64);
+        if hyper_p > MAX_HYPER_P as u64 {
+            return Err(hyper_p);
+        }
+        let hyper_p = hyper_p as u32;
+        let og_p = self.hyper_p;
+        if hyper_p == og_p {
+            return Ok(());
+        }
+        for link_id in 0..self.gate_evt.len() {
+            repeat_events(&mut self.gate_evt[link_id], og_p, hyper_p);
+            for evts in self.queue_occupy_evt[link_id].iter_mut() {
+                repeat_events(evts, og_p, hyper_p);
+            }
+        }
+        self.hyper_p = hyper_p;
+        for link_id in 0..self.gate_evt.len() {
+            self.rebuild_gate_lookup(link_id);
+        }
+        Ok(())
     }
     pub fn clear(&mut self) {
-        let edge_cnt = self.gate_evt.len();
-        self.gate_evt = vec![vec![]; edge_cnt];
-        self.gate_evt_lookup = vec![None; edge_cnt];
-        self.queue_occupy_evt = vec![Default::default(); edge_cnt];
+        let edge_cnt = self.port_configs.len();
+        self.gate_evt = vec![IntervalMap::new(); edge_cnt];
+        self.gate_evt_lookup = vec![vec![]; edge_cnt];
+        self.queue_occupy_evt = self
+            .port_configs
+            .iter()
+            .map(|config| vec![IntervalMap::new(); config.queue_cnt as usize])
+            .collect();
         self.queue_map = HashMap::new();
+        self.release_map = HashMap::new();
     }
     pub fn get_hyper_p(&self) -> u32 {
         self.hyper_p
     }
-    /// 回傳 `link_id` 上所有閘門關閉事件。
+    /// 把目前的排程結果存成快照，內容依連結、佇列及資料流編號排列
+    pub fn snapshot(&self) -> GCLSnapshot {
+        let mut queue_assignments: Vec<(usize, FlowID, u8)> = self
+            .queue_map
+            .iter()
+            .map(|(&(link_id, flow_id), &queue_id)| (link_id, flow_id, queue_id))
+            .collect();
+        queue_assignments.sort();
+        let mut releases: Vec<(FlowID, u32)> = self
+            .release_map
+            .iter()
+            .map(|(&flow_id, &release)| (flow_id, release))
+            .collect();
+        releases.sort();
+        GCLSnapshot {
+            hyper_p: self.hyper_p,
+            gate_events: (0..self.gate_evt.len())
+                .map(|link_id| self.get_raw_gate_events(link_id))
+                .collect(),
+            queue_events: (0..self.queue_occupy_evt.len())
+                .map(|link_id| {
+                    (0..self.queue_occupy_evt[link_id].len())
+                        .map(|queue_id| self.get_raw_queue_events(link_id, queue_id as u8))
+                        .collect()
+                })
+                .collect(),
+            queue_assignments,
+            releases,
+        }
+    }
+    /// 清空 GCL 後載入快照，埠口設定與保護帶維持不變
+    ///
+    /// 快照的連結數量、佇列數量或超週期與目前的設定不符者回傳錯誤，此時 GCL 不會被動到。
+    /// 閘門事件是否重疊等排程規則不在此檢查，請另外驗證排程。
+    pub fn restore(&mut self, snapshot: GCLSnapshot) -> Result<(), String> {
+        let edge_cnt = self.port_configs.len();
+        if snapshot.gate_events.len() != edge_cnt || snapshot.queue_events.len() != edge_cnt {
+            return Err(format!("快照的連結數量與拓撲不符，應為 {}", edge_cnt));
+        }
+        if snapshot.hyper_p == 0 || snapshot.hyper_p > MAX_HYPER_P {
+            return Err(format!("快照的超週期不合法：{}", snapshot.hyper_p));
+        }
+        for (link_id, queues) in snapshot.queue_events.iter().enumerate() {
+            let queue_cnt = self.port_configs[link_id].queue_cnt;
+            if queues.len() != queue_cnt as usize {
+                return Err(format!(
+                    "快照中連結 {} 的佇列數量與埠口設定不符，應為 {}",
+                    link_id, queue_cnt
+                ));
+            }
+        }
+        let out_of_range = snapshot
+            .gate_events
+            .iter()
+            .flat_map(|evts| evts.iter().map(|&(start, duration, ..)| (start, duration)))
+            .chain(snapshot.queue_events.iter().flat_map(|queues| {
+                queues
+                    .iter()
+                    .flat_map(|evts| evts.iter().map(|&(start, duration, _)| (start, duration)))
+            }))
+            .any(|(start, duration)| start >= snapshot.hyper_p || duration == 0);
+        let bad_queue = snapshot
+            .gate_events
+            .iter()
+            .enumerate()
+            .flat_map(|(link_id, evts)| evts.iter().map(move |evt| (link_id, evt.2)))
+            .chain(
+                snapshot
+                    .queue_assignments
+                    .iter()
+                    .map(|&(link_id, _, queue_id)| (link_id, queue_id)),
+            )
+            .any(|(link_id, queue_id)| {
+                link_id >= edge_cnt || queue_id >= self.port_configs[link_id].queue_cnt
+            });
+        if out_of_range {
+            return Err("快照中有超出超週期或長度為 0 的事件".to_owned());
+        } else if bad_queue {
+            return Err("快照中有不存在的連結或佇列".to_owned());
+        }
+        self.clear();
+        self.hyper_p = snapshot.hyper_p;
+        for (link_id, evts) in snapshot.gate_events.into_iter().enumerate() {
+            for (start, duration, queue_id, flow_id) in evts.into_iter() {
+                self.gate_evt[link_id].insert(start, duration, flow_id, queue_id);
+            }
+            self.rebuild_gate_lookup(link_id);
+        }
+        for (link_id, queues) in snapshot.queue_events.into_iter().enumerate() {
+            for (queue_id, evts) in queues.into_iter().enumerate() {
+                for (start, duration, flow_id) in evts.into_iter() {
+                    self.queue_occupy_evt[link_id][queue_id].insert(start, duration, flow_id, ());
+                }
+            }
+        }
+        for (link_id, flow_id, queue_id) in snapshot.queue_assignments.into_iter() {
+            self.queue_map.insert((link_id, flow_id), queue_id);
+        }
+        for (flow_id, release) in snapshot.releases.into_iter() {
+            self.release_map.insert(flow_id, release);
+        }
+        Ok(())
+    }
+    /// 回傳 `link_id` 上所有閘門關閉事件（對非 TT 流量而言），包含 TT 時段前的保護帶。
     /// * `回傳值` - 一個陣列，其內容為 (事件開始時間, 事件持續時間);
     pub fn get_gate_events(&self, link_id: usize) -> &Vec<(u32, u32)> {
         assert!(self.gate_evt.len() > link_id, "GCL: 指定了超出範圍的邊");
-        if self.gate_evt_lookup[link_id].is_none() {
-            // 生成快速查找表
-            let mut lookup = Vec::<(u32, u32)>::new();
-            let len = self.gate_evt[link_id].len();
-            if len > 0 {
-                let first_evt = self.gate_evt[link_id][0];
-                let mut cur_evt = (first_evt.0, first_evt.1);
-                for &(start, duration, ..) in self.gate_evt[link_id][1..len].iter() {
-                    if cur_evt.0 + cur_evt.1 == start {
-                        // 首尾相接
-                        cur_evt.1 += duration; // 把閘門事件延長
-                    } else {
-                        lookup.push(cur_evt);
-                        cur_evt = (start, duration);
-                    }
+        &self.gate_evt_lookup[link_id]
+    }
+    /// 依目前的閘門事件重新生成 `link_id` 的快速查找表
+    fn rebuild_gate_lookup(&mut self, link_id: usize) {
+        let guard_band = self.guard_bands[link_id];
+        let mut lookup = Vec::<(u32, u32)>::new();
+        let mut wrapped_blocks = vec![];
+        // NOTE 閘門事件依開始時間排列，加上保護帶後仍是如此，只有接到結尾的部份要另外合併
+        for (start, duration, ..) in self.gate_evt[link_id].iter() {
+            let (block, wrapped) = get_closed_blocks(start, duration, guard_band, self.hyper_p);
+            match lookup.last_mut() {
+                Some(last) if last.0 + last.1 >= block.0 => {
+                    let end = std::cmp::max(last.0 + last.1, block.0 + block.1);
+                    last.1 = end - last.0;
                 }
-                lookup.push(cur_evt);
-            }
-            unsafe {
-                // NOTE 內部可變，因為這只是加速用的
-                let _self = self as *const Self as *mut Self;
-                (*_self).gate_evt_lookup[link_id] = Some(lookup);
+                _ => lookup.push(block),
             }
+            wrapped_blocks.extend(wrapped);
+        }
+        for block in wrapped_blocks.into_iter() {
+            merge_block(&mut lookup, block);
         }
-        self.gate_evt_lookup[link_id].as_ref().unwrap()
+        self.gate_evt_lookup[link_id] = lookup;
+    }
+    /// 回傳 `link_id` 上屬於 `flow_id` 的所有閘門事件，依時間排序
+    /// * `回傳值` - 一個陣列，其內容為 (事件開始時間, 事件持續時間);
+    pub fn get_flow_gate_events(&self, link_id: usize, flow_id: FlowID) -> Vec<(u32, u32)> {
+        self.gate_evt[link_id]
+            .iter_flow(flow_id)
+            .map(|(start, duration, _)| (start, duration))
+            .collect()
     }
     pub fn insert_gate_evt(
         &mut self,
@@ -98,24 +485,31 @@ impl GCL {
         start_time: u32,
         duration: u32,
     ) {
-        self.gate_evt_lookup[link_id] = None;
         let entry = (start_time, duration, queue_id, flow_id);
         let evts = &mut self.gate_evt[link_id];
-        match evts.binary_search(&entry) {
-            Ok(_) => panic!("插入重複的閘門事件: link={}, {:?}", link_id, entry),
-            Err(pos) => {
-                if pos > 0 && evts[pos - 1].0 + evts[pos - 1].1 > start_time {
-                    // 開始時間位於前一個事件中
-                    panic!(
-                        "插入重疊的閘門事件： link={}, {:?} v.s. {:?}",
-                        link_id,
-                        evts[pos - 1],
-                        entry
-                    );
-                } else {
-                    evts.insert(pos, entry)
-                }
+        if evts.get(start_time, flow_id).is_some() {
+            panic!("插入重複的閘門事件: link={}, {:?}", link_id, entry);
+        }
+        match evts.last_before(start_time) {
+            Some(prev) if prev.0 + prev.1 > start_time => {
+                // 開始時間位於前一個事件中
+                panic!(
+                    "插入重疊的閘門事件： link={}, {:?} v.s. {:?}",
+                    link_id, prev, entry
+                );
             }
+            _ => evts.insert(start_time, duration, flow_id, queue_id),
+        }
+        let (block, wrapped) = get_closed_blocks(
+            start_time,
+            duration,
+            self.guard_bands[link_id],
+            self.hyper_p,
+        );
+        let lookup = &mut self.gate_evt_lookup[link_id];
+        merge_block(lookup, block);
+        if let Some(wrapped) = wrapped {
+            merge_block(lookup, wrapped);
         }
     }
     pub fn insert_queue_evt(
@@ -129,22 +523,15 @@ impl GCL {
         if duration == 0 {
             return;
         }
-        let entry = (start_time, duration, flow_id);
         let evts = &mut self.queue_occupy_evt[link_id][queue_id as usize];
-        match evts.binary_search(&entry) {
-            // FIXME: 這個異常有機率發生，試著重現看看！
-            Ok(_) => panic!(
-                "插入重複的佇列事件: link={}, queue={}, {:?}",
-                link_id, queue_id, entry
-            ),
-            Err(pos) => {
-                if pos > 0 && evts[pos - 1].0 + evts[pos - 1].1 >= start_time {
-                    // 開始時間位於前一個事件中，則延伸前一個事件
-                    evts[pos - 1].1 = start_time + duration - evts[pos - 1].0;
-                } else {
-                    evts.insert(pos, entry)
-                }
+        match evts.flow_last_before(flow_id, start_time) {
+            Some((prev_start, prev_duration, _)) if prev_start + prev_duration >= start_time => {
+                // 開始時間位於同個資料流的前一個事件中（包含重複的事件），則延伸前一個事件
+                // NOTE 不能併進別的資料流的事件，否則拔掉那個資料流時，這段佔用也會跟著消失
+                let end = std::cmp::max(prev_start + prev_duration, start_time + duration);
+                evts.insert(prev_start, end - prev_start, flow_id, ());
             }
+            _ => evts.insert(start_time, duration, flow_id, ()),
         }
     }
     /// 會先確認 start~(start+duration) 這段時間中有沒有與其它事件重疊
@@ -174,15 +561,14 @@ impl GCL {
     ///
     /// 回傳一組資料(usize, bool)，前者代表時間，後者代表該時間是閘門事件的開始還是結束（真代表開始）
     fn get_next_spot(&self, link_id: usize, time: u32) -> (u32, bool) {
-        // TODO 應該用二元搜索來優化?
-        for &(start, duration, ..) in self.gate_evt[link_id].iter() {
-            if start > time {
-                return (start, true);
-            } else if start + duration > time {
-                return (start + duration, false);
-            }
+        // NOTE 閘門事件互不重疊，能包含 time 的只有開始時間不晚於它的最後一個事件
+        let evts = &self.gate_evt[link_id];
+        match evts.last_before(time) {
+            Some((start, duration, ..)) if start + duration > time => (start + duration, false),
+            _ => evts
+                .first_after(time)
+                .map_or((self.hyper_p, true), |(start, ..)| (start, true)),
         }
-        (self.hyper_p, true)
     }
     pub fn get_queueid(&self, link_id: usize, flow_id: FlowID) -> u8 {
         *self.queue_map.get(&(link_id, flow_id)).unwrap()
@@ -190,6 +576,108 @@ impl GCL {
     pub fn set_queueid(&mut self, queueid: u8, link_id: usize, flow_id: FlowID) {
         self.queue_map.insert((link_id, flow_id), queueid);
     }
+    /// 資料流的釋出時間，未排進 GCL 者回傳 None
+    pub fn get_release(&self, flow_id: FlowID) -> Option<u32> {
+        self.release_map.get(&flow_id).cloned()
+    }
+    pub fn set_release(&mut self, release: u32, flow_id: FlowID) {
+        self.release_map.insert(flow_id, release);
+    }
+    /// 回傳 `link_id` 上所有未合併的閘門事件，其內容為 (事件開始時間, 事件持續時間, 佇列, 資料流)
+    pub fn get_raw_gate_events(&self, link_id: usize) -> Vec<(u32, u32, u8, FlowID)> {
+        self.gate_evt[link_id]
+            .iter()
+            .map(|(start, duration, flow_id, queue_id)| (start, duration, queue_id, flow_id))
+            .collect()
+    }
+    /// 該連結上的佇列是否已被任何資料流佔用過
+    pub fn is_queue_in_use(&self, link_id: usize, queue_id: u8) -> bool {
+        !self.queue_occupy_evt[link_id][queue_id as usize].is_empty()
+    }
+    /// 回傳 `link_id` 上某個佇列所有被佔用的事件，其內容為 (事件開始時間, 事件持續時間)
+    pub fn get_queue_events(&self, link_id: usize, queue_id: u8) -> Vec<(u32, u32)> {
+        self.queue_occupy_evt[link_id][queue_id as usize]
+            .iter()
+            .map(|(start, duration, ..)| (start, duration))
+            .collect()
+    }
+    /// 回傳 `link_id` 上某個佇列所有被佔用的事件，其內容為 (事件開始時間, 事件持續時間, 資料流)
+    pub fn get_raw_queue_events(&self, link_id: usize, queue_id: u8) -> Vec<(u32, u32, FlowID)> {
+        self.queue_occupy_evt[link_id][queue_id as usize]
+            .iter()
+            .map(|(start, duration, flow_id, _)| (start, duration, flow_id))
+            .collect()
+    }
+    /// 壓縮後的閘門控制清單，每一筆為 (開始時間, 持續時間, 閘門狀態)，依時間排列且恰涵蓋一個超週期
+    ///
+    /// 閘門狀態的第 i 個位元代表第 i 個佇列的閘門是否開啟。TT 時段只開啟其佇列，保護帶關閉所有閘門，
+    /// 其餘時間則開啟非 TT 的閘門（見 `PortConfig::get_non_tt_gates`）。
+    /// 1. 首尾相接且狀態相同者合併為一筆。
+    /// 2. 首尾相接但佇列不同的 TT 時段，若各自的佇列在對方的時段中都是空的，則同時開啟兩者，合併為一筆。
+    ///    佇列是空的，閘門開著也不會送出任何東西，故不影響排程結果。
+    /// 3. 超週期頭尾的兩筆若相同，則把清單的起點對齊到最後一筆的開始時間，兩筆合併為一筆，
+    ///    此時第一筆的開始時間不是 0，交換機的週期起點要跟著平移。
+    pub fn get_compact_gate_list(&self, link_id: usize) -> Vec<(u32, u32, u8)> {
+        let non_tt_gates = self.port_configs[link_id].get_non_tt_gates();
+        let guard_band = self.guard_bands[link_id];
+        let evts = self.get_raw_gate_events(link_id);
+        // 第一個 TT 時段的保護帶超出超週期開頭的部份，接到超週期的結尾
+        let wrapped_guard = evts
+            .first()
+            .map_or(0, |first| guard_band.saturating_sub(first.0));
+        // NOTE 先記下每一筆是不是 TT 時段，只有 TT 時段之間能以第 2 條規則合併
+        let mut list = Vec::<(u32, u32, u8, bool)>::new();
+        let mut cursor = 0;
+        for &(start, duration, queue_id, _) in evts.iter() {
+            let guard_start = std::cmp::max(cursor, start.saturating_sub(guard_band));
+            push_gate_entry(&mut list, (cursor, guard_start, non_tt_gates, false));
+            push_gate_entry(&mut list, (guard_start, start, 0, false));
+            let entry = (start, duration, 1 << queue_id);
+            match list.last_mut() {
+                Some(last)
+                    if last.3 && self.can_merge_gates(link_id, (last.0, last.1, last.2), entry) =>
+                {
+                    last.1 += duration;
+                    last.2 |= entry.2;
+                }
+                _ => list.push((entry.0, entry.1, entry.2, true)),
+            }
+            cursor = start + duration;
+        }
+        let guard_start = std::cmp::max(cursor, self.hyper_p.saturating_sub(wrapped_guard));
+        push_gate_entry(&mut list, (cursor, guard_start, non_tt_gates, false));
+        push_gate_entry(&mut list, (guard_start, self.hyper_p, 0, false));
+        let mut compact_list = Vec::<(u32, u32, u8)>::new();
+        for (start, duration, states, _) in list.into_iter() {
+            match compact_list.last_mut() {
+                Some(last) if last.2 == states => last.1 += duration,
+                _ => compact_list.push((start, duration, states)),
+            }
+        }
+        let len = compact_list.len();
+        if len > 1 && compact_list[0].2 == compact_list[len - 1].2 {
+            let (_, duration, _) = compact_list.remove(0);
+            let mut last = compact_list.pop().unwrap();
+            last.1 += duration;
+            compact_list.insert(0, last);
+        }
+        compact_list
+    }
+    /// 首尾相接的兩筆 TT 時段，各自的佇列在對方的時段中是否都是空的
+    fn can_merge_gates(&self, link_id: usize, a: (u32, u32, u8), b: (u32, u32, u8)) -> bool {
+        if a.0 + a.1 != b.0 {
+            return false;
+        }
+        let queue_cnt = self.port_configs[link_id].queue_cnt;
+        (0..queue_cnt).all(|queue_id| {
+            let mask = 1 << queue_id;
+            let a_free =
+                b.2 & !a.2 & mask == 0 || self.is_queue_free(link_id, queue_id, a.0, a.0 + a.1);
+            let b_free =
+                a.2 & !b.2 & mask == 0 || self.is_queue_free(link_id, queue_id, b.0, b.0 + b.1);
+            a_free && b_free
+        })
+    }
     /// 回傳 None 者，代表當前即是空的
     pub fn get_next_queue_empty_time(
         &self,
@@ -197,42 +685,27 @@ impl GCL {
         queue_id: u8,
         time: u32,
     ) -> Option<u32> {
-        let evts = &self.queue_occupy_evt[link_id][queue_id as usize];
-        for &(start, duration, _) in evts.iter() {
-            if start <= time {
-                if start + duration > time {
-                    return Some(start + duration);
-                } else {
-                    return None;
-                }
-            }
-        }
-        None
+        self.queue_occupy_evt[link_id][queue_id as usize]
+            .overlapping(time, time + 1)
+            .next()
+            .map(|(start, duration, ..)| start + duration)
+    }
+    /// start~end 這段時間中，該佇列是否都沒有被佔用
+    pub fn is_queue_free(&self, link_id: usize, queue_id: u8, start: u32, end: u32) -> bool {
+        self.queue_occupy_evt[link_id][queue_id as usize]
+            .overlapping(start, end)
+            .next()
+            .is_none()
     }
     pub fn delete_flow(&mut self, links: &Vec<usize>, flow_id: FlowID) {
+        self.release_map.remove(&flow_id);
         for &link_id in links.iter() {
-            self.gate_evt_lookup[link_id] = None;
-            let gate_evt = &mut self.gate_evt[link_id];
-            let mut i = 0;
             self.queue_map.remove(&(link_id, flow_id));
-            while i < gate_evt.len() {
-                if gate_evt[i].3 == flow_id {
-                    gate_evt.remove(i);
-                } else {
-                    i += 1;
-                }
-            }
-            for queue_id in 0..MAX_QUEUE {
-                let queue_evt = &mut self.queue_occupy_evt[link_id][queue_id as usize];
-                let mut i = 0;
-                while i < queue_evt.len() {
-                    if queue_evt[i].2 == flow_id {
-                        queue_evt.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                }
+            self.gate_evt[link_id].remove_flow(flow_id);
+            for queue_evt in self.queue_occupy_evt[link_id].iter_mut() {
+                queue_evt.remove_flow(flow_id);
             }
+            self.rebuild_gate_lookup(link_id);
         }
     }
 }
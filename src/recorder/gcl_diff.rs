@@ -0,0 +1,252 @@
+use super::gcl::{lcm, repeat_events};
+use super::interval_map::IntervalMap;
+use super::GCL;
+use crate::flow::FlowID;
+use crate::MAX_HYPER_P;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// 閘門事件 (開始時間, 持續時間, 佇列, 資料流)
+type GateEvent = (u32, u32, u8, FlowID);
+
+/// 單一埠口在兩次排程之間的變化
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PortDiff {
+    pub link_id: usize,
+    /// 新的排程中才有的閘門事件
+    pub added: Vec<GateEvent>,
+    /// 舊的排程中才有的閘門事件
+    pub removed: Vec<GateEvent>,
+    /// 同一個資料流被移動、改變長度或換了佇列的閘門事件，其內容為 (舊事件, 新事件)
+    pub shifted: Vec<(GateEvent, GateEvent)>,
+    /// 佇列分配改變的資料流，其內容為 (資料流, 舊佇列, 新佇列)，None 代表該資料流不經過此埠口
+    pub queue_changes: Vec<(FlowID, Option<u8>, Option<u8>)>,
+    /// 交換機上的設定是否要重新下發，即壓縮後的閘門控制清單或週期是否改變，或佇列分配有變
+    pub need_reprovision: bool,
+}
+impl PortDiff {
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.shifted.is_empty()
+            && self.queue_changes.is_empty()
+            && !self.need_reprovision
+    }
+}
+
+/// 兩次排程之間 GCL 的變化，每個埠口（連結）一筆，依連結編號排列
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GCLDiff {
+    pub old_hyper_p: u32,
+    pub new_hyper_p: u32,
+    pub ports: Vec<PortDiff>,
+}
+impl GCLDiff {
+    /// 完全沒有變化的埠口，重新配置時可以跳過
+    pub fn get_unchanged_ports(&self) -> Vec<usize> {
+        self.ports
+            .iter()
+            .filter(|port| port.is_unchanged())
+            .map(|port| port.link_id)
+            .collect()
+    }
+    /// 需要重新下發設定的埠口
+    pub fn get_reprovision_ports(&self) -> Vec<usize> {
+        self.ports
+            .iter()
+            .filter(|port| port.need_reprovision)
+            .map(|port| port.link_id)
+            .collect()
+    }
+}
+
+/// 比較兩次排程的 GCL，兩者必需來自同一個拓撲
+///
+/// 超週期不同時（插入新資料流使超週期延長），先把兩邊的事件都展開到兩者的最小公倍數再比較，
+/// 因此單純因延長而重覆出現的事件不算新增。
+/// 同一個資料流在同一埠口上的事件依時間順序一一配對，不同者即為 `shifted`，多出來的則是 `added` 或 `removed`。
+/// 若最小公倍數超過 `MAX_HYPER_P`，則不比較並回傳錯誤。
+pub fn diff_gcl(old: &GCL, new: &GCL) -> Result<GCLDiff, String> {
+    let old_snapshot = old.snapshot();
+    let new_snapshot = new.snapshot();
+    assert_eq!(
+        old_snapshot.gate_events.len(),
+        new_snapshot.gate_events.len(),
+        "GCL: 比較的兩者連結數量不同"
+    );
+    let (old_p, new_p) = (old.get_hyper_p(), new.get_hyper_p());
+    let hyper_p = lcm(old_p as u64, new_p as u64);
+    if hyper_p > MAX_HYPER_P as u64 {
+        return Err(format!(
+            "GCL: 兩者超週期 {} 與 {} 的最小公倍數 {} 過大，無法比較",
+            old_p, new_p, hyper_p
+        ));
+    }
+    let hyper_p = hyper_p as u32;
+    let old_queues = group_queue_assignments(&old_snapshot.queue_assignments);
+    let new_queues = group_queue_assignments(&new_snapshot.queue_assignments);
+    let ports = (0..old_snapshot.gate_events.len())
+        .map(|link_id| {
+            let old_evts = &old_snapshot.gate_events[link_id];
+            let new_evts = &new_snapshot.gate_events[link_id];
+            let mut diff = diff_gate_events(
+                link_id,
+                &expand_events(old_evts, old_p, hyper_p),
+                &expand_events(new_evts, new_p, hyper_p),
+            );
+            diff.queue_changes = diff_queues(old_queues.get(&link_id), new_queues.get(&link_id));
+            // NOTE 沒有 TT 的埠口不啟用排程，週期改變也不用重新下發
+            let gates_changed = if old_evts.is_empty() && new_evts.is_empty() {
+                false
+            } else {
+                old_p != new_p
+                    || old.get_compact_gate_list(link_id) != new.get_compact_gate_list(link_id)
+            };
+            diff.need_reprovision = gates_changed || !diff.queue_changes.is_empty();
+            diff
+        })
+        .collect();
+    Ok(GCLDiff {
+        old_hyper_p: old_p,
+        new_hyper_p: new_p,
+        ports,
+    })
+}
+
+/// 把一個週期 `period` 內的事件複製到 `hyper_p` 中的每一段
+fn expand_events(evts: &[GateEvent], period: u32, hyper_p: u32) -> Vec<GateEvent> {
+    let mut map = IntervalMap::new();
+    for &(start, duration, queue_id, flow_id) in evts.iter() {
+        map.insert(start, duration, flow_id, queue_id);
+    }
+    repeat_events(&mut map, period, hyper_p);
+    map.iter()
+        .map(|(start, duration, flow_id, queue_id)| (start, duration, queue_id, flow_id))
+        .collect()
+}
+
+fn diff_gate_events(link_id: usize, old: &[GateEvent], new: &[GateEvent]) -> PortDiff {
+    let mut per_flow = BTreeMap::<FlowID, (Vec<GateEvent>, Vec<GateEvent>)>::new();
+    for &evt in old.iter() {
+        per_flow.entry(evt.3).or_default().0.push(evt);
+    }
+    for &evt in new.iter() {
+        per_flow.entry(evt.3).or_default().1.push(evt);
+    }
+    let mut diff = PortDiff {
+        link_id,
+        added: vec![],
+        removed: vec![],
+        shifted: vec![],
+        queue_changes: vec![],
+        need_reprovision: false,
+    };
+    for (_, (mut old_evts, mut new_evts)) in per_flow.into_iter() {
+        old_evts.sort();
+        new_evts.sort();
+        let paired = std::cmp::min(old_evts.len(), new_evts.len());
+        for i in 0..paired {
+            if old_evts[i] != new_evts[i] {
+                diff.shifted.push((old_evts[i], new_evts[i]));
+            }
+        }
+        diff.removed.extend_from_slice(&old_evts[paired..]);
+        diff.added.extend_from_slice(&new_evts[paired..]);
+    }
+    diff.added.sort();
+    diff.removed.sort();
+    diff.shifted.sort();
+    diff
+}
+
+/// 依連結分組的佇列分配，資料流 -> 佇列
+fn group_queue_assignments(
+    assignments: &[(usize, FlowID, u8)],
+) -> BTreeMap<usize, BTreeMap<FlowID, u8>> {
+    let mut grouped = BTreeMap::<usize, BTreeMap<FlowID, u8>>::new();
+    for &(link_id, flow_id, queue_id) in assignments.iter() {
+        grouped
+            .entry(link_id)
+            .or_default()
+            .insert(flow_id, queue_id);
+    }
+    grouped
+}
+
+fn diff_queues(
+    old: Option<&BTreeMap<FlowID, u8>>,
+    new: Option<&BTreeMap<FlowID, u8>>,
+) -> Vec<(FlowID, Option<u8>, Option<u8>)> {
+    let empty = BTreeMap::new();
+    let (old, new) = (old.unwrap_or(&empty), new.unwrap_or(&empty));
+    let mut flows: Vec<FlowID> = old.keys().chain(new.keys()).cloned().collect();
+    flows.sort();
+    flows.dedup();
+    flows
+        .into_iter()
+        .map(|flow_id| {
+            (
+                flow_id,
+                old.get(&flow_id).cloned(),
+                new.get(&flow_id).cloned(),
+            )
+        })
+        .filter(|(_, old_queue, new_queue)| old_queue != new_queue)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_diff_gcl() {
+        let mut old = GCL::new(100, 3);
+        old.insert_gate_evt(0, 0.into(), 3, 10, 5);
+        old.set_queueid(3, 0, 0.into());
+        old.insert_gate_evt(1, 1.into(), 4, 20, 5);
+        old.set_queueid(4, 1, 1.into());
+        let mut new = old.clone();
+        // 延長超週期，0 號連結上的事件只是重覆出現，不算改變
+        new.update_hyper_p(200).unwrap();
+        // 1 號資料流被移動並換了佇列，2 號資料流是新的
+        new.delete_flow(&vec![1], 1.into());
+        new.insert_gate_evt(1, 1.into(), 5, 30, 5);
+        new.insert_gate_evt(1, 1.into(), 5, 130, 5);
+        new.set_queueid(5, 1, 1.into());
+        new.insert_gate_evt(1, 2.into(), 3, 50, 5);
+        new.set_queueid(3, 1, 2.into());
+
+        let diff = diff_gcl(&old, &new).unwrap();
+        assert_eq!((100, 200), (diff.old_hyper_p, diff.new_hyper_p));
+        let port = &diff.ports[0];
+        assert!(port.added.is_empty() && port.removed.is_empty() && port.shifted.is_empty());
+        // 週期變了，有 TT 的埠口還是要重新下發
+        assert!(port.need_reprovision);
+        let port = &diff.ports[1];
+        assert_eq!(vec![(50, 5, 3, 2.into())], port.added);
+        assert!(port.removed.is_empty());
+        assert_eq!(
+            vec![
+                ((20, 5, 4, 1.into()), (30, 5, 5, 1.into())),
+                ((120, 5, 4, 1.into()), (130, 5, 5, 1.into()))
+            ],
+            port.shifted
+        );
+        assert_eq!(
+            vec![(1.into(), Some(4), Some(5)), (2.into(), None, Some(3))],
+            port.queue_changes
+        );
+        assert_eq!(vec![2], diff.get_unchanged_ports());
+        assert_eq!(vec![0, 1], diff.get_reprovision_ports());
+
+        // 完全相同的 GCL 沒有任何變化
+        let diff = diff_gcl(&old, &old.clone()).unwrap();
+        assert_eq!(vec![0, 1, 2], diff.get_unchanged_ports());
+    }
+    #[test]
+    fn test_diff_gcl_hyper_p_too_large() {
+        let old = GCL::new(999_983, 1);
+        let new = GCL::new(1000, 1);
+        assert!(diff_gcl(&old, &new).is_err());
+    }
+}
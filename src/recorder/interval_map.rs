@@ -0,0 +1,272 @@
+use crate::flow::FlowID;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound::{Excluded, Unbounded};
+
+const MAX_FLOW: FlowID = FlowID(usize::MAX);
+
+/// 一群時間區段，每個區段屬於某個資料流，並附帶一個值（如佇列編號）
+///
+/// 以平衡樹依 (開始時間, 資料流) 存放，插入、刪除及查詢都是 O(log n)，查詢重疊的區段時另外加上重疊的數量。
+/// 區段之間可以重疊（如多個資料流同時在一個佇列中等待），另以 `EndTree` 記錄子樹中最晚的結束時間，
+/// 查詢時就不會漏掉開始得早但持續很久的區段。
+#[derive(Clone, Debug)]
+pub(super) struct IntervalMap<V: Copy> {
+    /// (開始時間, 資料流) -> (持續時間, 值)
+    tree: BTreeMap<(u32, FlowID), (u32, V)>,
+    /// 每個資料流所有區段的開始時間，用來快速拔掉一個資料流
+    flow_index: HashMap<FlowID, BTreeSet<u32>>,
+    /// 與 `tree` 存放同樣的區段，用來查詢重疊的區段
+    end_tree: EndTree,
+}
+impl<V: Copy> IntervalMap<V> {
+    pub fn new() -> Self {
+        IntervalMap {
+            tree: BTreeMap::new(),
+            flow_index: HashMap::new(),
+            end_tree: None,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// 所有區段，依開始時間排列，其內容為 (開始時間, 持續時間, 資料流, 值)
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (u32, u32, FlowID, V)> + 'a {
+        self.tree
+            .iter()
+            .map(|(&(start, flow_id), &(duration, value))| (start, duration, flow_id, value))
+    }
+    pub fn get(&self, start: u32, flow_id: FlowID) -> Option<(u32, V)> {
+        self.tree.get(&(start, flow_id)).cloned()
+    }
+    /// 若該資料流已有同樣開始時間的區段，則覆蓋之
+    pub fn insert(&mut self, start: u32, duration: u32, flow_id: FlowID, value: V) {
+        let key = (start, flow_id);
+        if self.tree.insert(key, (duration, value)).is_some() {
+            end_tree_remove(&mut self.end_tree, key);
+        }
+        end_tree_insert(&mut self.end_tree, key, start + duration);
+        self.flow_index.entry(flow_id).or_default().insert(start);
+    }
+    /// 拔掉屬於 `flow_id` 的所有區段
+    pub fn remove_flow(&mut self, flow_id: FlowID) {
+        if let Some(starts) = self.flow_index.remove(&flow_id) {
+            for start in starts.into_iter() {
+                self.tree.remove(&(start, flow_id));
+                end_tree_remove(&mut self.end_tree, (start, flow_id));
+            }
+        }
+    }
+    /// 屬於 `flow_id` 的所有區段，依開始時間排列，其內容為 (開始時間, 持續時間, 值)
+    pub fn iter_flow<'a>(&'a self, flow_id: FlowID) -> impl Iterator<Item = (u32, u32, V)> + 'a {
+        self.flow_index
+            .get(&flow_id)
+            .into_iter()
+            .flat_map(|starts| starts.iter())
+            .map(move |&start| {
+                let (duration, value) = self.tree[&(start, flow_id)];
+                (start, duration, value)
+            })
+    }
+    /// 屬於 `flow_id` 且開始時間不晚於 `time` 的最後一個區段
+    pub fn flow_last_before(&self, flow_id: FlowID, time: u32) -> Option<(u32, u32, V)> {
+        let starts = self.flow_index.get(&flow_id)?;
+        let &start = starts.range(..=time).next_back()?;
+        let (duration, value) = self.tree[&(start, flow_id)];
+        Some((start, duration, value))
+    }
+    /// 開始時間不晚於 `time` 的最後一個區段
+    pub fn last_before(&self, time: u32) -> Option<(u32, u32, FlowID, V)> {
+        self.tree
+            .range(..=(time, MAX_FLOW))
+            .next_back()
+            .map(|(&(start, flow_id), &(duration, value))| (start, duration, flow_id, value))
+    }
+    /// 開始時間晚於 `time` 的第一個區段
+    pub fn first_after(&self, time: u32) -> Option<(u32, u32, FlowID, V)> {
+        self.tree
+            .range((Excluded((time, MAX_FLOW)), Unbounded))
+            .next()
+            .map(|(&(start, flow_id), &(duration, value))| (start, duration, flow_id, value))
+    }
+    /// 所有與 start~end 重疊的區段（開始於 `end` 之前，且結束於 `start` 之後），依開始時間排列
+    pub fn overlapping<'a>(
+        &'a self,
+        start: u32,
+        end: u32,
+    ) -> impl Iterator<Item = (u32, u32, FlowID, V)> + 'a {
+        let mut keys = vec![];
+        end_tree_overlapping(&self.end_tree, start, end, &mut keys);
+        keys.into_iter().map(move |(evt_start, flow_id)| {
+            let (duration, value) = self.tree[&(evt_start, flow_id)];
+            (evt_start, duration, flow_id, value)
+        })
+    }
+}
+
+/// 依 (開始時間, 資料流) 排列的 treap，每個節點記下子樹中最晚的結束時間
+///
+/// 某個子樹中沒有區段結束於查詢的開始時間之後，就整個跳過，故查詢重疊的區段只需 O(log n) 加上重疊的數量。
+type EndTree = Option<Box<EndNode>>;
+
+#[derive(Clone, Debug)]
+struct EndNode {
+    key: (u32, FlowID),
+    end: u32,
+    /// 子樹中最晚的結束時間
+    max_end: u32,
+    /// 由鍵值雜湊而來，使同樣的區段總是建出同樣的樹
+    priority: u64,
+    left: EndTree,
+    right: EndTree,
+}
+
+fn get_max_end(tree: &EndTree) -> u32 {
+    tree.as_ref().map_or(0, |node| node.max_end)
+}
+
+fn update_max_end(node: &mut EndNode) {
+    node.max_end = node
+        .end
+        .max(get_max_end(&node.left))
+        .max(get_max_end(&node.right));
+}
+
+/// 以 splitmix64 把鍵值打散成優先度
+fn get_priority(key: (u32, FlowID)) -> u64 {
+    let mut x = ((key.0 as u64) << 32 ^ key.1 .0 as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// 把樹拆成鍵值滿足 `go_left` 者與其它者
+fn end_tree_split<P: Fn((u32, FlowID)) -> bool>(tree: EndTree, go_left: &P) -> (EndTree, EndTree) {
+    match tree {
+        None => (None, None),
+        Some(mut node) => {
+            if go_left(node.key) {
+                let (left, right) = end_tree_split(node.right.take(), go_left);
+                node.right = left;
+                update_max_end(&mut node);
+                (Some(node), right)
+            } else {
+                let (left, right) = end_tree_split(node.left.take(), go_left);
+                node.left = right;
+                update_max_end(&mut node);
+                (left, Some(node))
+            }
+        }
+    }
+}
+
+/// 合併兩棵樹，`left` 中的鍵值皆小於 `right` 中的鍵值
+fn end_tree_merge(left: EndTree, right: EndTree) -> EndTree {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut l), Some(mut r)) => {
+            if l.priority > r.priority {
+                l.right = end_tree_merge(l.right.take(), Some(r));
+                update_max_end(&mut l);
+                Some(l)
+            } else {
+                r.left = end_tree_merge(Some(l), r.left.take());
+                update_max_end(&mut r);
+                Some(r)
+            }
+        }
+    }
+}
+
+/// 呼叫者須確保樹中沒有同樣的鍵值
+fn end_tree_insert(tree: &mut EndTree, key: (u32, FlowID), end: u32) {
+    let node = Box::new(EndNode {
+        key,
+        end,
+        max_end: end,
+        priority: get_priority(key),
+        left: None,
+        right: None,
+    });
+    let (left, right) = end_tree_split(tree.take(), &|k| k < key);
+    *tree = end_tree_merge(end_tree_merge(left, Some(node)), right);
+}
+
+fn end_tree_remove(tree: &mut EndTree, key: (u32, FlowID)) {
+    let (left, right) = end_tree_split(tree.take(), &|k| k < key);
+    let (_, right) = end_tree_split(right, &|k| k <= key);
+    *tree = end_tree_merge(left, right);
+}
+
+/// 依鍵值順序，把開始於 `end` 之前且結束於 `start` 之後的區段放進 `keys`
+fn end_tree_overlapping(tree: &EndTree, start: u32, end: u32, keys: &mut Vec<(u32, FlowID)>) {
+    let node = match tree {
+        Some(node) if node.max_end > start => node,
+        _ => return,
+    };
+    end_tree_overlapping(&node.left, start, end, keys);
+    if node.key.0 < end {
+        if node.end > start {
+            keys.push(node.key);
+        }
+        end_tree_overlapping(&node.right, start, end, keys);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_interval_map() {
+        let mut map = IntervalMap::<u8>::new();
+        map.insert(10, 50, 0.into(), 3);
+        map.insert(20, 5, 1.into(), 4);
+        map.insert(70, 5, 1.into(), 4);
+        map.insert(80, 5, 0.into(), 3);
+        assert_eq!(4, map.iter().count());
+        // 開始得早但持續很久的區段也找得到
+        let found: Vec<FlowID> = map.overlapping(40, 75).map(|evt| evt.2).collect();
+        assert_eq!(vec![FlowID(0), FlowID(1)], found);
+        assert_eq!(0, map.overlapping(60, 70).count());
+        assert_eq!(Some((20, 5, 1.into(), 4)), map.last_before(69));
+        assert_eq!(Some((70, 5, 1.into(), 4)), map.first_after(20));
+        assert_eq!(None, map.first_after(80));
+        assert_eq!(Some((10, 50, 3)), map.flow_last_before(0.into(), 79));
+        assert_eq!(
+            vec![(20, 5, 4), (70, 5, 4)],
+            map.iter_flow(1.into()).collect::<Vec<_>>()
+        );
+        map.remove_flow(0.into());
+        assert_eq!(2, map.iter().count());
+        assert_eq!(None, map.get(10, 0.into()));
+        assert_eq!(0, map.iter_flow(0.into()).count());
+        assert_eq!(1, map.overlapping(0, 100).filter(|evt| evt.0 == 70).count());
+    }
+    #[test]
+    fn test_overlapping_after_updates() {
+        // 與逐一檢查每個區段的結果比較，包含覆蓋與拔掉資料流之後
+        let mut map = IntervalMap::<()>::new();
+        let mut evts = Vec::<(u32, u32, FlowID)>::new();
+        for i in 0..200u32 {
+            let start = i * 37 % 500;
+            let duration = 1 + i * 13 % (if i % 10 == 0 { 300 } else { 20 });
+            let flow_id = FlowID((i % 7) as usize);
+            evts.retain(|&(s, _, f)| (s, f) != (start, flow_id));
+            evts.push((start, duration, flow_id));
+            map.insert(start, duration, flow_id, ());
+            if i % 50 == 49 {
+                let removed = FlowID((i / 50) as usize);
+                evts.retain(|evt| evt.2 != removed);
+                map.remove_flow(removed);
+            }
+        }
+        evts.sort_by_key(|&(s, _, f)| (s, f));
+        for &(start, end) in [(0, 1), (100, 150), (250, 251), (400, 600), (0, 600)].iter() {
+            let expected: Vec<_> = evts
+                .iter()
+                .filter(|&&(s, d, _)| s < end && s + d > start)
+                .map(|&(s, d, f)| (s, d, f, ()))
+                .collect();
+            assert_eq!(expected, map.overlapping(start, end).collect::<Vec<_>>());
+        }
+    }
+}
@@ -1,4 +1,7 @@
 mod gcl;
-pub use gcl::GCL;
+mod interval_map;
+pub use gcl::{GCLSnapshot, GCL};
+mod gcl_diff;
+pub use gcl_diff::{diff_gcl, GCLDiff, PortDiff};
 
 pub mod flow_table;
@@ -16,9 +16,12 @@ pub fn do_aco(algo: &mut AdamsAnt, time_limit: u128) {
     algo.aco
         .do_aco(time_limit - time.elapsed().as_micros(), &vis, |state| {
             let (cost, dist) = compute_aco_dist(mut_wrapper, state, &mut best_dist);
-            if cost.avb_fail_cnt == 0 && Config::get().fast_stop {
+            if cost.is_avb_feasible() && Config::get().fast_stop {
                 // 找到可行解，且為快速終止模式
                 ACOJudgeResult::Stop(dist)
+            } else if let Some(error) = cost.tsn_fail_cause {
+                // TT 排程失敗，只懲罰排不進去的那個資料流所選的路徑
+                ACOJudgeResult::Penalize(dist, vec![error.flow_id.0])
             } else {
                 ACOJudgeResult::KeepOn(dist)
             }
@@ -76,7 +79,7 @@ fn compute_aco_dist(
     let cost = cur_wrapper.compute_all_cost();
     let dist = dist_computing(&cost);
 
-    if Config::get().fast_stop && cost.avb_fail_cnt == 0 {
+    if Config::get().fast_stop && cost.is_avb_feasible() {
         // 快速終止！
         *wrapper = cur_wrapper;
         return (cost, dist);
@@ -1,9 +1,11 @@
-use super::RoutingAlgo;
+use super::{load_deployment_with_yens, RoutingAlgo};
 use crate::config::Config;
 use crate::flow::{AVBFlow, Flow, FlowEnum, FlowID, TSNFlow};
 use crate::graph_util::StreamAwareGraph;
-use crate::network_wrapper::{NetworkWrapper, RoutingCost};
-use crate::recorder::flow_table::prelude::*;
+use crate::network_wrapper::{
+    AVBLatencyReport, Deployment, NetworkWrapper, RoutingCost, Violation,
+};
+use crate::recorder::{flow_table::prelude::*, GCL};
 use crate::util::{aco::ACO, YensAlgo};
 use crate::MAX_K;
 
@@ -31,8 +33,7 @@ impl AdamsAnt {
     pub fn new(g: StreamAwareGraph) -> Self {
         let yens_algo = Rc::new(RefCell::new(YensAlgo::new(g.clone(), MAX_K)));
         let tmp_yens = yens_algo.clone();
-        // TODO: 計算 hyper_p
-        let wrapper = NetworkWrapper::new(1000, g, move |flow_enum, &k| {
+        let wrapper = NetworkWrapper::new(g, move |flow_enum, &k| {
             let (src, dst) = get_src_dst(flow_enum);
             tmp_yens.borrow().get_kth_route(src, dst, k) as *const Vec<usize>
         });
@@ -85,20 +86,46 @@ impl RoutingAlgo for AdamsAnt {
     fn show_results(&self) {
         println!("TT Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_tsn() {
-            let route = self.get_route(flow.id);
-            println!("flow id = {:?}, route = {:?}", flow.id, route);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
+            let jitter = self.wrapper.compute_tsn_jitter(flow);
+            let release = self.wrapper.get_tsn_release(flow.id);
+            println!(
+                "flow id = {:?}, route = {:?}, offset = {:?}, jitter = {}",
+                flow.id, route, release, jitter
+            );
         }
         println!("AVB Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_avb() {
-            let route = self.get_route(flow.id);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
             let cost = self.wrapper.compute_single_avb_cost(flow);
             println!(
                 "flow id = {:?}, route = {:?} avb wcd / max latency = {:?}, reroute = {}",
                 flow.id, route, cost.avb_wcd, cost.reroute_overhead
             );
+            for hop in self
+                .wrapper
+                .compute_avb_latency_breakdown(flow, None)
+                .iter()
+            {
+                println!("    {}", hop);
+            }
         }
         let all_cost = self.wrapper.compute_all_cost();
         println!("the cost structure = {:?}", all_cost);
+        if let Some(error) = all_cost.tsn_fail_cause {
+            if let Some(link_id) = error.link_id {
+                println!(
+                    "TSN schedule fail at {}",
+                    self.wrapper.get_graph().get_link_name(link_id)
+                );
+            }
+        }
         println!("{}", all_cost.compute());
     }
     fn get_last_compute_time(&self) -> u128 {
@@ -107,4 +134,31 @@ impl RoutingAlgo for AdamsAnt {
     fn get_cost(&self) -> RoutingCost {
         self.wrapper.compute_all_cost()
     }
+    fn get_avb_latency_report(&self) -> Vec<AVBLatencyReport> {
+        self.wrapper.report_avb_latency()
+    }
+    fn validate_schedule(&self) -> Vec<Violation> {
+        self.wrapper.validate_schedule()
+    }
+    fn get_gcl(&self) -> &GCL {
+        self.wrapper.get_gcl()
+    }
+    fn get_graph(&self) -> &StreamAwareGraph {
+        self.wrapper.get_graph()
+    }
+    fn get_routed_tsns(&self) -> Vec<(&TSNFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_tsns()
+    }
+    fn get_routed_avbs(&self) -> Vec<(&AVBFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_avbs()
+    }
+    fn load_deployment(&mut self, deployment: Deployment) -> Result<(), String> {
+        load_deployment_with_yens(&mut self.wrapper, &self.yens_algo, deployment)?;
+        self.aco
+            .extend_state_len(self.wrapper.get_flow_table().get_max_id().0 + 1);
+        Ok(())
+    }
+    fn get_deployment(&self) -> Deployment {
+        self.wrapper.get_deployment()
+    }
 }
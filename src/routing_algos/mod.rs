@@ -1,5 +1,12 @@
-use crate::flow::{AVBFlow, FlowID, TSNFlow};
-use crate::network_wrapper::RoutingCost;
+use crate::flow::{AVBFlow, FlowEnum, FlowID, TSNFlow};
+use crate::graph_util::StreamAwareGraph;
+use crate::network_wrapper::{
+    AVBLatencyReport, Deployment, NetworkWrapper, RoutingCost, Violation,
+};
+use crate::recorder::GCL;
+use crate::util::YensAlgo;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub trait RoutingAlgo {
     fn add_flows(&mut self, tsns: Vec<TSNFlow>, avbs: Vec<AVBFlow>);
@@ -9,6 +16,51 @@ pub trait RoutingAlgo {
     fn show_results(&self);
     fn get_last_compute_time(&self) -> u128;
     fn get_cost(&self) -> RoutingCost;
+    /// 所有 AVB 資料流的延遲組成，用來解釋為何某些資料流趕不上死線
+    fn get_avb_latency_report(&self) -> Vec<AVBLatencyReport>;
+    /// 獨立檢查 TT 排程是否合法，部署前應先確認沒有違反任何規則
+    fn validate_schedule(&self) -> Vec<Violation>;
+    /// 目前部署的 Gate Control List，供匯出給交換機等外部工具使用
+    fn get_gcl(&self) -> &GCL;
+    fn get_graph(&self) -> &StreamAwareGraph;
+    /// 所有 TT 資料流及其路徑
+    fn get_routed_tsns(&self) -> Vec<(&TSNFlow, &Vec<usize>)>;
+    /// 所有 AVB 資料流及其路徑
+    fn get_routed_avbs(&self) -> Vec<(&AVBFlow, &Vec<usize>)>;
+    /// 載入已部署的排程作為起點，必需在加入任何資料流之前呼叫；之後加入資料流時不會任意改動這些資料流
+    fn load_deployment(&mut self, deployment: Deployment) -> Result<(), String>;
+    /// 目前的部署結果，存檔後可供下次啟動時 `load_deployment`
+    fn get_deployment(&self) -> Deployment;
+}
+
+/// 以 k 條最短路徑為候選的演算法共用：部署的路徑必需是候選路徑之一，記下它是第幾條
+fn load_deployment_with_yens(
+    wrapper: &mut NetworkWrapper<usize>,
+    yens_algo: &Rc<RefCell<YensAlgo<usize, StreamAwareGraph>>>,
+    deployment: Deployment,
+) -> Result<(), String> {
+    let pairs = deployment
+        .tsns
+        .iter()
+        .map(|deployed| (deployed.flow.src, deployed.flow.dst))
+        .chain(
+            deployment
+                .avbs
+                .iter()
+                .map(|deployed| (deployed.flow.src, deployed.flow.dst)),
+        );
+    for (src, dst) in pairs {
+        yens_algo.borrow_mut().compute_routes(src, dst);
+    }
+    let yens_algo = yens_algo.borrow();
+    wrapper.load_deployment(deployment, |flow_enum, route| {
+        let (src, dst) = match flow_enum {
+            FlowEnum::TSN(flow) => (flow.src, flow.dst),
+            FlowEnum::AVB(flow) => (flow.src, flow.dst),
+        };
+        (0..yens_algo.get_route_count(src, dst))
+            .find(|&k| yens_algo.get_kth_route(src, dst, k) == route)
+    })
 }
 
 mod shortest_path;
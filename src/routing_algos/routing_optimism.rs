@@ -1,9 +1,11 @@
-use super::RoutingAlgo;
+use super::{load_deployment_with_yens, RoutingAlgo};
 use crate::config::Config;
 use crate::flow::{AVBFlow, Flow, FlowEnum, FlowID, TSNFlow};
 use crate::graph_util::StreamAwareGraph;
-use crate::network_wrapper::{NetworkWrapper, RoutingCost};
-use crate::recorder::flow_table::prelude::*;
+use crate::network_wrapper::{
+    AVBLatencyReport, Deployment, NetworkWrapper, RoutingCost, Violation,
+};
+use crate::recorder::{flow_table::prelude::*, GCL};
 use crate::util::YensAlgo;
 use crate::MAX_K;
 use rand::Rng;
@@ -39,8 +41,7 @@ impl RO {
     pub fn new(g: StreamAwareGraph) -> Self {
         let yens_algo = Rc::new(RefCell::new(YensAlgo::new(g.clone(), MAX_K)));
         let tmp_yens = yens_algo.clone();
-        // TODO: 計算 hyper_p
-        let wrapper = NetworkWrapper::new(1000, g, move |flow_enum, &k| {
+        let wrapper = NetworkWrapper::new(g, move |flow_enum, &k| {
             let (src, dst) = get_src_dst(flow_enum);
             tmp_yens.borrow().get_kth_route(src, dst, k) as *const Vec<usize>
         });
@@ -78,7 +79,7 @@ impl RO {
             #[cfg(debug_assertions)]
             println!("start iteration #{}", iter_times);
             self.hill_climbing(&time, &mut min_cost, cur_wrapper);
-            if min_cost.avb_fail_cnt == 0 && Config::get().fast_stop {
+            if min_cost.is_avb_feasible() && Config::get().fast_stop {
                 // 找到可行解，且為快速終止模式
                 break;
             }
@@ -113,7 +114,7 @@ impl RO {
     ) {
         let mut iter_times = 0;
         while time.elapsed().as_micros() < Config::get().t_limit {
-            if min_cost.avb_fail_cnt == 0 && Config::get().fast_stop {
+            if min_cost.is_avb_feasible() && Config::get().fast_stop {
                 return; // 找到可行解，返回
             }
 
@@ -196,20 +197,46 @@ impl RoutingAlgo for RO {
     fn show_results(&self) {
         println!("TT Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_tsn() {
-            let route = self.get_route(flow.id);
-            println!("flow id = {:?}, route = {:?}", flow.id, route);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
+            let jitter = self.wrapper.compute_tsn_jitter(flow);
+            let release = self.wrapper.get_tsn_release(flow.id);
+            println!(
+                "flow id = {:?}, route = {:?}, offset = {:?}, jitter = {}",
+                flow.id, route, release, jitter
+            );
         }
         println!("AVB Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_avb() {
-            let route = self.get_route(flow.id);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
             let cost = self.wrapper.compute_single_avb_cost(flow);
             println!(
                 "flow id = {:?}, route = {:?} avb wcd / max latency = {:?}, reroute = {}",
                 flow.id, route, cost.avb_wcd, cost.reroute_overhead
             );
+            for hop in self
+                .wrapper
+                .compute_avb_latency_breakdown(flow, None)
+                .iter()
+            {
+                println!("    {}", hop);
+            }
         }
         let all_cost = self.wrapper.compute_all_cost();
         println!("the cost structure = {:?}", all_cost,);
+        if let Some(error) = all_cost.tsn_fail_cause {
+            if let Some(link_id) = error.link_id {
+                println!(
+                    "TSN schedule fail at {}",
+                    self.wrapper.get_graph().get_link_name(link_id)
+                );
+            }
+        }
         println!("{}", all_cost.compute());
     }
     fn get_last_compute_time(&self) -> u128 {
@@ -218,4 +245,28 @@ impl RoutingAlgo for RO {
     fn get_cost(&self) -> RoutingCost {
         self.wrapper.compute_all_cost()
     }
+    fn get_avb_latency_report(&self) -> Vec<AVBLatencyReport> {
+        self.wrapper.report_avb_latency()
+    }
+    fn validate_schedule(&self) -> Vec<Violation> {
+        self.wrapper.validate_schedule()
+    }
+    fn get_gcl(&self) -> &GCL {
+        self.wrapper.get_gcl()
+    }
+    fn get_graph(&self) -> &StreamAwareGraph {
+        self.wrapper.get_graph()
+    }
+    fn get_routed_tsns(&self) -> Vec<(&TSNFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_tsns()
+    }
+    fn get_routed_avbs(&self) -> Vec<(&AVBFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_avbs()
+    }
+    fn load_deployment(&mut self, deployment: Deployment) -> Result<(), String> {
+        load_deployment_with_yens(&mut self.wrapper, &self.yens_algo, deployment)
+    }
+    fn get_deployment(&self) -> Deployment {
+        self.wrapper.get_deployment()
+    }
 }
@@ -1,8 +1,10 @@
 use super::RoutingAlgo;
 use crate::flow::{AVBFlow, Flow, FlowID, TSNFlow};
 use crate::graph_util::StreamAwareGraph;
-use crate::network_wrapper::{NetworkWrapper, RoutingCost};
-use crate::recorder::flow_table::prelude::*;
+use crate::network_wrapper::{
+    AVBLatencyReport, Deployment, NetworkWrapper, RoutingCost, Violation,
+};
+use crate::recorder::{flow_table::prelude::*, GCL};
 use crate::util::Dijkstra;
 use std::time::Instant;
 
@@ -14,8 +16,7 @@ pub struct SPF {
 
 impl SPF {
     pub fn new(g: StreamAwareGraph) -> Self {
-        // TODO: 計算 hyper_p
-        let wrapper = NetworkWrapper::new(1000, g.clone(), |_, route| route as *const Vec<usize>);
+        let wrapper = NetworkWrapper::new(g.clone(), |_, route| route as *const Vec<usize>);
         SPF {
             wrapper,
             compute_time: 0,
@@ -52,25 +53,76 @@ impl RoutingAlgo for SPF {
     fn show_results(&self) {
         println!("TT Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_tsn() {
-            let route = self.get_route(flow.id);
-            println!("flow id = {:?}, route = {:?}", flow.id, route);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
+            let jitter = self.wrapper.compute_tsn_jitter(flow);
+            let release = self.wrapper.get_tsn_release(flow.id);
+            println!(
+                "flow id = {:?}, route = {:?}, offset = {:?}, jitter = {}",
+                flow.id, route, release, jitter
+            );
         }
         println!("AVB Flows:");
         for (flow, _) in self.wrapper.get_flow_table().iter_avb() {
-            let route = self.get_route(flow.id);
+            let route = self
+                .wrapper
+                .get_graph()
+                .get_route_link_names(self.get_route(flow.id));
             let cost = self.wrapper.compute_single_avb_cost(flow);
             println!(
                 "flow id = {:?}, route = {:?} avb wcd / max latency = {:?}, reroute = {}",
                 flow.id, route, cost.avb_wcd, cost.reroute_overhead
             );
+            for hop in self
+                .wrapper
+                .compute_avb_latency_breakdown(flow, None)
+                .iter()
+            {
+                println!("    {}", hop);
+            }
         }
         let all_cost = self.wrapper.compute_all_cost();
         println!("the cost structure = {:?}", all_cost,);
+        if let Some(error) = all_cost.tsn_fail_cause {
+            if let Some(link_id) = error.link_id {
+                println!(
+                    "TSN schedule fail at {}",
+                    self.wrapper.get_graph().get_link_name(link_id)
+                );
+            }
+        }
         println!("{}", all_cost.compute());
     }
     fn get_cost(&self) -> RoutingCost {
         self.wrapper.compute_all_cost()
     }
+    fn get_avb_latency_report(&self) -> Vec<AVBLatencyReport> {
+        self.wrapper.report_avb_latency()
+    }
+    fn validate_schedule(&self) -> Vec<Violation> {
+        self.wrapper.validate_schedule()
+    }
+    fn get_gcl(&self) -> &GCL {
+        self.wrapper.get_gcl()
+    }
+    fn get_graph(&self) -> &StreamAwareGraph {
+        self.wrapper.get_graph()
+    }
+    fn get_routed_tsns(&self) -> Vec<(&TSNFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_tsns()
+    }
+    fn get_routed_avbs(&self) -> Vec<(&AVBFlow, &Vec<usize>)> {
+        self.wrapper.get_routed_avbs()
+    }
+    fn load_deployment(&mut self, deployment: Deployment) -> Result<(), String> {
+        self.wrapper
+            .load_deployment(deployment, |_, route| Some(route.clone()))
+    }
+    fn get_deployment(&self) -> Deployment {
+        self.wrapper.get_deployment()
+    }
 }
 
 impl SPF {
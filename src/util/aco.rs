@@ -91,6 +91,8 @@ fn select_cluster(visibility: &[f64; MAX_K], pheromone: &[f64; MAX_K], k: usize,
 pub enum ACOJudgeResult {
     Stop(f64),
     KeepOn(f64),
+    /// 繼續搜索，但這個解中列出的狀態（如排程失敗的資料流）所做的選擇要受罰
+    Penalize(f64, Vec<usize>),
 }
 
 pub struct ACO {
@@ -157,7 +159,8 @@ impl ACO {
             epoch += 1;
             let (should_stop, local_best_state) =
                 self.do_single_epoch(&visibility, &mut judge_func);
-            if local_best_state.get_dist() < best_state.get_dist() {
+            // NOTE 每個解都有 TT 排程失敗時，距離可能都是無限大，仍要記下其中一個
+            if best_state.state.is_none() || local_best_state.get_dist() < best_state.get_dist() {
                 best_state = local_best_state;
             }
             if should_stop {
@@ -181,6 +184,8 @@ impl ACO {
         let mut max_heap: BinaryHeap<WeightedState> = BinaryHeap::new();
         let state_len = self.get_state_len();
         let mut should_stop = false;
+        // 要受罰的 (狀態, 選擇)
+        let mut penalties = Vec::<(usize, usize)>::new();
         for _ in 0..self.r {
             let mut cur_state = Vec::<usize>::with_capacity(state_len);
             for i in 0..state_len {
@@ -192,6 +197,12 @@ impl ACO {
                 ACOJudgeResult::KeepOn(dist) => {
                     max_heap.push(WeightedState::new(dist, Some(cur_state)));
                 }
+                ACOJudgeResult::Penalize(dist, culprits) => {
+                    for i in culprits.into_iter() {
+                        penalties.push((i, cur_state[i]));
+                    }
+                    max_heap.push(WeightedState::new(dist, Some(cur_state)));
+                }
                 ACOJudgeResult::Stop(dist) => {
                     max_heap.push(WeightedState::new(dist, Some(cur_state)));
                     should_stop = true;
@@ -200,7 +211,19 @@ impl ACO {
             }
         }
         self.evaporate();
-        (should_stop, self.offline_update(max_heap))
+        let best_state = self.offline_update(max_heap);
+        self.penalize(&penalties);
+        (should_stop, best_state)
+    }
+    /// 受罰的選擇額外蒸發一次，讓螞蟻少走導致失敗的選擇，而不是讓整個解一起受罰
+    fn penalize(&mut self, penalties: &[(usize, usize)]) {
+        for &(i, j) in penalties.iter() {
+            let mut ph = (1.0 - self.rho) * self.pheromone[i][j];
+            if ph <= self.min_ph {
+                ph = self.min_ph;
+            }
+            self.pheromone[i][j] = ph;
+        }
     }
     fn evaporate(&mut self) {
         let state_len = self.get_state_len();
@@ -281,4 +304,16 @@ mod test {
         });
         assert_eq!(vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1], new_state);
     }
+    #[test]
+    fn test_penalize() {
+        let mut aco = ACO::new(2, 2, None);
+        aco.set_args_usize(ACOArgsUSize::R, 4);
+        aco.set_args_usize(ACOArgsUSize::L, 4);
+        aco.do_single_epoch(&vec![[1.0; MAX_K]; 2], &mut |_| {
+            ACOJudgeResult::Penalize(1.0, vec![0])
+        });
+        // 兩個狀態得到的費洛蒙一樣多，但狀態 0 的選擇每次都受罰
+        let ph = aco.get_pharamon();
+        assert!(ph[0][0] + ph[0][1] < ph[1][0] + ph[1][1]);
+    }
 }